@@ -0,0 +1,544 @@
+//! Declarative description of a node's resources.
+//!
+//! Resources refer to each other by a symbolic `name` rather than by id, for
+//! example a sender names the device and flow it belongs to. Ids are random
+//! unless given explicitly, or derived from `seed` and the resource's name.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error as StdError,
+    fmt, fs, io,
+    path::Path,
+    result::Result as StdResult,
+    str::FromStr,
+};
+
+use serde::{de::IgnoredAny, Deserialize};
+use uuid::Uuid;
+
+use crate::{
+    error::Error,
+    resource::{
+        Device, DeviceType, Flow, Format, Node, Receiver, ReceiverCaps, ResourceBuilder,
+        ResourceBundle, Sender, Source, Transport,
+    },
+};
+
+pub type Result<T> = StdResult<T, ConfigError>;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    #[cfg(feature = "serde_yaml")]
+    Yaml(serde_yaml::Error),
+    UnsupportedFile(String),
+    Resource(Error),
+    DuplicateName {
+        kind: &'static str,
+        name: String,
+    },
+    UnresolvedReference {
+        kind: &'static str,
+        name: String,
+    },
+    UnknownField {
+        /// Kind and name of the resource, e.g. `device/main`
+        resource: String,
+        field: String,
+    },
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Toml(e)
+    }
+}
+
+#[cfg(feature = "serde_yaml")]
+impl From<serde_yaml::Error> for ConfigError {
+    fn from(e: serde_yaml::Error) -> Self {
+        ConfigError::Yaml(e)
+    }
+}
+
+impl From<Error> for ConfigError {
+    fn from(e: Error) -> Self {
+        ConfigError::Resource(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => fmt::Display::fmt(&e, f),
+            ConfigError::Json(e) => fmt::Display::fmt(&e, f),
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(e) => fmt::Display::fmt(&e, f),
+            #[cfg(feature = "serde_yaml")]
+            ConfigError::Yaml(e) => fmt::Display::fmt(&e, f),
+            ConfigError::UnsupportedFile(path) => write!(f, "Unsupported file type: {}", path),
+            ConfigError::Resource(e) => fmt::Display::fmt(&e, f),
+            ConfigError::DuplicateName { kind, name } => {
+                write!(f, "Duplicate {} name \"{}\"", kind, name)
+            }
+            ConfigError::UnresolvedReference { kind, name } => {
+                write!(f, "Reference to unknown {} \"{}\"", kind, name)
+            }
+            ConfigError::UnknownField { resource, field } => {
+                write!(f, "Unknown field \"{}\" in {}", field, resource)
+            }
+        }
+    }
+}
+
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Json(e) => Some(e),
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(e) => Some(e),
+            #[cfg(feature = "serde_yaml")]
+            ConfigError::Yaml(e) => Some(e),
+            ConfigError::Resource(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Fields shared by every resource description, flattened into each one.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CoreConfig {
+    pub label: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// Keys matching no field of the resource. serde's `deny_unknown_fields`
+    /// does not work with `flatten`, so these are rejected on build instead.
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, IgnoredAny>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeConfig {
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    pub href: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    pub device: String,
+    pub format: String,
+    #[serde(default)]
+    pub parents: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FlowConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    pub source: String,
+    #[serde(default)]
+    pub parents: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SenderConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    pub device: String,
+    pub flow: String,
+    pub transport: String,
+    #[serde(default)]
+    pub manifest: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReceiverConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub core: CoreConfig,
+    pub device: String,
+    pub format: String,
+    pub transport: String,
+    #[serde(default)]
+    pub caps: ReceiverCaps,
+}
+
+/// Top level of a resource description file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleConfig {
+    /// Seed for deriving stable ids, see `ResourceCoreBuilder::seeded_id`
+    #[serde(default)]
+    pub seed: Option<Uuid>,
+    pub node: NodeConfig,
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+    #[serde(default)]
+    pub flows: Vec<FlowConfig>,
+    #[serde(default)]
+    pub senders: Vec<SenderConfig>,
+    #[serde(default)]
+    pub receivers: Vec<ReceiverConfig>,
+}
+
+/// Map from symbolic name to resource id, for one kind of resource.
+struct Names {
+    kind: &'static str,
+    ids: HashMap<String, Uuid>,
+}
+
+impl Names {
+    fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            ids: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, name: &str, id: Uuid) -> Result<()> {
+        if self.ids.insert(name.to_owned(), id).is_some() {
+            return Err(ConfigError::DuplicateName {
+                kind: self.kind,
+                name: name.to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str) -> Result<Uuid> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| ConfigError::UnresolvedReference {
+                kind: self.kind,
+                name: name.to_owned(),
+            })
+    }
+}
+
+impl BundleConfig {
+    /// Id for a resource, in order of preference: explicit, seeded, random.
+    fn id(&self, core: &CoreConfig, path: &str) -> Uuid {
+        match (core.id, &self.seed) {
+            (Some(id), _) => id,
+            (None, Some(seed)) => Uuid::new_v5(seed, path.as_bytes()),
+            (None, None) => Uuid::new_v4(),
+        }
+    }
+
+    /// Apply the id, description and tags shared by every resource, after
+    /// checking the description has no unknown fields.
+    fn decorate<B: ResourceBuilder>(&self, builder: B, core: &CoreConfig, path: &str) -> Result<B> {
+        if let Some(field) = core.unknown.keys().next() {
+            return Err(ConfigError::UnknownField {
+                resource: path.to_owned(),
+                field: field.clone(),
+            });
+        }
+
+        let mut builder = builder.id(self.id(core, path));
+        if let Some(description) = &core.description {
+            builder = builder.description(description.as_str());
+        }
+        Ok(core.tags.iter().fold(builder, |builder, (key, values)| {
+            builder.tag(key.as_str(), values.iter().map(String::as_str))
+        }))
+    }
+
+    /// Resolve references and build the described resources.
+    pub fn build(&self) -> Result<ResourceBundle> {
+        let mut bundle = ResourceBundle::new();
+
+        let builder = Node::builder(self.node.core.label.as_str(), self.node.href.as_str());
+        let node = self.decorate(builder, &self.node.core, "node")?.build();
+
+        // Devices
+        let mut device_names = Names::new("device");
+        let mut devices = Vec::new();
+        for config in &self.devices {
+            let builder = Device::builder(
+                config.core.label.as_str(),
+                &node,
+                DeviceType::from_str(&config.type_)?,
+            );
+            let device = self
+                .decorate(builder, &config.core, &format!("device/{}", config.name))?
+                .build();
+
+            device_names.insert(&config.name, device.core.id)?;
+            devices.push(device);
+        }
+        let device = |name: &str| -> Result<&Device> {
+            let id = device_names.resolve(name)?;
+            Ok(devices
+                .iter()
+                .find(|d| d.core.id == id)
+                .expect("Device was named"))
+        };
+
+        // Sources
+        let mut source_names = Names::new("source");
+        let mut sources = Vec::new();
+        for config in &self.sources {
+            let builder = Source::builder(
+                config.core.label.as_str(),
+                device(&config.device)?,
+                Format::from_str(&config.format)?,
+            );
+            let source = self
+                .decorate(builder, &config.core, &format!("source/{}", config.name))?
+                .build();
+
+            source_names.insert(&config.name, source.core.id)?;
+            sources.push(source);
+        }
+        for (config, source) in self.sources.iter().zip(sources.iter_mut()) {
+            source.parents = resolve_all(&source_names, &config.parents)?;
+        }
+        let source = |name: &str| -> Result<&Source> {
+            let id = source_names.resolve(name)?;
+            Ok(sources
+                .iter()
+                .find(|s| s.core.id == id)
+                .expect("Source was named"))
+        };
+
+        // Flows
+        let mut flow_names = Names::new("flow");
+        let mut flows = Vec::new();
+        for config in &self.flows {
+            let builder = Flow::builder(config.core.label.as_str(), source(&config.source)?);
+            let flow = self
+                .decorate(builder, &config.core, &format!("flow/{}", config.name))?
+                .build();
+
+            flow_names.insert(&config.name, flow.core.id)?;
+            flows.push(flow);
+        }
+        for (config, flow) in self.flows.iter().zip(flows.iter_mut()) {
+            flow.parents = resolve_all(&flow_names, &config.parents)?;
+        }
+        let flow = |name: &str| -> Result<&Flow> {
+            let id = flow_names.resolve(name)?;
+            Ok(flows
+                .iter()
+                .find(|f| f.core.id == id)
+                .expect("Flow was named"))
+        };
+
+        // Senders
+        let mut sender_names = Names::new("sender");
+        let mut senders = Vec::new();
+        for config in &self.senders {
+            let mut builder = Sender::builder(
+                config.core.label.as_str(),
+                device(&config.device)?,
+                flow(&config.flow)?,
+                Transport::from_str(&config.transport)?,
+            );
+            if let Some(manifest) = &config.manifest {
+                builder = builder.manifest(manifest.as_str());
+            }
+            let sender = self
+                .decorate(builder, &config.core, &format!("sender/{}", config.name))?
+                .build();
+
+            sender_names.insert(&config.name, sender.core.id)?;
+            senders.push((device_names.resolve(&config.device)?, sender));
+        }
+
+        // Receivers
+        let mut receiver_names = Names::new("receiver");
+        let mut receivers = Vec::new();
+        for config in &self.receivers {
+            let builder = Receiver::builder(
+                config.core.label.as_str(),
+                device(&config.device)?,
+                Format::from_str(&config.format)?,
+                Transport::from_str(&config.transport)?,
+            )
+            .caps(config.caps.clone());
+            let receiver = self
+                .decorate(builder, &config.core, &format!("receiver/{}", config.name))?
+                .build();
+
+            receiver_names.insert(&config.name, receiver.core.id)?;
+            receivers.push((device_names.resolve(&config.device)?, receiver));
+        }
+
+        // Devices list the senders and receivers attached to them
+        for device in &mut devices {
+            let id = device.core.id;
+            device.senders = senders
+                .iter()
+                .filter(|(device_id, _)| *device_id == id)
+                .map(|(_, sender)| sender.core.id)
+                .collect();
+            device.receivers = receivers
+                .iter()
+                .filter(|(device_id, _)| *device_id == id)
+                .map(|(_, receiver)| receiver.core.id)
+                .collect();
+        }
+
+        bundle.insert_node(node);
+        devices.into_iter().for_each(|d| bundle.insert_device(d));
+        sources.into_iter().for_each(|s| bundle.insert_source(s));
+        flows.into_iter().for_each(|f| bundle.insert_flow(f));
+        senders
+            .into_iter()
+            .for_each(|(_, s)| bundle.insert_sender(s));
+        receivers
+            .into_iter()
+            .for_each(|(_, r)| bundle.insert_receiver(r));
+
+        Ok(bundle)
+    }
+}
+
+fn resolve_all(names: &Names, references: &[String]) -> Result<Vec<Uuid>> {
+    references.iter().map(|name| names.resolve(name)).collect()
+}
+
+impl ResourceBundle {
+    pub fn from_config(config: &BundleConfig) -> Result<Self> {
+        config.build()
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        Self::from_config(&serde_json::from_str(s)?)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        Self::from_config(&toml::from_str(s)?)
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    pub fn from_yaml_str(s: &str) -> Result<Self> {
+        Self::from_config(&serde_yaml::from_str(s)?)
+    }
+
+    /// Load a description file, choosing the format from its extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&contents),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml_str(&contents),
+            #[cfg(feature = "serde_yaml")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&contents),
+            _ => Err(ConfigError::UnsupportedFile(path.display().to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(result: Result<ResourceBundle>) {
+        match result {
+            Err(ConfigError::UnknownField { resource, field }) => {
+                assert_eq!(resource, "device/main");
+                assert_eq!(field, "colour");
+            }
+            other => panic!("Expected unknown field, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn json_fields() {
+        let valid = r#"{
+            "node": {"label": "Node", "href": "http://127.0.0.1:3000/"},
+            "devices": [{"name": "main", "label": "Device", "description": "Main",
+                         "type": "urn:x-nmos:device:generic"}]
+        }"#;
+        let bundle = ResourceBundle::from_json_str(valid).unwrap();
+        assert_eq!(bundle.devices[0].core.description, "Main");
+
+        let invalid = valid.replace(r#""description""#, r#""colour": "red", "description""#);
+        check(ResourceBundle::from_json_str(&invalid));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_fields() {
+        let valid = r#"
+            [node]
+            label = "Node"
+            href = "http://127.0.0.1:3000/"
+
+            [[devices]]
+            name = "main"
+            label = "Device"
+            description = "Main"
+            type = "urn:x-nmos:device:generic"
+        "#;
+        let bundle = ResourceBundle::from_toml_str(valid).unwrap();
+        assert_eq!(bundle.devices[0].core.description, "Main");
+
+        let invalid = valid.replace("description", "colour = \"red\"\n            description");
+        check(ResourceBundle::from_toml_str(&invalid));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn yaml_fields() {
+        let valid = "
+node:
+  label: Node
+  href: http://127.0.0.1:3000/
+devices:
+  - name: main
+    label: Device
+    description: Main
+    type: urn:x-nmos:device:generic
+";
+        let bundle = ResourceBundle::from_yaml_str(valid).unwrap();
+        assert_eq!(bundle.devices[0].core.description, "Main");
+
+        let invalid = valid.replace("description", "colour: red\n    description");
+        check(ResourceBundle::from_yaml_str(&invalid));
+    }
+}
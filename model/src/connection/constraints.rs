@@ -0,0 +1,355 @@
+//! Constraints on the transport parameters a sender or receiver accepts, as
+//! listed by IS-05 `/constraints`.
+//!
+//! Constraints are given per leg, so an ST 2022-7 sender has two sets. The
+//! typed builders start from every parameter of the transport being
+//! unconstrained.
+
+use std::{collections::BTreeMap, fmt, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::{error::Result, pattern::Pattern, resource::Transport};
+
+use super::{receiver_params, sender_params};
+
+/// Constraints on one leg, keyed by parameter name.
+pub type LegConstraints = BTreeMap<String, Constraint>;
+
+/// Constraint on a single transport parameter. Every part which is set must
+/// hold, except that the range only applies to numbers and the pattern to
+/// strings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Constraint {
+    #[serde(rename = "enum", default, skip_serializing_if = "Vec::is_empty")]
+    pub enum_values: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Constraint {
+    /// Allow any value.
+    #[must_use]
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn enumeration<I, V>(values: I) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        Self {
+            enum_values: values.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn range<N: Into<Number>>(minimum: N, maximum: N) -> Self {
+        Self {
+            minimum: Some(minimum.into()),
+            maximum: Some(maximum.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Strings matching an ECMAScript style regular expression, which is
+    /// rejected if it does not compile.
+    pub fn pattern(pattern: &str) -> Result<Self> {
+        Ok(Self {
+            pattern: Some(Pattern::new(pattern)?),
+            ..Self::default()
+        })
+    }
+
+    #[must_use]
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Check a value, describing the first part of the constraint it breaks.
+    pub fn check(&self, value: &Value) -> std::result::Result<(), Violation> {
+        if !self.enum_values.is_empty() && !self.enum_values.contains(value) {
+            return Err(Violation::Enum(self.enum_values.clone()));
+        }
+
+        if let Value::Number(number) = value {
+            let value = number.as_f64();
+            if let Some(minimum) = &self.minimum {
+                if value < minimum.as_f64() {
+                    return Err(Violation::Minimum(minimum.clone()));
+                }
+            }
+            if let Some(maximum) = &self.maximum {
+                if value > maximum.as_f64() {
+                    return Err(Violation::Maximum(maximum.clone()));
+                }
+            }
+        }
+
+        if let (Value::String(s), Some(pattern)) = (value, &self.pattern) {
+            if !pattern.is_match(s) {
+                return Err(Violation::Pattern(pattern.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The part of a constraint a value breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Enum(Vec<Value>),
+    Minimum(Number),
+    Maximum(Number),
+    Pattern(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Enum(values) => {
+                write!(f, "must be one of {}", Value::Array(values.clone()))
+            }
+            Violation::Minimum(minimum) => write!(f, "must be at least {}", minimum),
+            Violation::Maximum(maximum) => write!(f, "must be at most {}", maximum),
+            Violation::Pattern(pattern) => write!(f, "must match {}", pattern),
+        }
+    }
+}
+
+/// Parameters which may be set to `"auto"` regardless of constraints, for
+/// the sender or receiver to choose a value on activation.
+pub(super) fn supports_auto(param: &str) -> bool {
+    matches!(
+        param,
+        "source_ip"
+            | "destination_ip"
+            | "source_port"
+            | "destination_port"
+            | "interface_ip"
+            | "connection_uri"
+            | "connection_authorization"
+            | "source_host"
+            | "destination_host"
+            | "broker_protocol"
+            | "broker_authorization"
+    )
+}
+
+/// Constraints allowing any value for each parameter a transport defines.
+pub(super) fn unconstrained(params: &super::TransportParams) -> LegConstraints {
+    params
+        .keys()
+        .map(|key| (key.clone(), Constraint::any()))
+        .collect()
+}
+
+fn addresses<I: IntoIterator<Item = IpAddr>>(addresses: I) -> Constraint {
+    Constraint::enumeration(addresses.into_iter().map(|a| a.to_string()))
+}
+
+/// Define a typed builder for the constraints on one leg of a transport,
+/// starting from the transport's parameters being unconstrained.
+macro_rules! leg_constraints {
+    ($(#[$meta:meta])* $name:ident, $params:ident($transport:expr)) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        #[must_use]
+        pub struct $name(LegConstraints);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self(unconstrained(&$params(&$transport)))
+            }
+
+            /// Constrain any parameter, including ones not defined by the
+            /// transport, e.g. for FEC or RTCP.
+            pub fn constraint<S: Into<String>>(mut self, param: S, constraint: Constraint) -> Self {
+                self.0.insert(param.into(), constraint);
+                self
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl From<$name> for LegConstraints {
+            fn from(constraints: $name) -> Self {
+                constraints.0
+            }
+        }
+    };
+}
+
+leg_constraints!(
+    /// Constraints on one leg of an RTP sender.
+    RtpSenderConstraints,
+    sender_params(Transport::Rtp)
+);
+
+impl RtpSenderConstraints {
+    pub fn source_ip<I: IntoIterator<Item = IpAddr>>(self, ips: I) -> Self {
+        self.constraint("source_ip", addresses(ips))
+    }
+
+    pub fn destination_ip<I: IntoIterator<Item = IpAddr>>(self, ips: I) -> Self {
+        self.constraint("destination_ip", addresses(ips))
+    }
+
+    pub fn source_port(self, minimum: u16, maximum: u16) -> Self {
+        self.constraint("source_port", Constraint::range(minimum, maximum))
+    }
+
+    pub fn destination_port(self, minimum: u16, maximum: u16) -> Self {
+        self.constraint("destination_port", Constraint::range(minimum, maximum))
+    }
+
+    /// Only allow the leg to be enabled or disabled.
+    pub fn rtp_enabled(self, enabled: bool) -> Self {
+        self.constraint("rtp_enabled", Constraint::enumeration([enabled]))
+    }
+}
+
+leg_constraints!(
+    /// Constraints on one leg of an RTP receiver.
+    RtpReceiverConstraints,
+    receiver_params(Transport::Rtp)
+);
+
+impl RtpReceiverConstraints {
+    pub fn interface_ip<I: IntoIterator<Item = IpAddr>>(self, ips: I) -> Self {
+        self.constraint("interface_ip", addresses(ips))
+    }
+
+    /// Accept multicast groups matching an ECMAScript style regular
+    /// expression.
+    pub fn multicast_ip(self, pattern: &str) -> Result<Self> {
+        Ok(self.constraint("multicast_ip", Constraint::pattern(pattern)?))
+    }
+
+    pub fn destination_port(self, minimum: u16, maximum: u16) -> Self {
+        self.constraint("destination_port", Constraint::range(minimum, maximum))
+    }
+
+    /// Only allow the leg to be enabled or disabled.
+    pub fn rtp_enabled(self, enabled: bool) -> Self {
+        self.constraint("rtp_enabled", Constraint::enumeration([enabled]))
+    }
+}
+
+leg_constraints!(
+    /// Constraints on a WebSocket sender.
+    WebsocketSenderConstraints,
+    sender_params(Transport::Websocket)
+);
+
+impl WebsocketSenderConstraints {
+    pub fn connection_uri(self, pattern: &str) -> Result<Self> {
+        Ok(self.constraint("connection_uri", Constraint::pattern(pattern)?))
+    }
+
+    pub fn connection_authorization(self, authorization: bool) -> Self {
+        self.constraint(
+            "connection_authorization",
+            Constraint::enumeration([authorization]),
+        )
+    }
+}
+
+leg_constraints!(
+    /// Constraints on a WebSocket receiver.
+    WebsocketReceiverConstraints,
+    receiver_params(Transport::Websocket)
+);
+
+impl WebsocketReceiverConstraints {
+    pub fn connection_uri(self, pattern: &str) -> Result<Self> {
+        Ok(self.constraint("connection_uri", Constraint::pattern(pattern)?))
+    }
+
+    pub fn connection_authorization(self, authorization: bool) -> Self {
+        self.constraint(
+            "connection_authorization",
+            Constraint::enumeration([authorization]),
+        )
+    }
+}
+
+leg_constraints!(
+    /// Constraints on an MQTT sender.
+    MqttSenderConstraints,
+    sender_params(Transport::Mqtt)
+);
+
+impl MqttSenderConstraints {
+    /// Brokers the sender may publish to, by host name or address.
+    pub fn destination_host<I, S>(self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.constraint(
+            "destination_host",
+            Constraint::enumeration(hosts.into_iter().map(Into::into)),
+        )
+    }
+
+    pub fn destination_port(self, minimum: u16, maximum: u16) -> Self {
+        self.constraint("destination_port", Constraint::range(minimum, maximum))
+    }
+
+    /// Protocols to reach the broker with, `mqtt` or `secure-mqtt`.
+    pub fn broker_protocol<I: IntoIterator<Item = &'static str>>(self, protocols: I) -> Self {
+        self.constraint("broker_protocol", Constraint::enumeration(protocols))
+    }
+
+    pub fn broker_topic(self, pattern: &str) -> Result<Self> {
+        Ok(self.constraint("broker_topic", Constraint::pattern(pattern)?))
+    }
+}
+
+leg_constraints!(
+    /// Constraints on an MQTT receiver.
+    MqttReceiverConstraints,
+    receiver_params(Transport::Mqtt)
+);
+
+impl MqttReceiverConstraints {
+    /// Brokers the receiver may subscribe with, by host name or address.
+    pub fn source_host<I, S>(self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.constraint(
+            "source_host",
+            Constraint::enumeration(hosts.into_iter().map(Into::into)),
+        )
+    }
+
+    pub fn source_port(self, minimum: u16, maximum: u16) -> Self {
+        self.constraint("source_port", Constraint::range(minimum, maximum))
+    }
+
+    /// Protocols to reach the broker with, `mqtt` or `secure-mqtt`.
+    pub fn broker_protocol<I: IntoIterator<Item = &'static str>>(self, protocols: I) -> Self {
+        self.constraint("broker_protocol", Constraint::enumeration(protocols))
+    }
+
+    pub fn broker_topic(self, pattern: &str) -> Result<Self> {
+        Ok(self.constraint("broker_topic", Constraint::pattern(pattern)?))
+    }
+}
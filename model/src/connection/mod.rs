@@ -0,0 +1,953 @@
+//! IS-05 connection management state for senders and receivers.
+//!
+//! Each sender and receiver with an IS-05 capable transport has a staged
+//! and an active endpoint. Controllers modify the staged endpoint, which is
+//! copied to the active endpoint on activation.
+
+use std::{error::Error as StdError, fmt};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+pub use constraints::{
+    Constraint, LegConstraints, MqttReceiverConstraints, MqttSenderConstraints,
+    RtpReceiverConstraints, RtpSenderConstraints, Violation, WebsocketReceiverConstraints,
+    WebsocketSenderConstraints,
+};
+pub use rtp::{Auto, RtpPool, RtpReceiverParams, RtpResolver, RtpSenderParams, DEFAULT_RTP_PORT};
+use uuid::Uuid;
+
+use crate::{
+    resource::{Receiver, Sender, Transport},
+    sdp::{SdpError, SessionDescription},
+    tai::TaiTime,
+    version::{is_05, APIVersion},
+    Model,
+};
+
+mod constraints;
+mod rtp;
+
+/// Media type of SDP transport files.
+pub const SDP_MEDIA_TYPE: &str = "application/sdp";
+
+/// Transport parameters for a single leg, keyed by parameter name.
+pub type TransportParams = Map<String, Value>;
+
+fn is_rtp(transport: &Transport) -> bool {
+    matches!(
+        transport,
+        Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast
+    )
+}
+
+/// Whether a transport is managed by the given IS-05 version.
+#[must_use]
+pub fn supports_transport(transport: &Transport, api: &APIVersion) -> bool {
+    match transport {
+        Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast => true,
+        Transport::Websocket | Transport::Mqtt => *api >= is_05::V1_1,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivationMode {
+    #[serde(rename = "activate_immediate")]
+    Immediate,
+    #[serde(rename = "activate_scheduled_absolute")]
+    ScheduledAbsolute,
+    #[serde(rename = "activate_scheduled_relative")]
+    ScheduledRelative,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Activation {
+    pub mode: Option<ActivationMode>,
+    /// Absolute TAI time, or an offset for relative activations
+    pub requested_time: Option<TaiTime>,
+    pub activation_time: Option<TaiTime>,
+}
+
+impl Activation {
+    /// A scheduled activation as staged while pending, with relative
+    /// requested times resolved against `now`.
+    pub fn scheduled(
+        mode: ActivationMode,
+        requested_time: TaiTime,
+        now: TaiTime,
+    ) -> Result<Activation, ConnectionError> {
+        let activation_time = match mode {
+            ActivationMode::Immediate => now,
+            ActivationMode::ScheduledAbsolute => requested_time,
+            ActivationMode::ScheduledRelative => {
+                now.checked_add(requested_time.as_duration()).ok_or(
+                    ConnectionError::InvalidActivation("requested time out of range"),
+                )?
+            }
+        };
+
+        Ok(Activation {
+            mode: Some(mode),
+            requested_time: Some(requested_time),
+            activation_time: Some(activation_time),
+        })
+    }
+
+    /// Time a pending scheduled activation is due, if any.
+    #[must_use]
+    pub fn pending(&self) -> Option<TaiTime> {
+        match self.mode {
+            Some(ActivationMode::ScheduledAbsolute | ActivationMode::ScheduledRelative) => {
+                self.activation_time
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportFile {
+    pub data: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SenderEndpoint {
+    pub receiver_id: Option<Uuid>,
+    pub master_enable: bool,
+    pub activation: Activation,
+    pub transport_params: Vec<TransportParams>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiverEndpoint {
+    pub sender_id: Option<Uuid>,
+    pub master_enable: bool,
+    pub activation: Activation,
+    pub transport_file: TransportFile,
+    pub transport_params: Vec<TransportParams>,
+}
+
+/// Distinguish a field set to `null` from an absent one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActivationPatch {
+    /// `Some(None)`, an explicit `null`, cancels a pending scheduled
+    /// activation
+    #[serde(default, deserialize_with = "present")]
+    pub mode: Option<Option<ActivationMode>>,
+    #[serde(default)]
+    pub requested_time: Option<TaiTime>,
+}
+
+impl ActivationPatch {
+    /// Whether the patch cancels a pending scheduled activation.
+    #[must_use]
+    pub fn cancels(&self) -> bool {
+        self.mode == Some(None)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SenderPatch {
+    #[serde(default, deserialize_with = "present")]
+    pub receiver_id: Option<Option<Uuid>>,
+    pub master_enable: Option<bool>,
+    pub activation: Option<ActivationPatch>,
+    pub transport_params: Option<Vec<TransportParams>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReceiverPatch {
+    #[serde(default, deserialize_with = "present")]
+    pub sender_id: Option<Option<Uuid>>,
+    pub master_enable: Option<bool>,
+    pub activation: Option<ActivationPatch>,
+    pub transport_file: Option<TransportFile>,
+    pub transport_params: Option<Vec<TransportParams>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// Number of transport parameter legs differs from the endpoint
+    LegCount {
+        expected: usize,
+        found: usize,
+    },
+    UnknownParameter(String),
+    /// Transport parameters of the wrong type
+    InvalidParams {
+        leg: usize,
+        reason: String,
+    },
+    /// A transport parameter breaks its constraint
+    Constraint {
+        leg: usize,
+        param: String,
+        violation: Violation,
+    },
+    InvalidActivation(&'static str),
+    /// The transport file cannot be used to configure the receiver
+    TransportFile(String),
+    /// Staged parameters cannot change while an activation is pending
+    Locked,
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::LegCount { expected, found } => write!(
+                f,
+                "Expected transport parameters for {} legs, found {}",
+                expected, found
+            ),
+            ConnectionError::UnknownParameter(param) => {
+                write!(f, "Unknown transport parameter: {}", param)
+            }
+            ConnectionError::InvalidParams { leg, reason } => {
+                write!(f, "Invalid transport parameters on leg {}: {}", leg, reason)
+            }
+            ConnectionError::Constraint {
+                leg,
+                param,
+                violation,
+            } => write!(
+                f,
+                "Transport parameter {} on leg {} {}",
+                param, leg, violation
+            ),
+            ConnectionError::InvalidActivation(reason) => {
+                write!(f, "Invalid activation: {}", reason)
+            }
+            ConnectionError::TransportFile(reason) => {
+                write!(f, "Invalid transport file: {}", reason)
+            }
+            ConnectionError::Locked => write!(f, "A scheduled activation is pending"),
+        }
+    }
+}
+
+impl StdError for ConnectionError {}
+
+fn params(pairs: &[(&str, Value)]) -> TransportParams {
+    pairs
+        .iter()
+        .map(|(key, value)| ((*key).to_owned(), value.clone()))
+        .collect()
+}
+
+/// Initial parameters for one leg of a sender.
+fn sender_params(transport: &Transport) -> TransportParams {
+    match transport {
+        Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast => params(&[
+            ("source_ip", Value::from("auto")),
+            ("destination_ip", Value::from("auto")),
+            ("source_port", Value::from("auto")),
+            ("destination_port", Value::from("auto")),
+            ("rtp_enabled", Value::from(true)),
+        ]),
+        Transport::Websocket => params(&[
+            ("connection_uri", Value::from("auto")),
+            ("connection_authorization", Value::from("auto")),
+        ]),
+        Transport::Mqtt => params(&[
+            ("source_host", Value::from("auto")),
+            ("source_port", Value::from("auto")),
+            ("destination_host", Value::from("auto")),
+            ("destination_port", Value::from("auto")),
+            ("broker_protocol", Value::from("auto")),
+            ("broker_authorization", Value::from("auto")),
+            ("broker_topic", Value::Null),
+            ("connection_status_broker_topic", Value::Null),
+        ]),
+        _ => TransportParams::new(),
+    }
+}
+
+/// Initial parameters for one leg of a receiver.
+fn receiver_params(transport: &Transport) -> TransportParams {
+    match transport {
+        Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast => params(&[
+            ("source_ip", Value::Null),
+            ("multicast_ip", Value::Null),
+            ("interface_ip", Value::from("auto")),
+            ("destination_port", Value::from("auto")),
+            ("rtp_enabled", Value::from(true)),
+        ]),
+        Transport::Websocket => params(&[
+            ("connection_uri", Value::Null),
+            ("connection_authorization", Value::from("auto")),
+        ]),
+        Transport::Mqtt => params(&[
+            ("source_host", Value::from("auto")),
+            ("source_port", Value::from("auto")),
+            ("broker_protocol", Value::from("auto")),
+            ("broker_authorization", Value::from("auto")),
+            ("broker_topic", Value::Null),
+            ("connection_status_broker_topic", Value::Null),
+        ]),
+        _ => TransportParams::new(),
+    }
+}
+
+/// Resize endpoint parameters to one entry per leg, keeping existing legs.
+fn resize_params(params: &mut Vec<TransportParams>, legs: usize, initial: &TransportParams) {
+    params.resize(legs, initial.clone());
+}
+
+/// Check a patch has one entry per leg and only known parameters, each
+/// satisfying its constraint.
+fn check_params(
+    constraints: &[LegConstraints],
+    patch: &[TransportParams],
+) -> Result<(), ConnectionError> {
+    if patch.len() != constraints.len() {
+        return Err(ConnectionError::LegCount {
+            expected: constraints.len(),
+            found: patch.len(),
+        });
+    }
+
+    for (leg, (params, constraints)) in patch.iter().zip(constraints).enumerate() {
+        for (param, value) in params {
+            let constraint = constraints
+                .get(param)
+                .ok_or_else(|| ConnectionError::UnknownParameter(param.clone()))?;
+
+            if value == "auto" && constraints::supports_auto(param) {
+                continue;
+            }
+
+            constraint
+                .check(value)
+                .map_err(|violation| ConnectionError::Constraint {
+                    leg,
+                    param: param.clone(),
+                    violation,
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check each leg still reads as typed parameters once the patch is merged.
+fn check_typed<T, F>(
+    staged: &[TransportParams],
+    patch: &[TransportParams],
+    parse: F,
+) -> Result<(), ConnectionError>
+where
+    F: Fn(&TransportParams) -> serde_json::Result<T>,
+{
+    for (leg, (staged, patch)) in staged.iter().zip(patch).enumerate() {
+        let mut merged = staged.clone();
+        merged.extend(patch.clone());
+
+        parse(&merged).map_err(|err| ConnectionError::InvalidParams {
+            leg,
+            reason: err.to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// RTP parameters for each leg described by an SDP transport file. Legs
+/// missing from the file are disabled. Returns `None` when the file is
+/// cleared.
+fn transport_file_params(
+    file: &TransportFile,
+    legs: usize,
+) -> Result<Option<Vec<TransportParams>>, ConnectionError> {
+    let data = match &file.data {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    match file.type_.as_deref() {
+        Some(SDP_MEDIA_TYPE) => {}
+        _ => {
+            return Err(ConnectionError::TransportFile(format!(
+                "type must be {}",
+                SDP_MEDIA_TYPE
+            )))
+        }
+    }
+
+    let session: SessionDescription = data
+        .parse()
+        .map_err(|err: SdpError| ConnectionError::TransportFile(err.to_string()))?;
+
+    let mut described = session.receiver_params();
+    if described.len() > legs {
+        return Err(ConnectionError::TransportFile(format!(
+            "describes {} legs but the receiver has {}",
+            described.len(),
+            legs
+        )));
+    }
+    described.resize(
+        legs,
+        RtpReceiverParams {
+            rtp_enabled: false,
+            ..RtpReceiverParams::default()
+        },
+    );
+
+    Ok(Some(
+        described
+            .iter()
+            .map(|rtp| {
+                let mut params = TransportParams::new();
+                rtp.apply(&mut params);
+                params
+            })
+            .collect(),
+    ))
+}
+
+fn merge_params(staged: &mut [TransportParams], patch: Vec<TransportParams>) {
+    for (staged, patch) in staged.iter_mut().zip(patch) {
+        staged.extend(patch);
+    }
+}
+
+/// Nothing may change while a scheduled activation is pending, unless the
+/// same patch cancels it.
+fn check_locked(
+    staged: &Activation,
+    changes: bool,
+    activation: &Option<ActivationPatch>,
+) -> Result<(), ConnectionError> {
+    let cancels = activation.as_ref().map_or(false, ActivationPatch::cancels);
+    let activates = activation.as_ref().map_or(false, |a| a.mode.is_some());
+
+    if staged.pending().is_some() && !cancels && (changes || activates) {
+        Err(ConnectionError::Locked)
+    } else {
+        Ok(())
+    }
+}
+
+fn check_activation(activation: &ActivationPatch) -> Result<(), ConnectionError> {
+    match (activation.mode, activation.requested_time) {
+        (Some(Some(ActivationMode::Immediate)), Some(_)) => Err(
+            ConnectionError::InvalidActivation("immediate activations take no requested time"),
+        ),
+        (
+            Some(Some(ActivationMode::ScheduledAbsolute | ActivationMode::ScheduledRelative)),
+            None,
+        ) => Err(ConnectionError::InvalidActivation(
+            "scheduled activations need a requested time",
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SenderConnection {
+    pub transport: Transport,
+    /// Constraints for each leg, keyed by parameter name
+    pub constraints: Vec<LegConstraints>,
+    pub staged: SenderEndpoint,
+    pub active: SenderEndpoint,
+    /// Incremented whenever the staged endpoint changes, so an activation
+    /// carried out without the model locked can tell it was superseded.
+    pub generation: u64,
+}
+
+impl SenderConnection {
+    /// Connection state for a sender with the given number of legs, e.g. two
+    /// for ST 2022-7. The endpoints start from the IS-04 subscription.
+    #[must_use]
+    pub fn new(sender: &Sender, legs: usize) -> Self {
+        let params = sender_params(&sender.transport);
+        let endpoint = SenderEndpoint {
+            receiver_id: sender.subscription,
+            master_enable: sender.subscription_active,
+            activation: Activation::default(),
+            transport_params: vec![params.clone(); legs],
+        };
+
+        Self {
+            transport: sender.transport.clone(),
+            constraints: vec![constraints::unconstrained(&params); legs],
+            staged: endpoint.clone(),
+            active: endpoint,
+            generation: 0,
+        }
+    }
+
+    /// Replace the constraints, with one entry per leg. Endpoints gain or
+    /// lose legs to match.
+    pub fn set_constraints<L: Into<LegConstraints>>(&mut self, legs: Vec<L>) {
+        let initial = sender_params(&self.transport);
+        self.constraints = legs.into_iter().map(Into::into).collect();
+
+        let legs = self.constraints.len();
+        resize_params(&mut self.staged.transport_params, legs, &initial);
+        resize_params(&mut self.active.transport_params, legs, &initial);
+        self.generation += 1;
+    }
+
+    /// Apply the endpoint fields of a patch to the staged endpoint,
+    /// returning any activation request for the caller to carry out.
+    pub fn stage(
+        &mut self,
+        patch: SenderPatch,
+    ) -> Result<Option<ActivationPatch>, ConnectionError> {
+        // Validate everything before modifying anything
+        let changes = patch.receiver_id.is_some()
+            || patch.master_enable.is_some()
+            || patch.transport_params.is_some();
+        check_locked(&self.staged.activation, changes, &patch.activation)?;
+        if let Some(params) = &patch.transport_params {
+            check_params(&self.constraints, params)?;
+            if is_rtp(&self.transport) {
+                check_typed(
+                    &self.staged.transport_params,
+                    params,
+                    RtpSenderParams::from_params,
+                )?;
+            }
+        }
+        if let Some(activation) = &patch.activation {
+            check_activation(activation)?;
+        }
+
+        if let Some(receiver_id) = patch.receiver_id {
+            self.staged.receiver_id = receiver_id;
+        }
+        if let Some(master_enable) = patch.master_enable {
+            self.staged.master_enable = master_enable;
+        }
+        if let Some(params) = patch.transport_params {
+            merge_params(&mut self.staged.transport_params, params);
+        }
+        self.generation += 1;
+
+        Ok(patch.activation)
+    }
+
+    /// Schedule activation of the staged endpoint, returning the activation
+    /// as staged.
+    pub fn schedule(
+        &mut self,
+        mode: ActivationMode,
+        requested_time: TaiTime,
+        now: TaiTime,
+    ) -> Result<Activation, ConnectionError> {
+        self.staged.activation = Activation::scheduled(mode, requested_time, now)?;
+        self.generation += 1;
+        Ok(self.staged.activation.clone())
+    }
+
+    /// Cancel any pending scheduled activation.
+    pub fn cancel(&mut self) {
+        self.staged.activation = Activation::default();
+        self.generation += 1;
+    }
+
+    /// Copy the staged endpoint to the active endpoint with the given
+    /// transport parameters, clearing the staged activation. Returns the
+    /// activation as performed.
+    pub fn activate(
+        &mut self,
+        mode: ActivationMode,
+        requested_time: Option<TaiTime>,
+        time: TaiTime,
+        transport_params: Vec<TransportParams>,
+    ) -> Activation {
+        let activation = Activation {
+            mode: Some(mode),
+            requested_time,
+            activation_time: Some(time),
+        };
+
+        self.active = SenderEndpoint {
+            activation: activation.clone(),
+            transport_params,
+            ..self.staged.clone()
+        };
+        self.staged.activation = Activation::default();
+        self.generation += 1;
+
+        activation
+    }
+
+    /// Put back the staged endpoint from before a patch whose activation
+    /// failed, unless it has been staged again since `generation`. Returns
+    /// whether it was put back.
+    pub fn revert(&mut self, staged: SenderEndpoint, generation: u64) -> bool {
+        if self.generation != generation {
+            return false;
+        }
+
+        self.staged = staged;
+        self.generation += 1;
+        true
+    }
+
+    /// The staged transport parameters with `"auto"` RTP parameters replaced
+    /// by values chosen by the resolver.
+    #[must_use]
+    pub fn resolved(&self, sender_id: &Uuid, resolver: &dyn RtpResolver) -> Vec<TransportParams> {
+        let mut transport_params = self.staged.transport_params.clone();
+        if !is_rtp(&self.transport) {
+            return transport_params;
+        }
+
+        for (leg, params) in transport_params.iter_mut().enumerate() {
+            if let Ok(mut rtp) = RtpSenderParams::from_params(params) {
+                rtp.resolve(sender_id, leg, resolver);
+                rtp.apply(params);
+            }
+        }
+
+        transport_params
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiverConnection {
+    pub transport: Transport,
+    /// Constraints for each leg, keyed by parameter name
+    pub constraints: Vec<LegConstraints>,
+    pub staged: ReceiverEndpoint,
+    pub active: ReceiverEndpoint,
+    /// Incremented whenever the staged endpoint changes, so an activation
+    /// carried out without the model locked can tell it was superseded.
+    pub generation: u64,
+}
+
+impl ReceiverConnection {
+    /// Connection state for a receiver with the given number of legs, e.g.
+    /// two for ST 2022-7. The endpoints start from the IS-04 subscription.
+    #[must_use]
+    pub fn new(receiver: &Receiver, legs: usize) -> Self {
+        let params = receiver_params(&receiver.transport);
+        let endpoint = ReceiverEndpoint {
+            sender_id: receiver.subscription,
+            master_enable: receiver.subscription_active,
+            activation: Activation::default(),
+            transport_file: TransportFile::default(),
+            transport_params: vec![params.clone(); legs],
+        };
+
+        Self {
+            transport: receiver.transport.clone(),
+            constraints: vec![constraints::unconstrained(&params); legs],
+            staged: endpoint.clone(),
+            active: endpoint,
+            generation: 0,
+        }
+    }
+
+    /// Replace the constraints, with one entry per leg. Endpoints gain or
+    /// lose legs to match.
+    pub fn set_constraints<L: Into<LegConstraints>>(&mut self, legs: Vec<L>) {
+        let initial = receiver_params(&self.transport);
+        self.constraints = legs.into_iter().map(Into::into).collect();
+
+        let legs = self.constraints.len();
+        resize_params(&mut self.staged.transport_params, legs, &initial);
+        resize_params(&mut self.active.transport_params, legs, &initial);
+        self.generation += 1;
+    }
+
+    /// Apply the endpoint fields of a patch to the staged endpoint,
+    /// returning any activation request for the caller to carry out.
+    pub fn stage(
+        &mut self,
+        patch: ReceiverPatch,
+    ) -> Result<Option<ActivationPatch>, ConnectionError> {
+        // Validate everything before modifying anything
+        let changes = patch.sender_id.is_some()
+            || patch.master_enable.is_some()
+            || patch.transport_file.is_some()
+            || patch.transport_params.is_some();
+        check_locked(&self.staged.activation, changes, &patch.activation)?;
+        let file_params = match &patch.transport_file {
+            Some(file) if is_rtp(&self.transport) => {
+                transport_file_params(file, self.constraints.len())?
+            }
+            _ => None,
+        };
+        if let Some(params) = &file_params {
+            check_params(&self.constraints, params)?;
+        }
+        if let Some(params) = &patch.transport_params {
+            check_params(&self.constraints, params)?;
+            if is_rtp(&self.transport) {
+                check_typed(
+                    &self.staged.transport_params,
+                    params,
+                    RtpReceiverParams::from_params,
+                )?;
+            }
+        }
+        if let Some(activation) = &patch.activation {
+            check_activation(activation)?;
+        }
+
+        if let Some(sender_id) = patch.sender_id {
+            self.staged.sender_id = sender_id;
+        }
+        if let Some(master_enable) = patch.master_enable {
+            self.staged.master_enable = master_enable;
+        }
+        if let Some(transport_file) = patch.transport_file {
+            self.staged.transport_file = transport_file;
+        }
+        // Parameters given alongside a transport file take precedence
+        if let Some(params) = file_params {
+            merge_params(&mut self.staged.transport_params, params);
+        }
+        if let Some(params) = patch.transport_params {
+            merge_params(&mut self.staged.transport_params, params);
+        }
+        self.generation += 1;
+
+        Ok(patch.activation)
+    }
+
+    /// Schedule activation of the staged endpoint, returning the activation
+    /// as staged.
+    pub fn schedule(
+        &mut self,
+        mode: ActivationMode,
+        requested_time: TaiTime,
+        now: TaiTime,
+    ) -> Result<Activation, ConnectionError> {
+        self.staged.activation = Activation::scheduled(mode, requested_time, now)?;
+        self.generation += 1;
+        Ok(self.staged.activation.clone())
+    }
+
+    /// Cancel any pending scheduled activation.
+    pub fn cancel(&mut self) {
+        self.staged.activation = Activation::default();
+        self.generation += 1;
+    }
+
+    /// Copy the staged endpoint to the active endpoint with the given
+    /// transport parameters, clearing the staged activation. Returns the
+    /// activation as performed.
+    pub fn activate(
+        &mut self,
+        mode: ActivationMode,
+        requested_time: Option<TaiTime>,
+        time: TaiTime,
+        transport_params: Vec<TransportParams>,
+    ) -> Activation {
+        let activation = Activation {
+            mode: Some(mode),
+            requested_time,
+            activation_time: Some(time),
+        };
+
+        self.active = ReceiverEndpoint {
+            activation: activation.clone(),
+            transport_params,
+            ..self.staged.clone()
+        };
+        self.staged.activation = Activation::default();
+        self.generation += 1;
+
+        activation
+    }
+
+    /// Put back the staged endpoint from before a patch whose activation
+    /// failed, unless it has been staged again since `generation`. Returns
+    /// whether it was put back.
+    pub fn revert(&mut self, staged: ReceiverEndpoint, generation: u64) -> bool {
+        if self.generation != generation {
+            return false;
+        }
+
+        self.staged = staged;
+        self.generation += 1;
+        true
+    }
+
+    /// The staged transport parameters with `"auto"` RTP parameters replaced
+    /// by values chosen by the resolver.
+    #[must_use]
+    pub fn resolved(&self, receiver_id: &Uuid, resolver: &dyn RtpResolver) -> Vec<TransportParams> {
+        let mut transport_params = self.staged.transport_params.clone();
+        if !is_rtp(&self.transport) {
+            return transport_params;
+        }
+
+        for (leg, params) in transport_params.iter_mut().enumerate() {
+            if let Ok(mut rtp) = RtpReceiverParams::from_params(params) {
+                rtp.resolve(receiver_id, leg, resolver);
+                rtp.apply(params);
+            }
+        }
+
+        transport_params
+    }
+}
+
+impl Model {
+    /// Activate a sender's staged endpoint with the given transport
+    /// parameters, e.g. from [`SenderConnection::resolved`], and update the
+    /// IS-04 sender's subscription. Returns `None` if the sender is not
+    /// connection managed.
+    pub fn activate_sender(
+        &mut self,
+        id: &Uuid,
+        mode: ActivationMode,
+        requested_time: Option<TaiTime>,
+        time: TaiTime,
+        transport_params: Vec<TransportParams>,
+    ) -> Option<Activation> {
+        let connection = self.sender_connections.get_mut(id)?;
+        let activation = connection.activate(mode, requested_time, time, transport_params);
+
+        if let Some(sender) = self.senders.get_mut(id) {
+            sender.subscribe(
+                connection.active.receiver_id,
+                connection.active.master_enable,
+            );
+        }
+
+        Some(activation)
+    }
+
+    /// Activate a receiver's staged endpoint with the given transport
+    /// parameters, e.g. from [`ReceiverConnection::resolved`], and update the
+    /// IS-04 receiver's subscription. Returns `None` if the receiver is not
+    /// connection managed.
+    pub fn activate_receiver(
+        &mut self,
+        id: &Uuid,
+        mode: ActivationMode,
+        requested_time: Option<TaiTime>,
+        time: TaiTime,
+        transport_params: Vec<TransportParams>,
+    ) -> Option<Activation> {
+        let connection = self.receiver_connections.get_mut(id)?;
+        let activation = connection.activate(mode, requested_time, time, transport_params);
+
+        if let Some(receiver) = self.receivers.get_mut(id) {
+            receiver.subscribe(connection.active.sender_id, connection.active.master_enable);
+        }
+
+        Some(activation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::resource::{Device, DeviceType, Flow, Format, Node, Source};
+
+    /// A single leg RTP sender with a scheduled activation pending.
+    fn locked_sender() -> SenderConnection {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        let flow = Flow::builder("Flow", &source).build();
+        let sender = Sender::builder("Sender", &device, &flow, Transport::Rtp).build();
+
+        let mut connection = SenderConnection::new(&sender, 1);
+        connection
+            .schedule(
+                ActivationMode::ScheduledRelative,
+                TaiTime::from_duration(std::time::Duration::from_secs(60)),
+                TaiTime::now(),
+            )
+            .unwrap();
+        connection
+    }
+
+    fn patch(value: Value) -> SenderPatch {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn locked_rejects_changes() {
+        let mut connection = locked_sender();
+        let staged = connection.staged.clone();
+
+        for value in [
+            json!({"master_enable": true}),
+            json!({"transport_params": [{"destination_port": 5004}]}),
+            json!({"master_enable": true, "activation": {}}),
+            json!({"activation": {"mode": "activate_immediate"}}),
+        ] {
+            assert_eq!(connection.stage(patch(value)), Err(ConnectionError::Locked));
+        }
+        assert_eq!(connection.staged, staged);
+
+        // Empty patches change nothing
+        assert_eq!(connection.stage(patch(json!({}))), Ok(None));
+    }
+
+    #[test]
+    fn locked_allows_cancel() {
+        let mut connection = locked_sender();
+
+        let activation = connection
+            .stage(patch(json!({
+                "master_enable": true,
+                "activation": {"mode": null}
+            })))
+            .unwrap()
+            .unwrap();
+        assert!(activation.cancels());
+        assert!(connection.staged.master_enable);
+    }
+
+    #[test]
+    fn explicit_null_cancels() {
+        let empty: ActivationPatch = serde_json::from_value(json!({})).unwrap();
+        assert!(!empty.cancels());
+
+        let null: ActivationPatch = serde_json::from_value(json!({"mode": null})).unwrap();
+        assert!(null.cancels());
+    }
+
+    #[test]
+    fn revert_unless_staged_again() {
+        let mut connection = locked_sender();
+        connection.cancel();
+
+        let previous = connection.staged.clone();
+        let generation = connection.generation;
+        connection
+            .stage(patch(json!({"master_enable": true})))
+            .unwrap();
+        assert!(connection.generation > generation);
+
+        // Staged again since, so the later patch is kept
+        let staged = connection.generation;
+        connection
+            .stage(patch(json!({"receiver_id": null})))
+            .unwrap();
+        assert!(!connection.revert(previous.clone(), staged));
+        assert!(connection.staged.master_enable);
+
+        let staged = connection.generation;
+        assert!(connection.revert(previous.clone(), staged));
+        assert_eq!(connection.staged, previous);
+        assert!(connection.generation > staged);
+    }
+}
@@ -0,0 +1,399 @@
+//! Typed RTP transport parameters.
+//!
+//! Controllers may leave addresses and ports as `"auto"` for the sender or
+//! receiver to choose. They are resolved on activation by an [`RtpResolver`]
+//! and reported as concrete values in the active endpoint.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    sync::Mutex,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::TransportParams;
+
+/// Port used for RTP unless a resolver chooses another.
+pub const DEFAULT_RTP_PORT: u16 = 5004;
+
+/// A parameter value which may be left for the sender or receiver to choose,
+/// represented as `"auto"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Auto<T> {
+    Auto,
+    Value(T),
+}
+
+impl<T> Auto<T> {
+    #[must_use]
+    pub fn is_auto(&self) -> bool {
+        matches!(self, Auto::Auto)
+    }
+
+    /// The value, if one has been chosen.
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Auto::Auto => None,
+            Auto::Value(value) => Some(value),
+        }
+    }
+
+    /// Replace `"auto"` with the value returned by `f`, if any.
+    pub fn resolve<F: FnOnce() -> Option<T>>(&mut self, f: F) {
+        if self.is_auto() {
+            if let Some(value) = f() {
+                *self = Auto::Value(value);
+            }
+        }
+    }
+}
+
+impl<T> Default for Auto<T> {
+    fn default() -> Self {
+        Auto::Auto
+    }
+}
+
+impl<T> From<T> for Auto<T> {
+    fn from(value: T) -> Self {
+        Auto::Value(value)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Auto<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auto::Auto => f.write_str("auto"),
+            Auto::Value(value) => value.fmt(f),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Auto<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Auto::Auto => serializer.serialize_str("auto"),
+            Auto::Value(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Auto<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(s) if s == "auto" => Ok(Auto::Auto),
+            value => T::deserialize(value)
+                .map(Auto::Value)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Transport parameters for one leg of an RTP sender.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtpSenderParams {
+    pub source_ip: Auto<IpAddr>,
+    pub destination_ip: Auto<IpAddr>,
+    pub source_port: Auto<u16>,
+    pub destination_port: Auto<u16>,
+    pub rtp_enabled: bool,
+}
+
+impl Default for RtpSenderParams {
+    fn default() -> Self {
+        Self {
+            source_ip: Auto::Auto,
+            destination_ip: Auto::Auto,
+            source_port: Auto::Auto,
+            destination_port: Auto::Auto,
+            rtp_enabled: true,
+        }
+    }
+}
+
+impl RtpSenderParams {
+    /// Read the RTP parameters of a leg, ignoring any others such as FEC.
+    pub fn from_params(params: &TransportParams) -> serde_json::Result<Self> {
+        serde_json::from_value(Value::Object(params.clone()))
+    }
+
+    /// Write the RTP parameters into a leg, keeping any others.
+    pub fn apply(&self, params: &mut TransportParams) {
+        if let Value::Object(map) = serde_json::to_value(self).expect("Params are valid JSON") {
+            params.extend(map);
+        }
+    }
+
+    /// Choose values for every `"auto"` parameter the resolver can.
+    pub fn resolve(&mut self, sender_id: &Uuid, leg: usize, resolver: &dyn RtpResolver) {
+        self.source_ip.resolve(|| resolver.interface_ip(leg));
+        self.destination_ip
+            .resolve(|| resolver.destination_ip(sender_id, leg));
+        self.source_port.resolve(|| Some(resolver.port(leg)));
+        self.destination_port.resolve(|| Some(resolver.port(leg)));
+    }
+}
+
+/// Transport parameters for one leg of an RTP receiver.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtpReceiverParams {
+    /// Source for source specific multicast
+    pub source_ip: Option<IpAddr>,
+    /// Group to join, or `None` for unicast
+    pub multicast_ip: Option<IpAddr>,
+    pub interface_ip: Auto<IpAddr>,
+    pub destination_port: Auto<u16>,
+    pub rtp_enabled: bool,
+}
+
+impl Default for RtpReceiverParams {
+    fn default() -> Self {
+        Self {
+            source_ip: None,
+            multicast_ip: None,
+            interface_ip: Auto::Auto,
+            destination_port: Auto::Auto,
+            rtp_enabled: true,
+        }
+    }
+}
+
+impl RtpReceiverParams {
+    /// Read the RTP parameters of a leg, ignoring any others such as FEC.
+    pub fn from_params(params: &TransportParams) -> serde_json::Result<Self> {
+        serde_json::from_value(Value::Object(params.clone()))
+    }
+
+    /// Write the RTP parameters into a leg, keeping any others.
+    pub fn apply(&self, params: &mut TransportParams) {
+        if let Value::Object(map) = serde_json::to_value(self).expect("Params are valid JSON") {
+            params.extend(map);
+        }
+    }
+
+    /// Choose values for every `"auto"` parameter the resolver can.
+    pub fn resolve(&mut self, _receiver_id: &Uuid, leg: usize, resolver: &dyn RtpResolver) {
+        self.interface_ip.resolve(|| resolver.interface_ip(leg));
+        self.destination_port.resolve(|| Some(resolver.port(leg)));
+    }
+}
+
+/// Chooses values for `"auto"` RTP parameters on activation. Parameters the
+/// resolver has no value for are left as `"auto"`.
+pub trait RtpResolver: Send + Sync {
+    /// Address of the interface used by a leg, for a sender's `source_ip` and
+    /// a receiver's `interface_ip`.
+    fn interface_ip(&self, _leg: usize) -> Option<IpAddr> {
+        None
+    }
+
+    /// Address a sender's leg sends to, e.g. a multicast group allocated
+    /// from a pool. Called on every activation leaving it as `"auto"`.
+    fn destination_ip(&self, _sender_id: &Uuid, _leg: usize) -> Option<IpAddr> {
+        None
+    }
+
+    fn port(&self, _leg: usize) -> u16 {
+        DEFAULT_RTP_PORT
+    }
+}
+
+/// Resolver using one interface address per leg and allocating sender
+/// destinations from a pool of multicast groups. Each sender's leg keeps its
+/// group across activations.
+#[derive(Debug, Default)]
+pub struct RtpPool {
+    interfaces: Vec<IpAddr>,
+    groups: Vec<IpAddr>,
+    allocated: Mutex<HashMap<(Uuid, usize), IpAddr>>,
+}
+
+impl RtpPool {
+    /// Pool with the interface address of each leg, e.g. two for ST 2022-7.
+    #[must_use]
+    pub fn new(interfaces: Vec<IpAddr>) -> Self {
+        Self {
+            interfaces,
+            ..Self::default()
+        }
+    }
+
+    /// Add `count` consecutive IPv4 multicast groups starting at `first`.
+    #[must_use]
+    pub fn multicast_range(mut self, first: Ipv4Addr, count: u32) -> Self {
+        let first = u32::from(first);
+        self.groups.extend(
+            (0..count)
+                .filter_map(|i| first.checked_add(i))
+                .map(|group| IpAddr::V4(Ipv4Addr::from(group))),
+        );
+        self
+    }
+
+    #[must_use]
+    pub fn multicast_group(mut self, group: IpAddr) -> Self {
+        self.groups.push(group);
+        self
+    }
+}
+
+impl RtpResolver for RtpPool {
+    fn interface_ip(&self, leg: usize) -> Option<IpAddr> {
+        self.interfaces.get(leg).copied()
+    }
+
+    fn destination_ip(&self, sender_id: &Uuid, leg: usize) -> Option<IpAddr> {
+        let mut allocated = self.allocated.lock().expect("RTP pool poisoned");
+        if let Some(group) = allocated.get(&(*sender_id, leg)) {
+            return Some(*group);
+        }
+
+        let group = *self
+            .groups
+            .iter()
+            .find(|group| !allocated.values().any(|a| a == *group))?;
+        allocated.insert((*sender_id, leg), group);
+        Some(group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn auto_serde() {
+        let auto: Auto<u16> = serde_json::from_value(json!("auto")).unwrap();
+        assert!(auto.is_auto());
+        assert_eq!(serde_json::to_value(auto).unwrap(), json!("auto"));
+
+        let port: Auto<u16> = serde_json::from_value(json!(5004)).unwrap();
+        assert_eq!(port.value(), Some(&5004));
+        assert_eq!(serde_json::to_value(port).unwrap(), json!(5004));
+
+        assert!(serde_json::from_value::<Auto<u16>>(json!("5004")).is_err());
+        assert!(serde_json::from_value::<Auto<IpAddr>>(json!("not an address")).is_err());
+    }
+
+    #[test]
+    fn pool_interfaces_per_leg() {
+        let pool = RtpPool::new(vec![v4(192, 168, 1, 10), v4(192, 168, 2, 10)]);
+
+        assert_eq!(pool.interface_ip(0), Some(v4(192, 168, 1, 10)));
+        assert_eq!(pool.interface_ip(1), Some(v4(192, 168, 2, 10)));
+        assert_eq!(pool.interface_ip(2), None);
+    }
+
+    #[test]
+    fn pool_allocates_groups() {
+        let pool = RtpPool::new(Vec::new()).multicast_range(Ipv4Addr::new(239, 0, 0, 1), 3);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let a0 = pool.destination_ip(&a, 0).unwrap();
+        let a1 = pool.destination_ip(&a, 1).unwrap();
+        let b0 = pool.destination_ip(&b, 0).unwrap();
+        assert_eq!(a0, v4(239, 0, 0, 1));
+        assert_eq!(a1, v4(239, 0, 0, 2));
+        assert_eq!(b0, v4(239, 0, 0, 3));
+
+        // Kept across activations, until the pool runs out
+        assert_eq!(pool.destination_ip(&a, 0), Some(a0));
+        assert_eq!(pool.destination_ip(&b, 1), None);
+
+        // No groups, nothing allocated
+        assert_eq!(RtpPool::new(Vec::new()).destination_ip(&a, 0), None);
+    }
+
+    #[test]
+    fn pool_range_stops_at_end_of_address_space() {
+        let pool = RtpPool::new(Vec::new())
+            .multicast_range(Ipv4Addr::new(255, 255, 255, 254), 4)
+            .multicast_group(v4(239, 1, 1, 1));
+
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let groups: Vec<_> = ids.iter().map(|id| pool.destination_ip(id, 0)).collect();
+        assert_eq!(
+            groups,
+            [
+                Some(v4(255, 255, 255, 254)),
+                Some(v4(255, 255, 255, 255)),
+                Some(v4(239, 1, 1, 1)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn sender_resolve() {
+        let pool =
+            RtpPool::new(vec![v4(192, 168, 1, 10)]).multicast_range(Ipv4Addr::new(239, 0, 0, 1), 1);
+        let id = Uuid::new_v4();
+
+        let mut rtp = RtpSenderParams::default();
+        rtp.resolve(&id, 0, &pool);
+        assert_eq!(
+            rtp,
+            RtpSenderParams {
+                source_ip: Auto::Value(v4(192, 168, 1, 10)),
+                destination_ip: Auto::Value(v4(239, 0, 0, 1)),
+                source_port: Auto::Value(DEFAULT_RTP_PORT),
+                destination_port: Auto::Value(DEFAULT_RTP_PORT),
+                rtp_enabled: true,
+            }
+        );
+
+        // Chosen values are kept, and unresolvable ones stay "auto"
+        let mut rtp = RtpSenderParams {
+            destination_ip: Auto::Value(v4(192, 168, 1, 20)),
+            ..RtpSenderParams::default()
+        };
+        rtp.resolve(&id, 1, &pool);
+        assert_eq!(rtp.destination_ip, Auto::Value(v4(192, 168, 1, 20)));
+        assert!(rtp.source_ip.is_auto());
+    }
+
+    #[test]
+    fn receiver_resolve() {
+        let pool = RtpPool::new(vec![v4(192, 168, 1, 10)]);
+
+        let mut rtp = RtpReceiverParams {
+            multicast_ip: Some(v4(239, 0, 0, 1)),
+            ..RtpReceiverParams::default()
+        };
+        rtp.resolve(&Uuid::new_v4(), 0, &pool);
+        assert_eq!(rtp.interface_ip, Auto::Value(v4(192, 168, 1, 10)));
+        assert_eq!(rtp.destination_port, Auto::Value(DEFAULT_RTP_PORT));
+        assert_eq!(rtp.multicast_ip, Some(v4(239, 0, 0, 1)));
+    }
+
+    #[test]
+    fn apply_keeps_other_params() {
+        let mut params: TransportParams = serde_json::from_value(json!({
+            "source_ip": "auto",
+            "destination_ip": "auto",
+            "source_port": "auto",
+            "destination_port": "auto",
+            "rtp_enabled": true,
+            "fec_enabled": false
+        }))
+        .unwrap();
+
+        let mut rtp = RtpSenderParams::from_params(&params).unwrap();
+        rtp.destination_port = Auto::Value(5006);
+        rtp.apply(&mut params);
+
+        assert_eq!(params["destination_port"], 5006);
+        assert_eq!(params["destination_ip"], "auto");
+        assert_eq!(params["fec_enabled"], false);
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::result::Result as StdResult;
+
+use serde_json::Error as JsonError;
+
+use crate::version::APIVersion;
+
+pub type Result<T> = StdResult<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Json(JsonError),
+    UnsupportedVersion(APIVersion),
+    MissingField(&'static str),
+    InvalidField(&'static str),
+    InvalidFormat(String),
+    InvalidTransport(String),
+    UnsupportedTransport { transport: String, api: APIVersion },
+    InvalidDeviceType(String),
+    InvalidGroupHint(String),
+    InvalidPattern(String),
+}
+
+impl From<JsonError> for Error {
+    fn from(e: JsonError) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(e) => fmt::Display::fmt(&e, f),
+            Error::UnsupportedVersion(api) => write!(f, "Unsupported API: {}", api),
+            Error::MissingField(field) => write!(f, "Missing field: {}", field),
+            Error::InvalidField(field) => write!(f, "Invalid field: {}", field),
+            Error::InvalidFormat(format) => write!(f, "Invalid format: {}", format),
+            Error::InvalidTransport(transport) => write!(f, "Invalid transport: {}", transport),
+            Error::UnsupportedTransport { transport, api } => {
+                write!(f, "Transport {} is not supported by API {}", transport, api)
+            }
+            Error::InvalidDeviceType(type_) => write!(f, "Invalid device type: {}", type_),
+            Error::InvalidGroupHint(hint) => write!(f, "Invalid group hint: {}", hint),
+            Error::InvalidPattern(pattern) => write!(f, "Invalid pattern: {}", pattern),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -1,11 +1,43 @@
+pub mod config;
+pub mod connection;
+pub mod error;
+pub mod pattern;
+pub mod persist;
 pub mod resource;
+pub mod sdp;
 pub mod tai;
 pub mod version;
 
 use std::collections::HashMap;
 
-use resource::{Device, Flow, Node, Receiver, ResourceBundle, Sender, Source};
+use connection::{ReceiverConnection, SenderConnection};
+use resource::{
+    Device, DeviceJson, Flow, GroupHint, GroupScope, Node, Receiver, ResourceBundle, ResourceCore,
+    Sender, Source,
+};
 use uuid::Uuid;
+use version::{is_05, APIVersion};
+
+/// Senders and receivers belonging to a BCP-002-01 group, by id and role.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GroupMembers {
+    pub senders: Vec<(Uuid, String)>,
+    pub receivers: Vec<(Uuid, String)>,
+}
+
+impl GroupMembers {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty() && self.receivers.is_empty()
+    }
+}
+
+fn group_role(core: &ResourceCore, group: &str, scope: GroupScope) -> Option<String> {
+    core.group_hints()
+        .into_iter()
+        .find(|hint| hint.group == group && hint.scope == scope)
+        .map(|hint| hint.role)
+}
 
 #[derive(Debug, Default)]
 pub struct Model {
@@ -16,6 +48,10 @@ pub struct Model {
     pub flows: HashMap<Uuid, Flow>,
     pub senders: HashMap<Uuid, Sender>,
     pub receivers: HashMap<Uuid, Receiver>,
+
+    // IS-05 connection state, keyed by sender or receiver id
+    pub sender_connections: HashMap<Uuid, SenderConnection>,
+    pub receiver_connections: HashMap<Uuid, ReceiverConnection>,
 }
 
 impl Model {
@@ -79,13 +115,57 @@ impl Model {
                     map
                 });
 
-        Self {
+        let mut model = Self {
             nodes,
             devices,
             sources,
             flows,
             senders,
             receivers,
+            ..Self::default()
+        };
+
+        let sender_ids: Vec<Uuid> = model.senders.keys().copied().collect();
+        let receiver_ids: Vec<Uuid> = model.receivers.keys().copied().collect();
+        sender_ids
+            .iter()
+            .for_each(|id| model.add_sender_connection(id));
+        receiver_ids
+            .iter()
+            .for_each(|id| model.add_receiver_connection(id));
+
+        model
+    }
+
+    /// Create single leg IS-05 state for a sender, if its transport is
+    /// managed by IS-05 and it has none yet.
+    fn add_sender_connection(&mut self, id: &Uuid) {
+        let sender = match self.senders.get(id) {
+            Some(sender) if connection::supports_transport(&sender.transport, &is_05::V1_1) => {
+                sender
+            }
+            _ => return,
+        };
+
+        if !self.sender_connections.contains_key(id) {
+            let connection = SenderConnection::new(sender, 1);
+            self.sender_connections.insert(*id, connection);
+        }
+    }
+
+    /// Create single leg IS-05 state for a receiver, if its transport is
+    /// managed by IS-05 and it has none yet.
+    fn add_receiver_connection(&mut self, id: &Uuid) {
+        let receiver = match self.receivers.get(id) {
+            Some(receiver) if connection::supports_transport(&receiver.transport, &is_05::V1_1) => {
+                receiver
+            }
+            _ => return,
+        };
+
+        if !self.receiver_connections.contains_key(id) {
+            let connection = ReceiverConnection::new(receiver, 1);
+            self.receiver_connections.insert(*id, connection);
         }
     }
 
@@ -112,8 +192,141 @@ impl Model {
             return None;
         }
 
-        self.receivers.insert(receiver.core.id, receiver);
+        let id = receiver.core.id;
+        self.receivers.insert(id, receiver);
+        self.add_receiver_connection(&id);
 
         Some(())
     }
+
+    /// JSON for a device, listing only the senders and receivers whose
+    /// transport can be represented in the API version, as the others are
+    /// hidden from it.
+    #[must_use]
+    pub fn device_json(&self, device: &Device, api: &APIVersion) -> DeviceJson {
+        let senders: Vec<Uuid> = device
+            .senders
+            .iter()
+            .filter(|id| {
+                self.senders
+                    .get(id)
+                    .map_or(true, |sender| sender.transport.supports(api))
+            })
+            .copied()
+            .collect();
+        let receivers: Vec<Uuid> = device
+            .receivers
+            .iter()
+            .filter(|id| {
+                self.receivers
+                    .get(id)
+                    .map_or(true, |receiver| receiver.transport.supports(api))
+            })
+            .copied()
+            .collect();
+
+        device.to_json_listing(api, &senders, &receivers)
+    }
+
+    /// Members of a group. Device scoped groups only contain resources on
+    /// `device_id`, node scoped groups may span every device.
+    #[must_use]
+    pub fn group_members(&self, device_id: &Uuid, group: &str, scope: GroupScope) -> GroupMembers {
+        let in_scope = |id: &Uuid| scope == GroupScope::Node || id == device_id;
+
+        let mut members = GroupMembers {
+            senders: self
+                .senders
+                .values()
+                .filter(|sender| in_scope(&sender.device_id))
+                .filter_map(|sender| {
+                    Some((sender.core.id, group_role(&sender.core, group, scope)?))
+                })
+                .collect(),
+            receivers: self
+                .receivers
+                .values()
+                .filter(|receiver| in_scope(&receiver.device_id))
+                .filter_map(|receiver| {
+                    Some((receiver.core.id, group_role(&receiver.core, group, scope)?))
+                })
+                .collect(),
+        };
+
+        // Map iteration order is arbitrary
+        members.senders.sort();
+        members.receivers.sort();
+        members
+    }
+
+    /// Every distinct group hint used by senders and receivers.
+    #[must_use]
+    pub fn group_hints(&self) -> Vec<GroupHint> {
+        let senders = self.senders.values().map(|sender| &sender.core);
+        let receivers = self.receivers.values().map(|receiver| &receiver.core);
+
+        let mut hints: Vec<GroupHint> = senders
+            .chain(receivers)
+            .flat_map(ResourceCore::group_hints)
+            .collect();
+        hints.sort_by_key(|hint| {
+            (
+                hint.group.clone(),
+                hint.role.clone(),
+                hint.scope == GroupScope::Node,
+            )
+        });
+        hints.dedup();
+        hints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::{DeviceType, Format, Transport};
+    use crate::version::is_04::{V1_0, V1_2, V1_3};
+
+    #[test]
+    fn device_json_hides_unsupported_transports() {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let mut device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Data).build();
+        let flow = Flow::builder("Flow", &source).build();
+        let rtp = Sender::builder("RTP", &device, &flow, Transport::Rtp).build();
+        let mqtt = Sender::builder("MQTT", &device, &flow, Transport::Mqtt).build();
+        let srt = Receiver::builder("SRT", &device, Format::Data, Transport::Srt).build();
+        device.senders = vec![rtp.core.id, mqtt.core.id];
+        device.receivers = vec![srt.core.id];
+        let (rtp_id, mqtt_id, srt_id) = (rtp.core.id, mqtt.core.id, srt.core.id);
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_sender(rtp);
+        bundle.insert_sender(mqtt);
+        bundle.insert_receiver(srt);
+        let model = Model::from_resources(bundle);
+        let device = model.devices.values().next().unwrap();
+
+        let listing = |api: &APIVersion| {
+            let json = serde_json::to_value(model.device_json(device, api)).unwrap();
+            let ids =
+                |key: &str| -> Vec<String> { serde_json::from_value(json[key].clone()).unwrap() };
+            (ids("senders"), ids("receivers"))
+        };
+
+        assert_eq!(listing(&V1_0), (vec![rtp_id.to_string()], vec![]));
+        assert_eq!(
+            listing(&V1_2),
+            (vec![rtp_id.to_string()], vec![srt_id.to_string()])
+        );
+        assert_eq!(
+            listing(&V1_3),
+            (
+                vec![rtp_id.to_string(), mqtt_id.to_string()],
+                vec![srt_id.to_string()]
+            )
+        );
+    }
 }
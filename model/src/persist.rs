@@ -0,0 +1,458 @@
+//! Snapshots of model state which should survive a restart.
+//!
+//! Resources are matched to their saved state by id. Resources created with
+//! random ids are matched instead by kind and the label they were first
+//! created with, and take over their previous id.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    connection::{Activation, ReceiverEndpoint, SenderEndpoint},
+    resource::ResourceCore,
+    Model,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceState {
+    /// Kind and original label, used to match resources with random ids
+    pub key: String,
+    pub label: String,
+    pub description: String,
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Sender a receiver is subscribed to, or receiver a sender sends to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<Uuid>,
+    /// Whether the subscription is active, taken to be whenever it is set if
+    /// missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_active: Option<bool>,
+    /// IS-05 endpoints of a connection managed sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_connection: Option<Endpoints<SenderEndpoint>>,
+    /// IS-05 endpoints of a connection managed receiver
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receiver_connection: Option<Endpoints<ReceiverEndpoint>>,
+}
+
+/// Staged and active IS-05 endpoints.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Endpoints<E> {
+    pub staged: E,
+    pub active: E,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Resource state keyed by id
+    pub resources: BTreeMap<Uuid, ResourceState>,
+}
+
+impl Snapshot {
+    /// Load a snapshot, returning `None` if the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<Snapshot>> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Store the snapshot, atomically replacing any existing file.
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let contents = serde_json::to_vec_pretty(self)?;
+
+        // Write and sync a temporary file, then rename it over the original
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Whether a saved endpoint has one set of parameters per configured leg.
+fn legs_match<C, P>(constraints: &[C], params: &[P]) -> bool {
+    params.len() == constraints.len()
+}
+
+fn key(kind: &str, core: &ResourceCore) -> String {
+    format!("{}/{}", kind, core.label)
+}
+
+fn remap<V>(map: &mut HashMap<Uuid, V>, ids: &HashMap<Uuid, Uuid>) {
+    let old = std::mem::take(map);
+    map.extend(
+        old.into_iter()
+            .map(|(id, value)| (ids.get(&id).copied().unwrap_or(id), value)),
+    );
+}
+
+fn remap_id(id: &mut Uuid, ids: &HashMap<Uuid, Uuid>) {
+    if let Some(new) = ids.get(id) {
+        *id = *new;
+    }
+}
+
+fn remap_all(list: &mut [Uuid], ids: &HashMap<Uuid, Uuid>) {
+    list.iter_mut().for_each(|id| remap_id(id, ids));
+}
+
+impl Model {
+    /// Every resource core, with the kind of resource it belongs to.
+    fn cores(&self) -> impl Iterator<Item = (&'static str, &ResourceCore)> {
+        let nodes = self.nodes.values().map(|r| ("node", &r.core));
+        let devices = self.devices.values().map(|r| ("device", &r.core));
+        let sources = self.sources.values().map(|r| ("source", &r.core));
+        let flows = self.flows.values().map(|r| ("flow", &r.core));
+        let senders = self.senders.values().map(|r| ("sender", &r.core));
+        let receivers = self.receivers.values().map(|r| ("receiver", &r.core));
+
+        nodes
+            .chain(devices)
+            .chain(sources)
+            .chain(flows)
+            .chain(senders)
+            .chain(receivers)
+    }
+
+    fn cores_mut(&mut self) -> impl Iterator<Item = &mut ResourceCore> {
+        let nodes = self.nodes.values_mut().map(|r| &mut r.core);
+        let devices = self.devices.values_mut().map(|r| &mut r.core);
+        let sources = self.sources.values_mut().map(|r| &mut r.core);
+        let flows = self.flows.values_mut().map(|r| &mut r.core);
+        let senders = self.senders.values_mut().map(|r| &mut r.core);
+        let receivers = self.receivers.values_mut().map(|r| &mut r.core);
+
+        nodes
+            .chain(devices)
+            .chain(sources)
+            .chain(flows)
+            .chain(senders)
+            .chain(receivers)
+    }
+
+    /// Capture the current state. Keys are carried over from `previous` so
+    /// renamed resources still match the definition they were created from.
+    #[must_use]
+    pub fn snapshot(&self, previous: Option<&Snapshot>) -> Snapshot {
+        let resources = self
+            .cores()
+            .map(|(kind, core)| {
+                let key = previous
+                    .and_then(|p| p.resources.get(&core.id))
+                    .map_or_else(|| key(kind, core), |state| state.key.clone());
+
+                let subscription = match (self.senders.get(&core.id), self.receivers.get(&core.id))
+                {
+                    (Some(sender), _) => Some((sender.subscription, sender.subscription_active)),
+                    (_, Some(receiver)) => {
+                        Some((receiver.subscription, receiver.subscription_active))
+                    }
+                    _ => None,
+                };
+
+                let state = ResourceState {
+                    key,
+                    label: core.label.clone(),
+                    description: core.description.clone(),
+                    tags: core.tags.clone(),
+                    subscription: subscription.and_then(|(id, _)| id),
+                    subscription_active: subscription.map(|(_, active)| active),
+                    sender_connection: self.sender_connections.get(&core.id).map(|c| Endpoints {
+                        staged: c.staged.clone(),
+                        active: c.active.clone(),
+                    }),
+                    receiver_connection: self.receiver_connections.get(&core.id).map(|c| {
+                        Endpoints {
+                            staged: c.staged.clone(),
+                            active: c.active.clone(),
+                        }
+                    }),
+                };
+
+                (core.id, state)
+            })
+            .collect();
+
+        Snapshot { resources }
+    }
+
+    /// Apply saved state to freshly created resources. Versions are left
+    /// untouched as the resources are new to any registry. Connections are
+    /// left inactive, with what was active staged in their place.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // Keys must be unique on both sides to be matched
+        let mut saved_keys: HashMap<&str, Vec<Uuid>> = HashMap::new();
+        for (id, state) in &snapshot.resources {
+            saved_keys.entry(state.key.as_str()).or_default().push(*id);
+        }
+
+        let mut model_keys: HashMap<String, Vec<Uuid>> = HashMap::new();
+        for (kind, core) in self.cores() {
+            model_keys.entry(key(kind, core)).or_default().push(core.id);
+        }
+
+        let ids: HashMap<Uuid, Uuid> = model_keys
+            .iter()
+            .filter_map(
+                |(key, ids)| match (ids.as_slice(), saved_keys.get(key.as_str())) {
+                    ([id], Some(saved)) => match saved.as_slice() {
+                        [saved] if !snapshot.resources.contains_key(id) => Some((*id, *saved)),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+            )
+            .collect();
+
+        if !ids.is_empty() {
+            self.remap_ids(&ids);
+        }
+
+        for core in self.cores_mut() {
+            if let Some(state) = snapshot.resources.get(&core.id) {
+                core.label = state.label.clone();
+                core.description = state.description.clone();
+                core.tags = state.tags.clone();
+            }
+        }
+
+        for (id, state) in &snapshot.resources {
+            let subscription_active = state
+                .subscription_active
+                .unwrap_or_else(|| state.subscription.is_some());
+
+            if let Some(sender) = self.senders.get_mut(id) {
+                sender.subscription = state.subscription;
+                sender.subscription_active = subscription_active;
+            } else if let Some(receiver) = self.receivers.get_mut(id) {
+                receiver.subscription = state.subscription;
+                receiver.subscription_active = subscription_active;
+            }
+        }
+
+        // The endpoints which were active are restored as staged, if the
+        // number of legs is unchanged, for the node to activate through its
+        // handlers. Until then connections are inactive. Scheduled
+        // activations and unactivated staged changes do not survive a restart.
+        for (id, connection) in self.sender_connections.iter_mut() {
+            let saved = snapshot
+                .resources
+                .get(id)
+                .and_then(|state| state.sender_connection.as_ref());
+            match saved {
+                Some(saved)
+                    if legs_match(&connection.constraints, &saved.active.transport_params) =>
+                {
+                    connection.staged = SenderEndpoint {
+                        activation: Activation::default(),
+                        ..saved.active.clone()
+                    };
+                }
+                _ => {
+                    if let Some(sender) = self.senders.get(id) {
+                        connection.staged.receiver_id = sender.subscription;
+                        connection.staged.master_enable = sender.subscription_active;
+                    }
+                }
+            }
+            if let Some(sender) = self.senders.get_mut(id) {
+                sender.subscription = connection.active.receiver_id;
+                sender.subscription_active = connection.active.master_enable;
+            }
+        }
+        for (id, connection) in self.receiver_connections.iter_mut() {
+            let saved = snapshot
+                .resources
+                .get(id)
+                .and_then(|state| state.receiver_connection.as_ref());
+            match saved {
+                Some(saved)
+                    if legs_match(&connection.constraints, &saved.active.transport_params) =>
+                {
+                    connection.staged = ReceiverEndpoint {
+                        activation: Activation::default(),
+                        ..saved.active.clone()
+                    };
+                }
+                _ => {
+                    if let Some(receiver) = self.receivers.get(id) {
+                        connection.staged.sender_id = receiver.subscription;
+                        connection.staged.master_enable = receiver.subscription_active;
+                    }
+                }
+            }
+            if let Some(receiver) = self.receivers.get_mut(id) {
+                receiver.subscription = connection.active.sender_id;
+                receiver.subscription_active = connection.active.master_enable;
+            }
+        }
+    }
+
+    /// Replace resource ids, including references between resources.
+    fn remap_ids(&mut self, ids: &HashMap<Uuid, Uuid>) {
+        for core in self.cores_mut() {
+            remap_id(&mut core.id, ids);
+        }
+
+        remap(&mut self.nodes, ids);
+        remap(&mut self.devices, ids);
+        remap(&mut self.sources, ids);
+        remap(&mut self.flows, ids);
+        remap(&mut self.senders, ids);
+        remap(&mut self.receivers, ids);
+        remap(&mut self.sender_connections, ids);
+        remap(&mut self.receiver_connections, ids);
+
+        for device in self.devices.values_mut() {
+            remap_id(&mut device.node_id, ids);
+            remap_all(&mut device.senders, ids);
+            remap_all(&mut device.receivers, ids);
+        }
+        for source in self.sources.values_mut() {
+            remap_id(&mut source.device_id, ids);
+            remap_all(&mut source.parents, ids);
+        }
+        for flow in self.flows.values_mut() {
+            remap_id(&mut flow.source_id, ids);
+            remap_all(&mut flow.parents, ids);
+        }
+        for sender in self.senders.values_mut() {
+            remap_id(&mut sender.flow_id, ids);
+            remap_id(&mut sender.device_id, ids);
+        }
+        for receiver in self.receivers.values_mut() {
+            remap_id(&mut receiver.device_id, ids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::connection::{ActivationMode, ReceiverPatch};
+    use crate::resource::{
+        Device, DeviceType, Flow, Format, Node, Receiver, ResourceBundle, Sender, Source, Transport,
+    };
+    use crate::tai::TaiTime;
+
+    /// A node with one RTP sender and receiver, created with random ids.
+    fn new_model() -> Model {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        let flow = Flow::builder("Flow", &source).build();
+        let sender = Sender::builder("Sender", &device, &flow, Transport::Rtp).build();
+        let receiver =
+            Receiver::builder("Receiver", &device, Format::Video, Transport::Rtp).build();
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_source(source);
+        bundle.insert_flow(flow);
+        bundle.insert_sender(sender);
+        bundle.insert_receiver(receiver);
+        Model::from_resources(bundle)
+    }
+
+    fn id_of(model: &Model, label: &str) -> Uuid {
+        model
+            .cores()
+            .find(|(_, core)| core.label == label)
+            .map(|(_, core)| core.id)
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut model = new_model();
+        let first = model.snapshot(None);
+
+        // Rename the device and connect the receiver
+        let device_id = id_of(&model, "Device");
+        let receiver_id = id_of(&model, "Receiver");
+        let sender_id = Uuid::new_v4();
+
+        let core = &mut model.devices.get_mut(&device_id).unwrap().core;
+        core.set_label("Renamed");
+        core.set_description("Described");
+        core.set_tag("location", vec!["Studio 1"]);
+
+        let patch: ReceiverPatch = serde_json::from_value(json!({
+            "sender_id": sender_id,
+            "master_enable": true,
+        }))
+        .unwrap();
+        let connection = model.receiver_connections.get_mut(&receiver_id).unwrap();
+        connection.stage(patch).unwrap();
+        let params = connection.staged.transport_params.clone();
+        model
+            .activate_receiver(
+                &receiver_id,
+                ActivationMode::Immediate,
+                None,
+                TaiTime::now(),
+                params,
+            )
+            .unwrap();
+        let active = model.receiver_connections[&receiver_id].active.clone();
+
+        // Store and load through a file
+        let snapshot = model.snapshot(Some(&first));
+        let path = std::env::temp_dir().join(format!("nmos-persist-{}.json", Uuid::new_v4()));
+        snapshot.store(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        // A fresh model takes over the saved ids by original label
+        let mut restored = new_model();
+        restored.restore(&loaded);
+
+        let device = &restored.devices[&device_id];
+        assert_eq!(device.core.label, "Renamed");
+        assert_eq!(device.core.description, "Described");
+        assert_eq!(device.core.tags["location"], vec!["Studio 1"]);
+        assert_eq!(restored.senders.len(), 1);
+        for sender in restored.senders.values() {
+            assert_eq!(sender.device_id, device_id);
+        }
+
+        // The active endpoint is staged for activation, not made active
+        let connection = &restored.receiver_connections[&receiver_id];
+        assert_eq!(connection.staged.sender_id, Some(sender_id));
+        assert!(connection.staged.master_enable);
+        assert_eq!(connection.staged.transport_params, active.transport_params);
+        assert_eq!(connection.staged.activation, Activation::default());
+        assert!(!connection.active.master_enable);
+        assert_eq!(connection.active.sender_id, None);
+
+        let receiver = &restored.receivers[&receiver_id];
+        assert_eq!(receiver.subscription, None);
+        assert!(!receiver.subscription_active);
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join(format!("nmos-persist-{}.json", Uuid::new_v4()));
+        assert!(Snapshot::load(path).unwrap().is_none());
+    }
+}
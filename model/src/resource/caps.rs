@@ -0,0 +1,721 @@
+//! Receiver capabilities: accepted media types and BCP-004-01 constraint sets.
+
+use std::{cmp::Ordering, collections::BTreeMap, error::Error as StdError, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::{Error, Result},
+    pattern::Pattern,
+    version::{
+        is_04::{V1_1, V1_3},
+        APIVersion,
+    },
+};
+
+use super::{json::JsonObject, Flow, Format};
+
+/// BCP-004-01 parameter URNs understood by the evaluator.
+pub mod cap {
+    pub const MEDIA_TYPE: &str = "urn:x-nmos:cap:format:media_type";
+    pub const GRAIN_RATE: &str = "urn:x-nmos:cap:format:grain_rate";
+    pub const FRAME_WIDTH: &str = "urn:x-nmos:cap:format:frame_width";
+    pub const FRAME_HEIGHT: &str = "urn:x-nmos:cap:format:frame_height";
+    pub const INTERLACE_MODE: &str = "urn:x-nmos:cap:format:interlace_mode";
+    pub const COLORSPACE: &str = "urn:x-nmos:cap:format:colorspace";
+    pub const COLOR_SAMPLING: &str = "urn:x-nmos:cap:format:color_sampling";
+    pub const COMPONENT_DEPTH: &str = "urn:x-nmos:cap:format:component_depth";
+    pub const SAMPLE_RATE: &str = "urn:x-nmos:cap:format:sample_rate";
+    pub const SAMPLE_DEPTH: &str = "urn:x-nmos:cap:format:sample_depth";
+    pub const CHANNEL_COUNT: &str = "urn:x-nmos:cap:format:channel_count";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rational {
+    pub numerator: i64,
+    #[serde(default = "Rational::default_denominator")]
+    pub denominator: i64,
+}
+
+impl Rational {
+    #[must_use]
+    pub const fn new(numerator: i64, denominator: i64) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    fn default_denominator() -> i64 {
+        1
+    }
+
+    #[must_use]
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.denominator == 0 || other.denominator == 0 {
+            return None;
+        }
+        // Compare n1/d1 with n2/d2 without losing precision
+        let lhs = i128::from(self.numerator) * i128::from(other.denominator);
+        let rhs = i128::from(other.numerator) * i128::from(self.denominator);
+        Some(if (self.denominator < 0) ^ (other.denominator < 0) {
+            rhs.cmp(&lhs)
+        } else {
+            lhs.cmp(&rhs)
+        })
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// A value a parameter constraint can refer to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConstraintValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+    Rational(Rational),
+}
+
+impl ConstraintValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (ConstraintValue::Integer(a), ConstraintValue::Integer(b)) => Some(a.cmp(b)),
+            (ConstraintValue::Rational(a), ConstraintValue::Rational(b)) => a.partial_cmp(b),
+            (ConstraintValue::Rational(a), ConstraintValue::Integer(b)) => {
+                a.partial_cmp(&Rational::new(*b, 1))
+            }
+            (ConstraintValue::Integer(a), ConstraintValue::Rational(b)) => {
+                Rational::new(*a, 1).partial_cmp(b)
+            }
+            _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            ConstraintValue::Integer(i) => Some(*i as f64),
+            ConstraintValue::Number(n) => Some(*n),
+            ConstraintValue::Rational(r) => Some(r.as_f64()),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for ConstraintValue {
+    fn from(value: bool) -> Self {
+        ConstraintValue::Boolean(value)
+    }
+}
+
+impl From<i64> for ConstraintValue {
+    fn from(value: i64) -> Self {
+        ConstraintValue::Integer(value)
+    }
+}
+
+impl From<u32> for ConstraintValue {
+    fn from(value: u32) -> Self {
+        ConstraintValue::Integer(value.into())
+    }
+}
+
+impl From<f64> for ConstraintValue {
+    fn from(value: f64) -> Self {
+        ConstraintValue::Number(value)
+    }
+}
+
+impl From<&str> for ConstraintValue {
+    fn from(value: &str) -> Self {
+        ConstraintValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for ConstraintValue {
+    fn from(value: String) -> Self {
+        ConstraintValue::String(value)
+    }
+}
+
+impl From<Rational> for ConstraintValue {
+    fn from(value: Rational) -> Self {
+        ConstraintValue::Rational(value)
+    }
+}
+
+/// Constraint on a single parameter. Every part which is set must hold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterConstraint {
+    #[serde(rename = "enum", default, skip_serializing_if = "Vec::is_empty")]
+    pub enum_values: Vec<ConstraintValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<ConstraintValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<ConstraintValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<Pattern>,
+}
+
+impl ParameterConstraint {
+    pub fn enumeration<I, V>(values: I) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<ConstraintValue>,
+    {
+        Self {
+            enum_values: values.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn range<V: Into<ConstraintValue>>(minimum: V, maximum: V) -> Self {
+        Self {
+            minimum: Some(minimum.into()),
+            maximum: Some(maximum.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn minimum<V: Into<ConstraintValue>>(minimum: V) -> Self {
+        Self {
+            minimum: Some(minimum.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn maximum<V: Into<ConstraintValue>>(maximum: V) -> Self {
+        Self {
+            maximum: Some(maximum.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Strings matching an ECMAScript style regular expression, which is
+    /// rejected if it does not compile.
+    pub fn pattern(pattern: &str) -> Result<Self> {
+        Ok(Self {
+            pattern: Some(Pattern::new(pattern)?),
+            ..Self::default()
+        })
+    }
+
+    #[must_use]
+    pub fn is_satisfied_by(&self, value: &ConstraintValue) -> bool {
+        if !self.enum_values.is_empty()
+            && !self
+                .enum_values
+                .iter()
+                .any(|v| v == value || v.partial_cmp(value) == Some(Ordering::Equal))
+        {
+            return false;
+        }
+
+        if let Some(minimum) = &self.minimum {
+            match value.partial_cmp(minimum) {
+                Some(Ordering::Greater | Ordering::Equal) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(maximum) = &self.maximum {
+            match value.partial_cmp(maximum) {
+                Some(Ordering::Less | Ordering::Equal) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(pattern) = &self.pattern {
+            let matches = match value {
+                ConstraintValue::String(s) => pattern.is_match(s),
+                _ => false,
+            };
+            if !matches {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A BCP-004-01 constraint set. A flow is acceptable if it satisfies every
+/// constraint in any one enabled set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConstraintSet {
+    #[serde(
+        rename = "urn:x-nmos:cap:meta:label",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub label: Option<String>,
+    #[serde(
+        rename = "urn:x-nmos:cap:meta:preference",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub preference: Option<i8>,
+    #[serde(
+        rename = "urn:x-nmos:cap:meta:enabled",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub enabled: Option<bool>,
+    /// Constraints keyed by parameter URN, see [`cap`]
+    #[serde(flatten)]
+    pub constraints: BTreeMap<String, ParameterConstraint>,
+}
+
+impl ConstraintSet {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Preference from -100 to 100, higher being preferred.
+    #[must_use]
+    pub fn preference(mut self, preference: i8) -> Self {
+        self.preference = Some(preference);
+        self
+    }
+
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    #[must_use]
+    pub fn constraint<S: Into<String>>(
+        mut self,
+        parameter: S,
+        constraint: ParameterConstraint,
+    ) -> Self {
+        self.constraints.insert(parameter.into(), constraint);
+        self
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Whether the flow satisfies every constraint. Parameters the evaluator
+    /// does not understand are ignored, as BCP-004-01 requires.
+    #[must_use]
+    pub fn is_satisfied_by(&self, flow: &Flow) -> bool {
+        self.constraints.iter().all(
+            |(parameter, constraint)| match flow_value(flow, parameter) {
+                Some(Some(value)) => constraint.is_satisfied_by(&value),
+                Some(None) => false,
+                None => true,
+            },
+        )
+    }
+}
+
+/// The value of a parameter for a flow. `None` if the parameter is not
+/// understood, `Some(None)` if the flow does not describe it.
+fn flow_value(flow: &Flow, parameter: &str) -> Option<Option<ConstraintValue>> {
+    let video = flow.video.as_ref();
+    let audio = flow.audio.as_ref();
+
+    let value = match parameter {
+        cap::MEDIA_TYPE => flow.media_type.clone().map(Into::into),
+        cap::GRAIN_RATE => flow.grain_rate.map(Into::into),
+        cap::FRAME_WIDTH => video.map(|v| v.frame_width.into()),
+        cap::FRAME_HEIGHT => video.map(|v| v.frame_height.into()),
+        cap::INTERLACE_MODE => video.map(|v| v.interlace_mode.clone().into()),
+        cap::COLORSPACE => video.map(|v| v.colorspace.clone().into()),
+        cap::COLOR_SAMPLING => video.and_then(|v| v.color_sampling.clone()).map(Into::into),
+        cap::COMPONENT_DEPTH => video.and_then(|v| v.component_depth).map(Into::into),
+        cap::SAMPLE_RATE => audio.map(|a| a.sample_rate.into()),
+        cap::SAMPLE_DEPTH => audio.and_then(|a| a.bit_depth).map(Into::into),
+        cap::CHANNEL_COUNT => audio.and_then(|a| a.channel_count).map(Into::into),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Why a flow is not acceptable to a receiver.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CapsMismatch {
+    Format { expected: Format, found: Format },
+    MediaType(Option<String>),
+    ConstraintSets,
+}
+
+impl fmt::Display for CapsMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapsMismatch::Format { expected, found } => {
+                write!(f, "Expected format {}, found {}", expected, found)
+            }
+            CapsMismatch::MediaType(Some(media_type)) => {
+                write!(f, "Media type {} is not accepted", media_type)
+            }
+            CapsMismatch::MediaType(None) => write!(f, "Flow has no media type"),
+            CapsMismatch::ConstraintSets => write!(f, "No constraint set is satisfied"),
+        }
+    }
+}
+
+impl StdError for CapsMismatch {}
+
+/// What a receiver is able to consume.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReceiverCaps {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraint_sets: Vec<ConstraintSet>,
+}
+
+impl ReceiverCaps {
+    /// Caps as defined by the given IS-04 version. Media types are defined
+    /// from v1.1 and constraint sets from v1.3.
+    #[must_use]
+    pub fn to_json(&self, api: &APIVersion) -> BTreeMap<String, Value> {
+        let mut caps = BTreeMap::new();
+
+        if *api >= V1_1 && !self.media_types.is_empty() {
+            caps.insert(
+                "media_types".to_owned(),
+                Value::from(self.media_types.clone()),
+            );
+        }
+
+        if *api >= V1_3 && !self.constraint_sets.is_empty() {
+            let constraint_sets = serde_json::to_value(&self.constraint_sets)
+                .expect("Constraint sets are valid JSON");
+            caps.insert("constraint_sets".to_owned(), constraint_sets);
+        }
+
+        caps
+    }
+
+    /// Read caps, ignoring members this model does not understand.
+    pub fn from_json(json: &Value) -> Result<Self> {
+        let object = JsonObject::new(json).map_err(|_| Error::InvalidField("caps"))?;
+
+        Ok(ReceiverCaps {
+            media_types: object.opt_deserialize("media_types")?.unwrap_or_default(),
+            constraint_sets: object
+                .opt_deserialize("constraint_sets")?
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Check a flow against the media types and constraint sets.
+    pub fn check(&self, flow: &Flow) -> std::result::Result<(), CapsMismatch> {
+        if !self.media_types.is_empty() {
+            match &flow.media_type {
+                Some(media_type) if self.media_types.contains(media_type) => {}
+                media_type => return Err(CapsMismatch::MediaType(media_type.clone())),
+            }
+        }
+
+        // Without enabled constraint sets there is nothing to satisfy
+        let mut enabled = self
+            .constraint_sets
+            .iter()
+            .filter(|set| set.is_enabled())
+            .peekable();
+        if enabled.peek().is_some() && !enabled.any(|set| set.is_satisfied_by(flow)) {
+            return Err(CapsMismatch::ConstraintSets);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::resource::{AudioAttributes, Device, DeviceType, Node, Source, VideoAttributes};
+
+    /// 1080i25 10 bit 4:2:2 raw video.
+    fn video_flow() -> Flow {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        Flow::builder("Flow", &source)
+            .media_type("video/raw")
+            .grain_rate(Rational::new(25, 1))
+            .video(VideoAttributes {
+                interlace_mode: String::from("interlaced_tff"),
+                color_sampling: Some(String::from("YCbCr-4:2:2")),
+                component_depth: Some(10),
+                ..VideoAttributes::new(1920, 1080)
+            })
+            .build()
+    }
+
+    /// 48 kHz 24 bit stereo L24 audio.
+    fn audio_flow() -> Flow {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Audio).build();
+        Flow::builder("Flow", &source)
+            .media_type("audio/L24")
+            .audio(AudioAttributes {
+                bit_depth: Some(24),
+                channel_count: Some(2),
+                ..AudioAttributes::new(Rational::new(48000, 1))
+            })
+            .build()
+    }
+
+    fn set(parameter: &str, constraint: ParameterConstraint) -> ConstraintSet {
+        ConstraintSet::new().constraint(parameter, constraint)
+    }
+
+    #[test]
+    fn parameter_constraints() {
+        let cases: Vec<(ConstraintSet, bool)> = vec![
+            // enum
+            (
+                set(
+                    cap::MEDIA_TYPE,
+                    ParameterConstraint::enumeration(["video/raw", "video/jxsv"]),
+                ),
+                true,
+            ),
+            (
+                set(
+                    cap::MEDIA_TYPE,
+                    ParameterConstraint::enumeration(["video/jxsv"]),
+                ),
+                false,
+            ),
+            (
+                set(
+                    cap::FRAME_WIDTH,
+                    ParameterConstraint::enumeration([1280u32, 1920]),
+                ),
+                true,
+            ),
+            (
+                set(
+                    cap::COMPONENT_DEPTH,
+                    ParameterConstraint::enumeration([8u32]),
+                ),
+                false,
+            ),
+            // minimum and maximum
+            (
+                set(cap::FRAME_HEIGHT, ParameterConstraint::range(720u32, 1080)),
+                true,
+            ),
+            (
+                set(cap::FRAME_HEIGHT, ParameterConstraint::maximum(720u32)),
+                false,
+            ),
+            (
+                set(cap::FRAME_WIDTH, ParameterConstraint::minimum(1920u32)),
+                true,
+            ),
+            (
+                set(cap::FRAME_WIDTH, ParameterConstraint::minimum(3840u32)),
+                false,
+            ),
+            // rational
+            (
+                set(
+                    cap::GRAIN_RATE,
+                    ParameterConstraint::enumeration([
+                        Rational::new(25, 1),
+                        Rational::new(30000, 1001),
+                    ]),
+                ),
+                true,
+            ),
+            (
+                set(
+                    cap::GRAIN_RATE,
+                    ParameterConstraint::enumeration([Rational::new(50, 2)]),
+                ),
+                true,
+            ),
+            (
+                set(
+                    cap::GRAIN_RATE,
+                    ParameterConstraint::range(Rational::new(30000, 1001), Rational::new(60, 1)),
+                ),
+                false,
+            ),
+            (
+                set(
+                    cap::GRAIN_RATE,
+                    ParameterConstraint::maximum(Rational::new(25, 0)),
+                ),
+                false,
+            ),
+            // pattern
+            (
+                set(
+                    cap::COLOR_SAMPLING,
+                    ParameterConstraint::pattern("^YCbCr-4:2:[02]$").unwrap(),
+                ),
+                true,
+            ),
+            (
+                set(
+                    cap::INTERLACE_MODE,
+                    ParameterConstraint::pattern("^progressive$").unwrap(),
+                ),
+                false,
+            ),
+            (
+                set(
+                    cap::FRAME_WIDTH,
+                    ParameterConstraint::pattern("1920").unwrap(),
+                ),
+                false,
+            ),
+            // audio parameters are not described by a video flow
+            (
+                set(cap::SAMPLE_RATE, ParameterConstraint::minimum(1u32)),
+                false,
+            ),
+            // unknown parameters are ignored
+            (
+                set(
+                    "urn:x-nmos:cap:transport:packet_time",
+                    ParameterConstraint::enumeration([0.125]),
+                ),
+                true,
+            ),
+        ];
+
+        let flow = video_flow();
+        for (i, (set, expected)) in cases.iter().enumerate() {
+            assert_eq!(set.is_satisfied_by(&flow), *expected, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn audio_constraints() {
+        let flow = audio_flow();
+        let stereo = set(cap::CHANNEL_COUNT, ParameterConstraint::range(1u32, 2))
+            .constraint(
+                cap::SAMPLE_RATE,
+                ParameterConstraint::enumeration([Rational::new(48000, 1)]),
+            )
+            .constraint(cap::SAMPLE_DEPTH, ParameterConstraint::minimum(16u32));
+        assert!(stereo.is_satisfied_by(&flow));
+
+        let surround = set(cap::CHANNEL_COUNT, ParameterConstraint::minimum(6u32));
+        assert!(!surround.is_satisfied_by(&flow));
+    }
+
+    #[test]
+    fn receiver_caps() {
+        let flow = video_flow();
+        let hd = set(
+            cap::FRAME_WIDTH,
+            ParameterConstraint::enumeration([1920u32]),
+        );
+        let uhd = set(
+            cap::FRAME_WIDTH,
+            ParameterConstraint::enumeration([3840u32]),
+        );
+
+        let caps = |media_types: &[&str], sets: Vec<ConstraintSet>| ReceiverCaps {
+            media_types: media_types.iter().map(|m| m.to_string()).collect(),
+            constraint_sets: sets,
+        };
+
+        assert_eq!(caps(&[], vec![]).check(&flow), Ok(()));
+        assert_eq!(caps(&["video/raw"], vec![]).check(&flow), Ok(()));
+        assert_eq!(
+            caps(&["video/jxsv"], vec![]).check(&flow),
+            Err(CapsMismatch::MediaType(Some(String::from("video/raw"))))
+        );
+
+        // Any one set is enough
+        assert_eq!(
+            caps(&[], vec![uhd.clone(), hd.clone()]).check(&flow),
+            Ok(())
+        );
+        assert_eq!(
+            caps(&[], vec![uhd.clone()]).check(&flow),
+            Err(CapsMismatch::ConstraintSets)
+        );
+
+        // Disabled sets are skipped, and with none enabled anything goes
+        assert_eq!(
+            caps(&[], vec![uhd.clone(), hd.enabled(false)]).check(&flow),
+            Err(CapsMismatch::ConstraintSets)
+        );
+        assert_eq!(caps(&[], vec![uhd.enabled(false)]).check(&flow), Ok(()));
+    }
+
+    #[test]
+    fn json() {
+        let caps = ReceiverCaps {
+            media_types: vec![String::from("video/raw")],
+            constraint_sets: vec![ConstraintSet::new()
+                .label("HD")
+                .preference(10)
+                .constraint(cap::FRAME_WIDTH, ParameterConstraint::range(1280u32, 1920))
+                .constraint(
+                    cap::GRAIN_RATE,
+                    ParameterConstraint::enumeration([Rational::new(25, 1)]),
+                )
+                .constraint(
+                    cap::COLORSPACE,
+                    ParameterConstraint::pattern("^BT(709|2020)$").unwrap(),
+                )],
+        };
+
+        let expected = json!({
+            "media_types": ["video/raw"],
+            "constraint_sets": [{
+                "urn:x-nmos:cap:meta:label": "HD",
+                "urn:x-nmos:cap:meta:preference": 10,
+                "urn:x-nmos:cap:format:frame_width": {"minimum": 1280, "maximum": 1920},
+                "urn:x-nmos:cap:format:grain_rate": {"enum": [{"numerator": 25, "denominator": 1}]},
+                "urn:x-nmos:cap:format:colorspace": {"pattern": "^BT(709|2020)$"},
+            }],
+        });
+        let json = Value::Object(caps.to_json(&V1_3).into_iter().collect());
+        assert_eq!(json, expected);
+        assert_eq!(ReceiverCaps::from_json(&json).unwrap(), caps);
+
+        // Constraint sets are only defined from v1.3
+        assert!(!caps.to_json(&V1_1).contains_key("constraint_sets"));
+
+        let invalid = json!({"constraint_sets": [{
+            "urn:x-nmos:cap:format:colorspace": {"pattern": "("},
+        }]});
+        assert!(ReceiverCaps::from_json(&invalid).is_err());
+    }
+}
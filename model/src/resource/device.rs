@@ -1,20 +1,44 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
+    error::{Error, Result},
     resource::Node,
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    is_urn,
+    json::{self, JsonObject},
+    ResourceCore, ResourceCoreBuilder,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum DeviceType {
     Generic,
     Pipeline,
+    /// Vendor specific type, e.g. `urn:x-manufacturer:device:multiviewer`
+    Custom(String),
+}
+
+impl DeviceType {
+    /// A vendor specific type, checked to be a URN of the form
+    /// `urn:x-<namespace>:device:<name>`.
+    pub fn custom<S: Into<String>>(urn: S) -> Result<Self> {
+        let urn = urn.into();
+        if is_urn(&urn, "device") {
+            Ok(DeviceType::Custom(urn))
+        } else {
+            Err(Error::InvalidDeviceType(urn))
+        }
+    }
 }
 
 impl fmt::Display for DeviceType {
@@ -22,15 +46,40 @@ impl fmt::Display for DeviceType {
         match self {
             DeviceType::Generic => write!(f, "urn:x-nmos:device:generic"),
             DeviceType::Pipeline => write!(f, "urn:x-nmos:device:pipeline"),
+            DeviceType::Custom(urn) => f.write_str(urn),
+        }
+    }
+}
+
+impl FromStr for DeviceType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "urn:x-nmos:device:generic" => Ok(DeviceType::Generic),
+            "urn:x-nmos:device:pipeline" => Ok(DeviceType::Pipeline),
+            _ if is_urn(s, "device") => Ok(DeviceType::Custom(s.to_owned())),
+            _ => Err(Error::InvalidDeviceType(s.to_owned())),
         }
     }
 }
 
+impl_urn_serde!(DeviceType);
+
+/// An API controlling a device, e.g. IS-05 connection management.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceControl {
+    pub href: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
 #[must_use]
 pub struct DeviceBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     type_: DeviceType,
     node_id: Uuid,
+    controls: Vec<DeviceControl>,
 }
 
 impl DeviceBuilder {
@@ -39,9 +88,16 @@ impl DeviceBuilder {
             core: ResourceCoreBuilder::new(label),
             type_: device_type,
             node_id: node.core.id,
+            controls: Vec::new(),
         }
     }
 
+    /// Advertise a control API, listed from IS-04 v1.1.
+    pub fn control(mut self, control: DeviceControl) -> Self {
+        self.controls.push(control);
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Device {
         Device {
@@ -50,6 +106,7 @@ impl DeviceBuilder {
             node_id: self.node_id,
             senders: Vec::new(),
             receivers: Vec::new(),
+            controls: self.controls,
         }
     }
 }
@@ -61,6 +118,7 @@ pub struct Device {
     pub node_id: Uuid,
     pub senders: Vec<Uuid>,
     pub receivers: Vec<Uuid>,
+    pub controls: Vec<DeviceControl>,
 }
 
 impl Device {
@@ -72,15 +130,41 @@ impl Device {
         DeviceBuilder::new(label, node, device_type)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        let full_core = json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        Ok(Device {
+            core: object.core(full_core)?,
+            type_: object.parse("type")?,
+            node_id: object.uuid("node_id")?,
+            senders: object.uuid_array("senders")?,
+            receivers: object.uuid_array("receivers")?,
+            controls: object.opt_deserialize("controls")?.unwrap_or_default(),
+        })
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> DeviceJson {
+        self.to_json_listing(api, &self.senders, &self.receivers)
+    }
+
+    /// JSON listing only the given senders and receivers, e.g. those visible
+    /// in the API version.
+    pub(crate) fn to_json_listing(
+        &self,
+        api: &APIVersion,
+        senders: &[Uuid],
+        receivers: &[Uuid],
+    ) -> DeviceJson {
         match *api {
             V1_0 => {
                 // Senders
-                let senders = self.senders.iter().map(ToString::to_string).collect();
+                let senders = senders.iter().map(ToString::to_string).collect();
 
                 // Receivers
-                let receivers = self.receivers.iter().map(ToString::to_string).collect();
+                let receivers = receivers.iter().map(ToString::to_string).collect();
 
                 DeviceJson::V1_0(is_04::v1_0_x::Device {
                     id: self.core.id.to_string(),
@@ -92,6 +176,23 @@ impl Device {
                     receivers,
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let mut json = json::core_json(&self.core);
+                json.insert("type".to_owned(), Value::from(self.type_.to_string()));
+                json.insert("node_id".to_owned(), Value::from(self.node_id.to_string()));
+                json.insert("senders".to_owned(), uuid_array(senders));
+                json.insert("receivers".to_owned(), uuid_array(receivers));
+                json.insert(
+                    "controls".to_owned(),
+                    serde_json::to_value(&self.controls).expect("Controls are valid JSON"),
+                );
+
+                match *api {
+                    V1_1 => DeviceJson::V1_1(json::to_schema(json)),
+                    V1_2 => DeviceJson::V1_2(json::to_schema(json)),
+                    _ => DeviceJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -101,4 +202,11 @@ impl Device {
 #[serde(untagged)]
 pub enum DeviceJson {
     V1_0(is_04::v1_0_x::Device),
+    V1_1(is_04::v1_1_x::Device),
+    V1_2(is_04::v1_2_x::Device),
+    V1_3(is_04::v1_3_x::Device),
+}
+
+fn uuid_array(ids: &[Uuid]) -> Value {
+    ids.iter().map(ToString::to_string).collect()
 }
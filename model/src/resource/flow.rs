@@ -1,45 +1,219 @@
 use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
-    resource::{Format, Source},
-    version::{is_04::V1_0, APIVersion},
+    error::Result,
+    resource::{Format, Rational, Source},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonObject},
+    ResourceBuilder, ResourceCore, ResourceCoreBuilder,
+};
+
+/// Raw video flow attributes, IS-04 v1.1 onwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoAttributes {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// e.g. `progressive` or `interlaced_tff`
+    pub interlace_mode: String,
+    /// e.g. `BT709` or `BT2020`
+    pub colorspace: String,
+    /// e.g. `YCbCr-4:2:2`
+    pub color_sampling: Option<String>,
+    pub component_depth: Option<u32>,
+}
+
+impl VideoAttributes {
+    /// Progressive BT.709 video of the given size.
+    #[must_use]
+    pub fn new(frame_width: u32, frame_height: u32) -> Self {
+        Self {
+            frame_width,
+            frame_height,
+            interlace_mode: String::from("progressive"),
+            colorspace: String::from("BT709"),
+            color_sampling: None,
+            component_depth: None,
+        }
+    }
+}
+
+/// Raw audio flow attributes, IS-04 v1.1 onwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioAttributes {
+    pub sample_rate: Rational,
+    pub bit_depth: Option<u32>,
+    pub channel_count: Option<u32>,
+}
+
+impl AudioAttributes {
+    #[must_use]
+    pub fn new(sample_rate: Rational) -> Self {
+        Self {
+            sample_rate,
+            bit_depth: None,
+            channel_count: None,
+        }
+    }
+}
+
+/// A component of a raw video flow, as in the IS-04 JSON.
+#[derive(Serialize, Deserialize)]
+struct Component {
+    name: String,
+    width: u32,
+    height: u32,
+    bit_depth: u32,
+}
+
+/// Sampling as used by BCP-004-01 and SDP, from the relative component
+/// sizes, e.g. `YCbCr-4:2:2`.
+fn color_sampling(components: &[Component]) -> Option<String> {
+    let find = |name: &str| components.iter().find(|c| c.name == name);
+
+    if let (Some(y), Some(cb), Some(_)) = (find("Y"), find("Cb"), find("Cr")) {
+        let sampling = match (y.width / cb.width.max(1), y.height / cb.height.max(1)) {
+            (1, 1) => "4:4:4",
+            (2, 1) => "4:2:2",
+            (2, 2) => "4:2:0",
+            (4, 1) => "4:1:1",
+            _ => return None,
+        };
+        return Some(format!("YCbCr-{}", sampling));
+    }
+
+    if find("R").is_some() && find("G").is_some() && find("B").is_some() {
+        return Some(String::from("RGB"));
+    }
+
+    None
+}
+
+/// Components of a raw video flow, the inverse of [`color_sampling`].
+/// Sampling and depth default to those used for SDP.
+fn components(video: &VideoAttributes) -> Vec<Component> {
+    let (width, height, bit_depth) = (
+        video.frame_width,
+        video.frame_height,
+        video.component_depth.unwrap_or(10),
+    );
+    let component = |name: &str, width: u32, height: u32| Component {
+        name: name.to_owned(),
+        width,
+        height,
+        bit_depth,
+    };
+
+    let (chroma_width, chroma_height) =
+        match video.color_sampling.as_deref().unwrap_or("YCbCr-4:2:2") {
+            "RGB" => {
+                return vec![
+                    component("R", width, height),
+                    component("G", width, height),
+                    component("B", width, height),
+                ]
+            }
+            "YCbCr-4:4:4" => (width, height),
+            "YCbCr-4:2:0" => (width / 2, height / 2),
+            "YCbCr-4:1:1" => (width / 4, height),
+            _ => (width / 2, height),
+        };
+
+    vec![
+        component("Y", width, height),
+        component("Cb", chroma_width, chroma_height),
+        component("Cr", chroma_width, chroma_height),
+    ]
+}
+
+/// Media type assumed for a flow without one, which IS-04 v1.1 onwards
+/// requires.
+fn default_media_type(format: &Format) -> Option<&'static str> {
+    match format {
+        Format::Video => Some("video/raw"),
+        Format::Audio => Some("audio/L24"),
+        Format::Data => Some("video/smpte291"),
+        Format::Mux => Some("video/SMPTE2022-6"),
+        Format::Other(_) => None,
+    }
+}
 
 #[must_use]
 pub struct FlowBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     format: Format,
     source_id: Uuid,
+    device_id: Uuid,
     parents: Vec<Uuid>,
+    media_type: Option<String>,
+    grain_rate: Option<Rational>,
+    video: Option<VideoAttributes>,
+    audio: Option<AudioAttributes>,
 }
 
 impl FlowBuilder {
     pub fn new<S: Into<String>>(label: S, source: &Source) -> Self {
         FlowBuilder {
             core: ResourceCoreBuilder::new(label),
-            format: source.format,
+            format: source.format.clone(),
             source_id: source.core.id,
+            device_id: source.device_id,
             parents: Vec::new(),
+            media_type: None,
+            grain_rate: None,
+            video: None,
+            audio: None,
         }
     }
 
-    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
-        self.core = self.core.description(description);
-        self
+    /// See [`ResourceBuilder::description`].
+    pub fn description<S: Into<String>>(self, description: S) -> Self {
+        ResourceBuilder::description(self, description)
     }
 
-    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    /// See [`ResourceBuilder::tag`].
+    pub fn tag<S, V>(self, key: S, values: V) -> Self
     where
         S: Into<String>,
         V: IntoIterator<Item = S>,
     {
-        self.core = self.core.tag(key, values);
+        ResourceBuilder::tag(self, key, values)
+    }
+
+    /// Add a flow this flow is derived from.
+    pub fn parent(mut self, flow: &Flow) -> Self {
+        self.parents.push(flow.core.id);
+        self
+    }
+
+    /// MIME type, e.g. `video/raw` or `audio/L24`.
+    pub fn media_type<S: Into<String>>(mut self, media_type: S) -> Self {
+        self.media_type = Some(media_type.into());
+        self
+    }
+
+    pub fn grain_rate(mut self, grain_rate: Rational) -> Self {
+        self.grain_rate = Some(grain_rate);
+        self
+    }
+
+    pub fn video(mut self, video: VideoAttributes) -> Self {
+        self.video = Some(video);
+        self
+    }
+
+    pub fn audio(mut self, audio: AudioAttributes) -> Self {
+        self.audio = Some(audio);
         self
     }
 
@@ -49,7 +223,12 @@ impl FlowBuilder {
             core: self.core.build(),
             format: self.format,
             source_id: self.source_id,
+            device_id: self.device_id,
             parents: self.parents,
+            media_type: self.media_type,
+            grain_rate: self.grain_rate,
+            video: self.video,
+            audio: self.audio,
         }
     }
 }
@@ -59,7 +238,13 @@ pub struct Flow {
     pub core: ResourceCore,
     pub format: Format,
     pub source_id: Uuid,
+    /// Device of the source. Nil when built from v1.0 JSON, which lacks it.
+    pub device_id: Uuid,
     pub parents: Vec<Uuid>,
+    pub media_type: Option<String>,
+    pub grain_rate: Option<Rational>,
+    pub video: Option<VideoAttributes>,
+    pub audio: Option<AudioAttributes>,
 }
 
 impl Flow {
@@ -67,6 +252,53 @@ impl Flow {
         FlowBuilder::new(label, source)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        // Raw video and audio attributes are only present from v1.1
+        let components: Option<Vec<Component>> = object.opt_deserialize("components")?;
+        let video = match object.opt_deserialize::<u32>("frame_width")? {
+            Some(frame_width) => Some(VideoAttributes {
+                frame_width,
+                frame_height: object.deserialize("frame_height")?,
+                interlace_mode: object
+                    .opt_str("interlace_mode")?
+                    .unwrap_or("progressive")
+                    .to_owned(),
+                colorspace: object.str("colorspace")?.to_owned(),
+                color_sampling: components.as_deref().and_then(color_sampling),
+                component_depth: components
+                    .as_deref()
+                    .and_then(|c| c.first())
+                    .map(|c| c.bit_depth),
+            }),
+            None => None,
+        };
+
+        let audio = match object.opt_deserialize::<Rational>("sample_rate")? {
+            Some(sample_rate) => Some(AudioAttributes {
+                sample_rate,
+                bit_depth: object.opt_deserialize("bit_depth")?,
+                channel_count: None,
+            }),
+            None => None,
+        };
+
+        Ok(Flow {
+            core: object.core(true)?,
+            format: object.parse("format")?,
+            source_id: object.uuid("source_id")?,
+            device_id: object.opt_uuid("device_id")?.unwrap_or_default(),
+            parents: object.uuid_array("parents")?,
+            media_type: object.opt_str("media_type")?.map(ToOwned::to_owned),
+            grain_rate: object.opt_deserialize("grain_rate")?,
+            video,
+            audio,
+        })
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> FlowJson {
         match *api {
@@ -95,6 +327,75 @@ impl Flow {
                     parents,
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let parents = self
+                    .parents
+                    .iter()
+                    .map(|id| Value::from(id.to_string()))
+                    .collect();
+
+                let mut json = json::core_json(&self.core);
+                if let Some(grain_rate) = &self.grain_rate {
+                    json.insert(
+                        "grain_rate".to_owned(),
+                        serde_json::to_value(grain_rate).expect("Rational is valid JSON"),
+                    );
+                }
+                json.insert(
+                    "source_id".to_owned(),
+                    Value::from(self.source_id.to_string()),
+                );
+                json.insert(
+                    "device_id".to_owned(),
+                    Value::from(self.device_id.to_string()),
+                );
+                json.insert("parents".to_owned(), Value::Array(parents));
+                json.insert("format".to_owned(), Value::from(self.format.to_string()));
+
+                let media_type = self
+                    .media_type
+                    .as_deref()
+                    .or_else(|| default_media_type(&self.format));
+                if let Some(media_type) = media_type {
+                    json.insert("media_type".to_owned(), Value::from(media_type));
+                }
+
+                if let Some(video) = &self.video {
+                    json.insert("frame_width".to_owned(), Value::from(video.frame_width));
+                    json.insert("frame_height".to_owned(), Value::from(video.frame_height));
+                    json.insert(
+                        "interlace_mode".to_owned(),
+                        Value::from(video.interlace_mode.clone()),
+                    );
+                    json.insert(
+                        "colorspace".to_owned(),
+                        Value::from(video.colorspace.clone()),
+                    );
+                    if media_type == Some("video/raw") {
+                        json.insert(
+                            "components".to_owned(),
+                            serde_json::to_value(components(video))
+                                .expect("Components are valid JSON"),
+                        );
+                    }
+                }
+
+                if let Some(audio) = &self.audio {
+                    json.insert(
+                        "sample_rate".to_owned(),
+                        serde_json::to_value(audio.sample_rate).expect("Rational is valid JSON"),
+                    );
+                    if let Some(bit_depth) = audio.bit_depth {
+                        json.insert("bit_depth".to_owned(), Value::from(bit_depth));
+                    }
+                }
+
+                match *api {
+                    V1_1 => FlowJson::V1_1(json::to_schema(json)),
+                    V1_2 => FlowJson::V1_2(json::to_schema(json)),
+                    _ => FlowJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -104,4 +405,7 @@ impl Flow {
 #[serde(untagged)]
 pub enum FlowJson {
     V1_0(is_04::v1_0_x::Flow),
+    V1_1(is_04::v1_1_x::Flow),
+    V1_2(is_04::v1_2_x::Flow),
+    V1_3(is_04::v1_3_x::Flow),
 }
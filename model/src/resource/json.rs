@@ -0,0 +1,224 @@
+//! Helpers for building resources from NMOS JSON.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use nmos_schema::is_04;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    error::{Error, Result},
+    tai::TaiTime,
+    version::{
+        is_04::{self as versions, V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
+};
+
+use super::{Device, Flow, Node, Receiver, ResourceCore, Sender, Source};
+
+/// Typed access to the fields of a JSON object.
+pub(crate) struct JsonObject<'a> {
+    map: &'a Map<String, Value>,
+}
+
+impl<'a> JsonObject<'a> {
+    pub fn new(value: &'a Value) -> Result<Self> {
+        match value.as_object() {
+            Some(map) => Ok(Self { map }),
+            None => Err(Error::InvalidField("resource")),
+        }
+    }
+
+    pub fn object(&self, field: &'static str) -> Result<JsonObject<'a>> {
+        match self.map.get(field) {
+            Some(value) => JsonObject::new(value).map_err(|_| Error::InvalidField(field)),
+            None => Err(Error::MissingField(field)),
+        }
+    }
+
+    /// Optional string, where both an absent field and `null` give `None`.
+    pub fn opt_str(&self, field: &'static str) -> Result<Option<&'a str>> {
+        match self.map.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(Error::InvalidField(field)),
+        }
+    }
+
+    pub fn str(&self, field: &'static str) -> Result<&'a str> {
+        self.opt_str(field)?.ok_or(Error::MissingField(field))
+    }
+
+    pub fn opt_uuid(&self, field: &'static str) -> Result<Option<Uuid>> {
+        self.opt_str(field)?
+            .map(|s| Uuid::from_str(s).map_err(|_| Error::InvalidField(field)))
+            .transpose()
+    }
+
+    pub fn uuid(&self, field: &'static str) -> Result<Uuid> {
+        self.opt_uuid(field)?.ok_or(Error::MissingField(field))
+    }
+
+    pub fn uuid_array(&self, field: &'static str) -> Result<Vec<Uuid>> {
+        let array = match self.map.get(field) {
+            Some(Value::Array(array)) => array,
+            Some(_) => return Err(Error::InvalidField(field)),
+            None => return Err(Error::MissingField(field)),
+        };
+
+        array
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .and_then(|s| Uuid::from_str(s).ok())
+                    .ok_or(Error::InvalidField(field))
+            })
+            .collect()
+    }
+
+    pub fn array(&self, field: &'static str) -> Result<&'a Vec<Value>> {
+        match self.map.get(field) {
+            Some(Value::Array(array)) => Ok(array),
+            Some(_) => Err(Error::InvalidField(field)),
+            None => Err(Error::MissingField(field)),
+        }
+    }
+
+    pub fn value(&self, field: &'static str) -> Result<&'a Value> {
+        self.map.get(field).ok_or(Error::MissingField(field))
+    }
+
+    /// Optional field of any deserialisable type, where `null` gives `None`.
+    pub fn opt_deserialize<T: DeserializeOwned>(&self, field: &'static str) -> Result<Option<T>> {
+        match self.map.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => T::deserialize(value)
+                .map(Some)
+                .map_err(|_| Error::InvalidField(field)),
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self, field: &'static str) -> Result<T> {
+        self.opt_deserialize(field)?
+            .ok_or(Error::MissingField(field))
+    }
+
+    pub fn parse<T: FromStr<Err = Error>>(&self, field: &'static str) -> Result<T> {
+        self.str(field)?.parse()
+    }
+
+    /// Parse the fields shared by every resource. `description` and `tags`
+    /// are only required when `full_core` is set, as v1.0 nodes and devices
+    /// lack them.
+    pub fn core(&self, full_core: bool) -> Result<ResourceCore> {
+        let id = self.uuid("id")?;
+        let version =
+            TaiTime::from_str(self.str("version")?).map_err(|_| Error::InvalidField("version"))?;
+        let label = self.str("label")?.to_owned();
+
+        let description = match self.opt_str("description")? {
+            Some(description) => description.to_owned(),
+            None if full_core => return Err(Error::MissingField("description")),
+            None => String::new(),
+        };
+
+        let tags = match self.map.get("tags") {
+            None | Some(Value::Null) if full_core => return Err(Error::MissingField("tags")),
+            None | Some(Value::Null) => BTreeMap::new(),
+            Some(tags) => parse_tags(tags)?,
+        };
+
+        Ok(ResourceCore {
+            id,
+            version,
+            label,
+            description,
+            tags,
+        })
+    }
+}
+
+fn parse_tags(value: &Value) -> Result<BTreeMap<String, Vec<String>>> {
+    let map = value.as_object().ok_or(Error::InvalidField("tags"))?;
+
+    map.iter()
+        .map(|(key, values)| {
+            let values = values
+                .as_array()
+                .ok_or(Error::InvalidField("tags"))?
+                .iter()
+                .map(|v| v.as_str().map(ToOwned::to_owned))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::InvalidField("tags"))?;
+
+            Ok((key.clone(), values))
+        })
+        .collect()
+}
+
+/// JSON for the fields shared by every v1.1+ resource.
+pub(crate) fn core_json(core: &ResourceCore) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("id".to_owned(), Value::from(core.id.to_string()));
+    map.insert("version".to_owned(), Value::from(core.version.to_string()));
+    map.insert("label".to_owned(), Value::from(core.label.clone()));
+    map.insert(
+        "description".to_owned(),
+        Value::from(core.description.clone()),
+    );
+    map.insert(
+        "tags".to_owned(),
+        serde_json::to_value(&core.tags).expect("Tags are valid JSON"),
+    );
+    map
+}
+
+/// Convert JSON laid out as in the schema into the generated type. A
+/// mismatch is a bug in the caller, so panics.
+pub(crate) fn to_schema<T: DeserializeOwned>(map: Map<String, Value>) -> T {
+    serde_json::from_value(Value::Object(map)).expect("JSON does not match schema")
+}
+
+/// Check the version is one of the IS-04 versions, and return whether it
+/// uses the full v1.1+ resource core.
+pub(crate) fn check_version(api: &APIVersion) -> Result<bool> {
+    if !versions::ALL.contains(api) {
+        return Err(Error::UnsupportedVersion(*api));
+    }
+    Ok(*api >= V1_1)
+}
+
+/// Build a resource from any serialisable schema type by going through JSON.
+pub(crate) fn from_schema<T, R, F>(api: &APIVersion, resource: &T, from_json: F) -> Result<R>
+where
+    T: Serialize,
+    F: FnOnce(&APIVersion, &Value) -> Result<R>,
+{
+    let value = serde_json::to_value(resource)?;
+    from_json(api, &value)
+}
+
+macro_rules! impl_try_from_schema {
+    ($($resource:ident),*) => {
+        $(
+            impl_try_from_schema!(@version $resource, v1_0_x, V1_0);
+            impl_try_from_schema!(@version $resource, v1_1_x, V1_1);
+            impl_try_from_schema!(@version $resource, v1_2_x, V1_2);
+            impl_try_from_schema!(@version $resource, v1_3_x, V1_3);
+        )*
+    };
+    (@version $resource:ident, $module:ident, $api:ident) => {
+        impl TryFrom<&is_04::$module::$resource> for $resource {
+            type Error = Error;
+
+            fn try_from(resource: &is_04::$module::$resource) -> Result<Self> {
+                from_schema(&$api, resource, $resource::from_json)
+            }
+        }
+    };
+}
+
+impl_try_from_schema!(Node, Device, Source, Flow, Sender, Receiver);
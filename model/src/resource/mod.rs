@@ -1,36 +1,141 @@
-use std::{collections::BTreeMap, fmt};
+use std::{collections::BTreeMap, fmt, str::FromStr, time::Duration};
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
-pub use device::{Device, DeviceBuilder, DeviceJson, DeviceType};
-pub use flow::{Flow, FlowBuilder, FlowJson};
-pub use node::{Node, NodeBuilder, NodeJson, NodeService};
+pub use caps::{
+    cap, CapsMismatch, ConstraintSet, ConstraintValue, ParameterConstraint, Rational, ReceiverCaps,
+};
+pub use device::{Device, DeviceBuilder, DeviceControl, DeviceJson, DeviceType};
+pub use flow::{AudioAttributes, Flow, FlowBuilder, FlowJson, VideoAttributes};
+pub use node::{Node, NodeBuilder, NodeEndpoint, NodeJson, NodeService};
 pub use receiver::{Receiver, ReceiverBuilder, ReceiverJson};
 pub use sender::{Sender, SenderBuilder, SenderJson};
-pub use source::{Source, SourceBuilder, SourceJson};
+pub use source::{AudioChannel, Source, SourceBuilder, SourceJson};
+pub use tags::{
+    AssetInfo, GroupHint, GroupScope, ASSET_FUNCTION_TAG, ASSET_INSTANCE_ID_TAG,
+    ASSET_MANUFACTURER_TAG, ASSET_PRODUCT_TAG, GROUP_HINT_TAG,
+};
 
-use crate::tai::TaiTime;
+use crate::{
+    error::Error,
+    tai::TaiTime,
+    version::{
+        is_04::{V1_1, V1_3},
+        APIVersion,
+    },
+};
 
+/// Check `s` is a URN of the form `urn:x-<namespace>:<kind>:<name>`, as used
+/// for formats, transports and device types.
+pub(crate) fn is_urn(s: &str, kind: &str) -> bool {
+    let parts = s
+        .strip_prefix("urn:x-")
+        .and_then(|s| s.split_once(':'))
+        .and_then(|(namespace, s)| Some((namespace, s.split_once(':')?)));
+
+    let (namespace, (urn_kind, name)) = match parts {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    let namespace_valid = !namespace.is_empty()
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    let name_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._:/".contains(c));
+
+    namespace_valid && urn_kind == kind && name_valid
+}
+
+/// (De)serialise a URN enum through its `Display` and `FromStr` impls.
+macro_rules! impl_urn_serde {
+    ($type:ty) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(de::Error::custom)
+            }
+        }
+    };
+}
+
+mod caps;
 mod device;
 mod flow;
+mod json;
 mod node;
 mod receiver;
 mod sender;
 mod source;
+mod tags;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Format {
     Video,
     Audio,
     Data,
+    /// Multiplexed formats, IS-04 v1.1 onwards
+    Mux,
+    /// Any other format URN, e.g. `urn:x-nmos:format:...` from a later API
+    Other(String),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Transport {
     Rtp,
     RtpUnicast,
     RtpMulticast,
     Dash,
+    /// IS-07 events over WebSocket, IS-04 v1.3 onwards
+    Websocket,
+    /// IS-07 events over MQTT, IS-04 v1.3 onwards
+    Mqtt,
+    /// SRT, as registered by Matrox
+    Srt,
+    /// Any other transport URN, e.g. `urn:x-manufacturer:transport:...`
+    Custom(String),
+}
+
+impl Transport {
+    /// Whether the transport can be represented in the given IS-04 version.
+    /// v1.0 only defines the RTP and DASH transports.
+    #[must_use]
+    pub fn supports(&self, api: &APIVersion) -> bool {
+        match self {
+            Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast | Transport::Dash => {
+                true
+            }
+            Transport::Websocket | Transport::Mqtt => *api >= V1_3,
+            Transport::Srt | Transport::Custom(_) => *api >= V1_1,
+        }
+    }
+
+    /// Fail with `Error::UnsupportedTransport` unless `supports` holds.
+    pub fn check(&self, api: &APIVersion) -> Result<(), Error> {
+        if self.supports(api) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedTransport {
+                transport: self.to_string(),
+                api: *api,
+            })
+        }
+    }
 }
 
 impl fmt::Display for Format {
@@ -39,10 +144,29 @@ impl fmt::Display for Format {
             Format::Video => write!(f, "urn:x-nmos:format:video"),
             Format::Audio => write!(f, "urn:x-nmos:format:audio"),
             Format::Data => write!(f, "urn:x-nmos:format:data"),
+            Format::Mux => write!(f, "urn:x-nmos:format:mux"),
+            Format::Other(urn) => f.write_str(urn),
         }
     }
 }
 
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "urn:x-nmos:format:video" => Ok(Format::Video),
+            "urn:x-nmos:format:audio" => Ok(Format::Audio),
+            "urn:x-nmos:format:data" => Ok(Format::Data),
+            "urn:x-nmos:format:mux" => Ok(Format::Mux),
+            _ if is_urn(s, "format") => Ok(Format::Other(s.to_owned())),
+            _ => Err(Error::InvalidFormat(s.to_owned())),
+        }
+    }
+}
+
+impl_urn_serde!(Format);
+
 impl fmt::Display for Transport {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -50,13 +174,38 @@ impl fmt::Display for Transport {
             Transport::RtpUnicast => write!(f, "urn:x-nmos:transport:rtp.ucast"),
             Transport::RtpMulticast => write!(f, "urn:x-nmos:transport:rtp.mcast"),
             Transport::Dash => write!(f, "urn:x-nmos:transport:dash"),
+            Transport::Websocket => write!(f, "urn:x-nmos:transport:websocket"),
+            Transport::Mqtt => write!(f, "urn:x-nmos:transport:mqtt"),
+            Transport::Srt => write!(f, "urn:x-matrox:transport:srt"),
+            Transport::Custom(urn) => f.write_str(urn),
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "urn:x-nmos:transport:rtp" => Ok(Transport::Rtp),
+            "urn:x-nmos:transport:rtp.ucast" => Ok(Transport::RtpUnicast),
+            "urn:x-nmos:transport:rtp.mcast" => Ok(Transport::RtpMulticast),
+            "urn:x-nmos:transport:dash" => Ok(Transport::Dash),
+            "urn:x-nmos:transport:websocket" => Ok(Transport::Websocket),
+            "urn:x-nmos:transport:mqtt" => Ok(Transport::Mqtt),
+            "urn:x-matrox:transport:srt" => Ok(Transport::Srt),
+            _ if is_urn(s, "transport") => Ok(Transport::Custom(s.to_owned())),
+            _ => Err(Error::InvalidTransport(s.to_owned())),
         }
     }
 }
 
+impl_urn_serde!(Transport);
+
 #[derive(Debug)]
 #[must_use]
 pub struct ResourceCoreBuilder {
+    pub id: Option<Uuid>,
     pub label: String,
     pub description: Option<String>,
     pub tags: BTreeMap<String, Vec<String>>,
@@ -65,12 +214,26 @@ pub struct ResourceCoreBuilder {
 impl ResourceCoreBuilder {
     pub fn new<S: Into<String>>(label: S) -> Self {
         Self {
+            id: None,
             label: label.into(),
             description: None,
             tags: BTreeMap::new(),
         }
     }
 
+    /// Use an explicit id rather than a random one.
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Derive a UUID v5 id from a per-node seed and a path which is stable
+    /// across restarts, e.g. `"device/0/sender/video"`.
+    pub fn seeded_id(mut self, seed: &Uuid, path: &str) -> Self {
+        self.id = Some(Uuid::new_v5(seed, path.as_bytes()));
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.description = Some(description.into());
         self
@@ -87,10 +250,16 @@ impl ResourceCoreBuilder {
         self
     }
 
+    /// Append a single value to a tag, keeping any existing values.
+    pub fn add_tag<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.tags.entry(key.into()).or_default().push(value.into());
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> ResourceCore {
         ResourceCore {
-            id: Uuid::new_v4(),
+            id: self.id.unwrap_or_else(Uuid::new_v4),
             version: TaiTime::now(),
             label: self.label,
             description: self.description.unwrap_or_default(),
@@ -112,8 +281,162 @@ impl ResourceCore {
     pub fn builder<S: Into<String>>(label: S) -> ResourceCoreBuilder {
         ResourceCoreBuilder::new(label)
     }
+
+    /// Mark the resource as modified by assigning a strictly greater version.
+    pub fn bump_version(&mut self) {
+        let now = TaiTime::now();
+
+        // The current version may be ahead of the clock if it was restored or
+        // assigned by hand
+        self.version = if now > self.version {
+            now
+        } else {
+            self.version + Duration::from_nanos(1)
+        };
+    }
+
+    pub fn set_label<S: Into<String>>(&mut self, label: S) {
+        self.label = label.into();
+        self.bump_version();
+    }
+
+    pub fn set_description<S: Into<String>>(&mut self, description: S) {
+        self.description = description.into();
+        self.bump_version();
+    }
+
+    pub fn set_tag<S, V>(&mut self, key: S, values: V)
+    where
+        S: Into<String>,
+        V: IntoIterator<Item = S>,
+    {
+        let values: Vec<String> = values.into_iter().map(Into::into).collect();
+
+        self.tags.insert(key.into(), values);
+        self.bump_version();
+    }
+
+    pub fn remove_tag(&mut self, key: &str) -> Option<Vec<String>> {
+        let values = self.tags.remove(key);
+        if values.is_some() {
+            self.bump_version();
+        }
+        values
+    }
+
+    pub fn set_tags(&mut self, tags: BTreeMap<String, Vec<String>>) {
+        self.tags = tags;
+        self.bump_version();
+    }
+}
+
+/// Common behaviour of all IS-04 resources.
+pub trait Resource {
+    fn core(&self) -> &ResourceCore;
+
+    fn core_mut(&mut self) -> &mut ResourceCore;
+
+    fn id(&self) -> Uuid {
+        self.core().id
+    }
+
+    fn version(&self) -> TaiTime {
+        self.core().version
+    }
+
+    /// Mark the resource as modified without changing any fields.
+    fn touch(&mut self) {
+        self.core_mut().bump_version();
+    }
+}
+
+macro_rules! impl_resource {
+    ($($resource:ty),*) => {
+        $(
+            impl Resource for $resource {
+                fn core(&self) -> &ResourceCore {
+                    &self.core
+                }
+
+                fn core_mut(&mut self) -> &mut ResourceCore {
+                    &mut self.core
+                }
+            }
+        )*
+    };
 }
 
+impl_resource!(Node, Device, Source, Flow, Sender, Receiver);
+
+/// Setters shared by every resource builder, so generic code can decorate
+/// any resource before it is built.
+pub trait ResourceBuilder: Sized {
+    /// Replace the builder's core with the result of `f`.
+    fn map_core<F>(self, f: F) -> Self
+    where
+        F: FnOnce(ResourceCoreBuilder) -> ResourceCoreBuilder;
+
+    fn id(self, id: Uuid) -> Self {
+        self.map_core(|core| core.id(id))
+    }
+
+    fn seeded_id(self, seed: &Uuid, path: &str) -> Self {
+        self.map_core(|core| core.seeded_id(seed, path))
+    }
+
+    fn description<S: Into<String>>(self, description: S) -> Self {
+        self.map_core(|core| core.description(description))
+    }
+
+    fn tag<S, V>(self, key: S, values: V) -> Self
+    where
+        S: Into<String>,
+        V: IntoIterator<Item = S>,
+    {
+        self.map_core(|core| core.tag(key, values))
+    }
+
+    /// Add the resource to a BCP-002-01 group with the given role, e.g.
+    /// `group_hint("Input 1", "Video")`.
+    fn group_hint<G: Into<String>, R: Into<String>>(self, group: G, role: R) -> Self {
+        self.add_group_hint(&GroupHint::new(group, role))
+    }
+
+    fn add_group_hint(self, hint: &GroupHint) -> Self {
+        self.map_core(|core| core.add_group_hint(hint))
+    }
+
+    /// Tag a node or device with BCP-002-02 asset information.
+    fn asset(self, asset: &AssetInfo) -> Self {
+        self.map_core(|core| core.asset(asset))
+    }
+}
+
+macro_rules! impl_resource_builder {
+    ($($builder:ty),*) => {
+        $(
+            impl ResourceBuilder for $builder {
+                fn map_core<F>(mut self, f: F) -> Self
+                where
+                    F: FnOnce(ResourceCoreBuilder) -> ResourceCoreBuilder,
+                {
+                    self.core = f(self.core);
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_resource_builder!(
+    NodeBuilder,
+    DeviceBuilder,
+    SourceBuilder,
+    FlowBuilder,
+    SenderBuilder,
+    ReceiverBuilder
+);
+
 #[derive(Debug, Default)]
 pub struct ResourceBundle {
     pub(crate) nodes: Vec<Node>,
@@ -154,3 +477,246 @@ impl ResourceBundle {
         self.receivers.push(receiver);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::version::is_04::{V1_0, V1_2};
+
+    /// Decorate any resource, as generic code would.
+    fn decorate<B: ResourceBuilder>(builder: B) -> B {
+        builder
+            .description("Description")
+            .tag("location", vec!["Studio 1"])
+            .group_hint("Input 1", "Video")
+    }
+
+    fn node() -> Node {
+        Node::builder("Node", "http://127.0.0.1:3000/").build()
+    }
+
+    #[test]
+    fn shared_setters() {
+        let node = decorate(Node::builder("Node", "http://127.0.0.1:3000/")).build();
+        let device = decorate(Device::builder("Device", &node, DeviceType::Generic)).build();
+        let source = decorate(Source::builder("Source", &device, Format::Video)).build();
+
+        for core in [&node.core, &device.core, &source.core] {
+            assert_eq!(core.description, "Description");
+            assert_eq!(core.tags["location"], vec!["Studio 1"]);
+            assert_eq!(core.tags[GROUP_HINT_TAG], vec!["Input 1:Video"]);
+        }
+    }
+
+    #[test]
+    fn ids() {
+        let id = Uuid::new_v4();
+        let node = Node::builder("Node", "http://127.0.0.1:3000/")
+            .id(id)
+            .build();
+        assert_eq!(node.core.id, id);
+
+        let seed = Uuid::new_v4();
+        let device = |path| {
+            Device::builder("Device", &node, DeviceType::Generic)
+                .seeded_id(&seed, path)
+                .build()
+        };
+        assert_eq!(device("device/0").core.id, device("device/0").core.id);
+        assert_ne!(device("device/0").core.id, device("device/1").core.id);
+    }
+
+    #[test]
+    fn parents() {
+        let node = node();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let first = Source::builder("First", &device, Format::Video).build();
+        let second = Source::builder("Second", &device, Format::Video).build();
+
+        let source = Source::builder("Source", &device, Format::Video)
+            .parent(&first)
+            .parent(&second)
+            .build();
+        assert_eq!(source.parents, vec![first.core.id, second.core.id]);
+
+        let json = serde_json::to_value(source.to_json(&V1_0)).unwrap();
+        assert_eq!(
+            json["parents"],
+            json!([first.core.id.to_string(), second.core.id.to_string()])
+        );
+
+        let parent = Flow::builder("Parent", &first).build();
+        let flow = Flow::builder("Flow", &source).parent(&parent).build();
+        assert_eq!(flow.parents, vec![parent.core.id]);
+    }
+
+    #[test]
+    fn transport_supports() {
+        let custom = Transport::from_str("urn:x-manufacturer:transport:udp").unwrap();
+        let cases = [
+            (Transport::Rtp, V1_0),
+            (Transport::RtpUnicast, V1_0),
+            (Transport::RtpMulticast, V1_0),
+            (Transport::Dash, V1_0),
+            (Transport::Srt, V1_1),
+            (custom, V1_1),
+            (Transport::Websocket, V1_3),
+            (Transport::Mqtt, V1_3),
+        ];
+
+        for (transport, first) in cases {
+            for api in crate::version::is_04::ALL {
+                assert_eq!(
+                    transport.supports(api),
+                    *api >= first,
+                    "{} in {}",
+                    transport,
+                    api
+                );
+                assert_eq!(transport.check(api).is_ok(), *api >= first);
+            }
+        }
+
+        assert!(matches!(
+            Transport::Mqtt.check(&V1_2),
+            Err(Error::UnsupportedTransport { api, .. }) if api == V1_2
+        ));
+    }
+
+    #[test]
+    fn device_json() {
+        let node = node();
+        let device = Device::builder("Device", &node, DeviceType::Generic)
+            .description("Description")
+            .tag("location", vec!["Studio 1"])
+            .control(DeviceControl {
+                href: String::from("http://127.0.0.1:3000/x-nmos/connection/v1.1/"),
+                type_: String::from("urn:x-nmos:control:sr-ctrl/v1.1"),
+            })
+            .build();
+
+        // v1.0 has no description, tags or controls
+        let json = serde_json::to_value(device.to_json(&V1_0)).unwrap();
+        assert!(json.get("description").is_none());
+        assert!(json.get("tags").is_none());
+
+        for api in [V1_1, V1_2, V1_3] {
+            let json = serde_json::to_value(device.to_json(&api)).unwrap();
+            assert_eq!(json["description"], "Description", "{}", api);
+            assert_eq!(json["tags"], json!({"location": ["Studio 1"]}), "{}", api);
+            assert_eq!(
+                json["controls"][0]["type"], "urn:x-nmos:control:sr-ctrl/v1.1",
+                "{}",
+                api
+            );
+
+            let parsed = Device::from_json(&api, &json).unwrap();
+            assert_eq!(parsed.core.description, device.core.description);
+            assert_eq!(parsed.core.tags, device.core.tags);
+            assert_eq!(parsed.controls, device.controls);
+        }
+    }
+
+    #[test]
+    fn node_json() {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/")
+            .api_version(V1_2)
+            .api_version(V1_3)
+            .api_endpoint(NodeEndpoint {
+                host: String::from("127.0.0.1"),
+                port: 3000,
+                protocol: String::from("http"),
+            })
+            .build();
+
+        // v1.0 has no API listing
+        let json = serde_json::to_value(node.to_json(&V1_0)).unwrap();
+        assert!(json.get("api").is_none());
+        assert!(Node::from_json(&V1_0, &json)
+            .unwrap()
+            .api_versions
+            .is_empty());
+
+        for api in [V1_1, V1_2, V1_3] {
+            let json = serde_json::to_value(node.to_json(&api)).unwrap();
+            assert_eq!(json["api"]["versions"], json!(["v1.2", "v1.3"]), "{}", api);
+            assert_eq!(json["clocks"], json!([]), "{}", api);
+            assert_eq!(json.get("interfaces").is_some(), api >= V1_2, "{}", api);
+
+            let parsed = Node::from_json(&api, &json).unwrap();
+            assert_eq!(parsed.api_versions, node.api_versions);
+            assert_eq!(parsed.api_endpoints, node.api_endpoints);
+        }
+    }
+
+    #[test]
+    fn source_json() {
+        let node = node();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let video = Source::builder("Video", &device, Format::Video).build();
+        let audio = Source::builder("Audio", &device, Format::Audio)
+            .channel(AudioChannel {
+                symbol: Some(String::from("L")),
+                ..AudioChannel::new("Left")
+            })
+            .channel(AudioChannel::new("Right"))
+            .build();
+
+        for api in [V1_1, V1_2, V1_3] {
+            let json = serde_json::to_value(video.to_json(&api)).unwrap();
+            assert!(json["clock_name"].is_null(), "{}", api);
+            assert!(json.get("channels").is_none(), "{}", api);
+
+            let json = serde_json::to_value(audio.to_json(&api)).unwrap();
+            assert_eq!(
+                json["channels"],
+                json!([{"label": "Left", "symbol": "L"}, {"label": "Right"}]),
+                "{}",
+                api
+            );
+            assert_eq!(
+                Source::from_json(&api, &json).unwrap().channels,
+                audio.channels
+            );
+        }
+    }
+
+    #[test]
+    fn flow_json() {
+        let node = node();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        let flow = Flow::builder("Flow", &source)
+            .grain_rate(Rational::new(25, 1))
+            .video(VideoAttributes {
+                color_sampling: Some(String::from("YCbCr-4:2:0")),
+                component_depth: Some(8),
+                ..VideoAttributes::new(1920, 1080)
+            })
+            .build();
+        assert_eq!(flow.device_id, device.core.id);
+
+        // v1.0 has no device id or media attributes
+        let json = serde_json::to_value(flow.to_json(&V1_0)).unwrap();
+        assert!(json.get("device_id").is_none());
+        assert!(Flow::from_json(&V1_0, &json).unwrap().device_id.is_nil());
+
+        for api in [V1_1, V1_2, V1_3] {
+            let json = serde_json::to_value(flow.to_json(&api)).unwrap();
+            assert_eq!(json["media_type"], "video/raw", "{}", api);
+            assert_eq!(
+                json["components"][1],
+                json!({"name": "Cb", "width": 960, "height": 540, "bit_depth": 8}),
+                "{}",
+                api
+            );
+
+            let parsed = Flow::from_json(&api, &json).unwrap();
+            assert_eq!(parsed.device_id, flow.device_id);
+            assert_eq!(parsed.grain_rate, flow.grain_rate);
+            assert_eq!(parsed.video, flow.video);
+        }
+    }
+}
@@ -1,11 +1,21 @@
 use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::version::{is_04::V1_0, APIVersion};
+use crate::{
+    error::{Error, Result},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
+};
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonObject},
+    ResourceCore, ResourceCoreBuilder,
+};
 
 #[derive(Debug)]
 pub struct NodeService {
@@ -13,11 +23,22 @@ pub struct NodeService {
     pub type_: String,
 }
 
+/// Where the node API is served, listed from IS-04 v1.1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// `http` or `https`
+    pub protocol: String,
+}
+
 #[must_use]
 pub struct NodeBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     href: String,
     hostname: Option<String>,
+    api_versions: Vec<APIVersion>,
+    api_endpoints: Vec<NodeEndpoint>,
     services: Vec<NodeService>,
 }
 
@@ -27,10 +48,24 @@ impl NodeBuilder {
             core: ResourceCoreBuilder::new(label),
             href: href.into(),
             hostname: None,
+            api_versions: Vec::new(),
+            api_endpoints: Vec::new(),
             services: Vec::new(),
         }
     }
 
+    /// Add a version of the node API served, listed from IS-04 v1.1.
+    pub fn api_version(mut self, api: APIVersion) -> Self {
+        self.api_versions.push(api);
+        self
+    }
+
+    /// Add an endpoint the node API is served at, listed from IS-04 v1.1.
+    pub fn api_endpoint(mut self, endpoint: NodeEndpoint) -> Self {
+        self.api_endpoints.push(endpoint);
+        self
+    }
+
     pub fn with_service(mut self, service: NodeService) -> Self {
         self.services.push(service);
         self
@@ -42,6 +77,8 @@ impl NodeBuilder {
             core: self.core.build(),
             href: self.href,
             hostname: self.hostname,
+            api_versions: self.api_versions,
+            api_endpoints: self.api_endpoints,
             services: self.services,
         }
     }
@@ -52,6 +89,8 @@ pub struct Node {
     pub core: ResourceCore,
     pub href: String,
     pub hostname: Option<String>,
+    pub api_versions: Vec<APIVersion>,
+    pub api_endpoints: Vec<NodeEndpoint>,
     pub services: Vec<NodeService>,
 }
 
@@ -60,6 +99,53 @@ impl Node {
         NodeBuilder::new(label, href)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        let full_core = json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        let services = object
+            .array("services")?
+            .iter()
+            .map(|service| {
+                let service =
+                    JsonObject::new(service).map_err(|_| Error::InvalidField("services"))?;
+
+                Ok(NodeService {
+                    href: service.str("href")?.to_owned(),
+                    type_: service.str("type")?.to_owned(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        // The API versions and endpoints are only present from v1.1
+        let (api_versions, api_endpoints) = if full_core {
+            let api_object = object.object("api")?;
+            let versions = api_object
+                .array("versions")?
+                .iter()
+                .map(|version| {
+                    version
+                        .as_str()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or(Error::InvalidField("versions"))
+                })
+                .collect::<Result<_>>()?;
+            (versions, api_object.deserialize("endpoints")?)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        Ok(Node {
+            core: object.core(full_core)?,
+            href: object.str("href")?.to_owned(),
+            hostname: object.opt_str("hostname")?.map(ToOwned::to_owned),
+            api_versions,
+            api_endpoints,
+            services,
+        })
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> NodeJson {
         match *api {
@@ -83,6 +169,51 @@ impl Node {
                     services,
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let versions = self
+                    .api_versions
+                    .iter()
+                    .map(|v| Value::from(v.to_string()))
+                    .collect();
+                let mut api_json = Map::new();
+                api_json.insert("versions".to_owned(), Value::Array(versions));
+                api_json.insert(
+                    "endpoints".to_owned(),
+                    serde_json::to_value(&self.api_endpoints).expect("Endpoints are valid JSON"),
+                );
+
+                let services = self
+                    .services
+                    .iter()
+                    .map(|service| {
+                        let mut map = Map::new();
+                        map.insert("href".to_owned(), Value::from(service.href.clone()));
+                        map.insert("type".to_owned(), Value::from(service.type_.clone()));
+                        Value::Object(map)
+                    })
+                    .collect();
+
+                let mut json = json::core_json(&self.core);
+                json.insert("href".to_owned(), Value::from(self.href.clone()));
+                if let Some(hostname) = &self.hostname {
+                    json.insert("hostname".to_owned(), Value::from(hostname.clone()));
+                }
+                json.insert("api".to_owned(), Value::Object(api_json));
+                json.insert("caps".to_owned(), Value::Object(Map::new()));
+                json.insert("services".to_owned(), Value::Array(services));
+                json.insert("clocks".to_owned(), Value::Array(Vec::new()));
+
+                // Network interfaces are required from v1.2
+                if *api >= V1_2 {
+                    json.insert("interfaces".to_owned(), Value::Array(Vec::new()));
+                }
+
+                match *api {
+                    V1_1 => NodeJson::V1_1(json::to_schema(json)),
+                    V1_2 => NodeJson::V1_2(json::to_schema(json)),
+                    _ => NodeJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -92,4 +223,7 @@ impl Node {
 #[serde(untagged)]
 pub enum NodeJson {
     V1_0(is_04::v1_0_x::Node),
+    V1_1(is_04::v1_1_x::Node),
+    V1_2(is_04::v1_2_x::Node),
+    V1_3(is_04::v1_3_x::Node),
 }
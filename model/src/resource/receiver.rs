@@ -1,23 +1,32 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, result::Result as StdResult};
 
 use nmos_schema::is_04;
 use serde::Serialize;
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 use crate::{
-    resource::{Device, Format, Transport},
-    version::{is_04::V1_0, APIVersion},
+    error::Result,
+    resource::{CapsMismatch, ConstraintSet, Device, Flow, Format, ReceiverCaps, Transport},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonObject},
+    ResourceBuilder, ResourceCore, ResourceCoreBuilder,
+};
 
 #[must_use]
 pub struct ReceiverBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     format: Format,
     device_id: Uuid,
     transport: Transport,
     subscription: Option<Uuid>,
+    caps: ReceiverCaps,
 }
 
 impl ReceiverBuilder {
@@ -33,23 +42,49 @@ impl ReceiverBuilder {
             device_id: device.core.id,
             transport,
             subscription: None,
+            caps: ReceiverCaps::default(),
         }
     }
 
-    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
-        self.core = self.core.description(description);
-        self
+    /// See [`ResourceBuilder::description`].
+    pub fn description<S: Into<String>>(self, description: S) -> Self {
+        ResourceBuilder::description(self, description)
     }
 
-    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    /// See [`ResourceBuilder::tag`].
+    pub fn tag<S, V>(self, key: S, values: V) -> Self
     where
         S: Into<String>,
         V: IntoIterator<Item = S>,
     {
-        self.core = self.core.tag(key, values);
+        ResourceBuilder::tag(self, key, values)
+    }
+
+    /// Accept a media type, e.g. `video/raw`. Any is accepted if none are
+    /// given.
+    pub fn media_type<S: Into<String>>(mut self, media_type: S) -> Self {
+        self.caps.media_types.push(media_type.into());
+        self
+    }
+
+    /// Add a BCP-004-01 constraint set, advertised from IS-04 v1.3.
+    pub fn constraint_set(mut self, constraint_set: ConstraintSet) -> Self {
+        self.caps.constraint_sets.push(constraint_set);
+        self
+    }
+
+    pub fn caps(mut self, caps: ReceiverCaps) -> Self {
+        self.caps = caps;
         self
     }
 
+    /// Build, checking the transport can be represented in the given API
+    /// version.
+    pub fn try_build(self, api: &APIVersion) -> Result<Receiver> {
+        self.transport.check(api)?;
+        Ok(self.build())
+    }
+
     #[must_use]
     pub fn build(self) -> Receiver {
         Receiver {
@@ -58,6 +93,8 @@ impl ReceiverBuilder {
             device_id: self.device_id,
             transport: self.transport,
             subscription: self.subscription,
+            subscription_active: self.subscription.is_some(),
+            caps: self.caps,
         }
     }
 }
@@ -69,6 +106,9 @@ pub struct Receiver {
     pub device_id: Uuid,
     pub transport: Transport,
     pub subscription: Option<Uuid>,
+    /// Whether the receiver is actively receiving from the subscribed sender
+    pub subscription_active: bool,
+    pub caps: ReceiverCaps,
 }
 
 impl Receiver {
@@ -81,6 +121,58 @@ impl Receiver {
         ReceiverBuilder::new(label, device, format, transport)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        let transport: Transport = object.parse("transport")?;
+        transport.check(api)?;
+
+        // The active flag is only present from v1.2
+        let subscription = object.object("subscription")?;
+        let sender_id = subscription.opt_uuid("sender_id")?;
+        let active = subscription.opt_deserialize("active")?;
+
+        Ok(Receiver {
+            core: object.core(true)?,
+            format: object.parse("format")?,
+            device_id: object.uuid("device_id")?,
+            transport,
+            subscription: sender_id,
+            subscription_active: active.unwrap_or_else(|| sender_id.is_some()),
+            caps: ReceiverCaps::from_json(object.value("caps")?)?,
+        })
+    }
+
+    /// Check a flow is of the right format and satisfies the caps.
+    pub fn check_flow(&self, flow: &Flow) -> StdResult<(), CapsMismatch> {
+        if flow.format != self.format {
+            return Err(CapsMismatch::Format {
+                expected: self.format.clone(),
+                found: flow.format.clone(),
+            });
+        }
+
+        self.caps.check(flow)
+    }
+
+    /// Change the subscribed sender, bumping the version if it differs.
+    /// The subscription is active whenever a sender is set.
+    pub fn set_subscription(&mut self, sender_id: Option<Uuid>) {
+        self.subscribe(sender_id, sender_id.is_some());
+    }
+
+    /// Change the subscribed sender and whether it is active, as done by an
+    /// IS-05 activation, bumping the version if either differs.
+    pub fn subscribe(&mut self, sender_id: Option<Uuid>, active: bool) {
+        if self.subscription != sender_id || self.subscription_active != active {
+            self.subscription = sender_id;
+            self.subscription_active = active;
+            self.core.bump_version();
+        }
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> ReceiverJson {
         match *api {
@@ -105,13 +197,49 @@ impl Receiver {
                     label: self.core.label.clone(),
                     description: self.core.description.clone(),
                     format: self.format.to_string(),
-                    caps: BTreeMap::default(),
+                    caps: self.caps.to_json(api),
                     tags,
                     device_id: self.device_id.to_string(),
                     transport: self.transport.to_string(),
                     subscription,
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let mut subscription = Map::new();
+                subscription.insert(
+                    "sender_id".to_owned(),
+                    self.subscription
+                        .map_or(Value::Null, |id| Value::from(id.to_string())),
+                );
+
+                let mut json = json::core_json(&self.core);
+                json.insert(
+                    "device_id".to_owned(),
+                    Value::from(self.device_id.to_string()),
+                );
+                json.insert(
+                    "transport".to_owned(),
+                    Value::from(self.transport.to_string()),
+                );
+                json.insert("format".to_owned(), Value::from(self.format.to_string()));
+                json.insert(
+                    "caps".to_owned(),
+                    Value::Object(self.caps.to_json(api).into_iter().collect()),
+                );
+
+                // Interface bindings and an explicit active flag are required from v1.2
+                if *api >= V1_2 {
+                    json.insert("interface_bindings".to_owned(), Value::Array(Vec::new()));
+                    subscription.insert("active".to_owned(), Value::from(self.subscription_active));
+                }
+                json.insert("subscription".to_owned(), Value::Object(subscription));
+
+                match *api {
+                    V1_1 => ReceiverJson::V1_1(json::to_schema(json)),
+                    V1_2 => ReceiverJson::V1_2(json::to_schema(json)),
+                    _ => ReceiverJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -121,4 +249,7 @@ impl Receiver {
 #[serde(untagged)]
 pub enum ReceiverJson {
     V1_0(is_04::v1_0_x::Receiver),
+    V1_1(is_04::v1_1_x::Receiver),
+    V1_2(is_04::v1_2_x::Receiver),
+    V1_3(is_04::v1_3_x::Receiver),
 }
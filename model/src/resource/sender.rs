@@ -1,19 +1,34 @@
 use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 use crate::{
+    error::{Error, Result},
     resource::{Device, Flow, Transport},
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonObject},
+    ResourceBuilder, ResourceCore, ResourceCoreBuilder,
+};
+
+/// Sender subscription as represented from IS-04 v1.2.
+#[derive(Deserialize)]
+struct SubscriptionJson {
+    receiver_id: Option<Uuid>,
+    active: bool,
+}
 
 #[must_use]
 pub struct SenderBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     flow_id: Uuid,
     transport: Transport,
     device_id: Uuid,
@@ -36,26 +51,34 @@ impl SenderBuilder {
         }
     }
 
-    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
-        self.core = self.core.description(description);
-        self
+    /// See [`ResourceBuilder::description`].
+    pub fn description<S: Into<String>>(self, description: S) -> Self {
+        ResourceBuilder::description(self, description)
     }
 
-    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    /// See [`ResourceBuilder::tag`].
+    pub fn tag<S, V>(self, key: S, values: V) -> Self
     where
         S: Into<String>,
         V: IntoIterator<Item = S>,
     {
-        self.core = self.core.tag(key, values);
-        self
+        ResourceBuilder::tag(self, key, values)
     }
 
+    /// Href of a manifest provided by the application. Without one, the node
+    /// points RTP senders at SDP generated from the flow once it exists.
     pub fn manifest<S: Into<String>>(mut self, manifest: S) -> Self {
-        // TODO: Store manifest and generate href
         self.manifest_href = Some(manifest.into());
         self
     }
 
+    /// Build, checking the transport can be represented in the given API
+    /// version.
+    pub fn try_build(self, api: &APIVersion) -> Result<Sender> {
+        self.transport.check(api)?;
+        Ok(self.build())
+    }
+
     #[must_use]
     pub fn build(self) -> Sender {
         Sender {
@@ -64,6 +87,8 @@ impl SenderBuilder {
             transport: self.transport,
             device_id: self.device_id,
             manifest_href: self.manifest_href.unwrap_or_default(),
+            subscription: None,
+            subscription_active: false,
         }
     }
 }
@@ -75,6 +100,10 @@ pub struct Sender {
     pub transport: Transport,
     pub device_id: Uuid,
     pub manifest_href: String,
+    /// Receiver the sender is sending to, if any, when unicast
+    pub subscription: Option<Uuid>,
+    /// Whether the sender is actively sending
+    pub subscription_active: bool,
 }
 
 impl Sender {
@@ -87,6 +116,47 @@ impl Sender {
         SenderBuilder::new(label, device, flow, transport)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        let full_core = json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        // v1.0 senders require a description but not tags
+        let core = object.core(full_core)?;
+        if !full_core && object.opt_str("description")?.is_none() {
+            return Err(Error::MissingField("description"));
+        }
+
+        let transport: Transport = object.parse("transport")?;
+        transport.check(api)?;
+
+        let subscription: Option<SubscriptionJson> = object.opt_deserialize("subscription")?;
+
+        Ok(Sender {
+            core,
+            // Senders without a flow, allowed since v1.1, are not modelled
+            flow_id: object.uuid("flow_id")?,
+            transport,
+            device_id: object.uuid("device_id")?,
+            manifest_href: object
+                .opt_str("manifest_href")?
+                .unwrap_or_default()
+                .to_owned(),
+            subscription: subscription.as_ref().and_then(|s| s.receiver_id),
+            subscription_active: subscription.map_or(false, |s| s.active),
+        })
+    }
+
+    /// Change the receiver being sent to and whether the sender is active, as
+    /// done by an IS-05 activation, bumping the version if either differs.
+    pub fn subscribe(&mut self, receiver_id: Option<Uuid>, active: bool) {
+        if self.subscription != receiver_id || self.subscription_active != active {
+            self.subscription = receiver_id;
+            self.subscription_active = active;
+            self.core.bump_version();
+        }
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> SenderJson {
         match *api {
@@ -117,6 +187,42 @@ impl Sender {
                     manifest_href: self.manifest_href.clone(),
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let mut json = json::core_json(&self.core);
+                json.insert("flow_id".to_owned(), Value::from(self.flow_id.to_string()));
+                json.insert(
+                    "transport".to_owned(),
+                    Value::from(self.transport.to_string()),
+                );
+                json.insert(
+                    "device_id".to_owned(),
+                    Value::from(self.device_id.to_string()),
+                );
+                json.insert(
+                    "manifest_href".to_owned(),
+                    Value::from(self.manifest_href.clone()),
+                );
+
+                // Interface bindings and the subscription are required from v1.2
+                if *api >= V1_2 {
+                    let mut subscription = Map::new();
+                    subscription.insert(
+                        "receiver_id".to_owned(),
+                        self.subscription
+                            .map_or(Value::Null, |id| Value::from(id.to_string())),
+                    );
+                    subscription.insert("active".to_owned(), Value::from(self.subscription_active));
+
+                    json.insert("interface_bindings".to_owned(), Value::Array(Vec::new()));
+                    json.insert("subscription".to_owned(), Value::Object(subscription));
+                }
+
+                match *api {
+                    V1_1 => SenderJson::V1_1(json::to_schema(json)),
+                    V1_2 => SenderJson::V1_2(json::to_schema(json)),
+                    _ => SenderJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -126,4 +232,7 @@ impl Sender {
 #[serde(untagged)]
 pub enum SenderJson {
     V1_0(is_04::v1_0_x::Sender),
+    V1_1(is_04::v1_1_x::Sender),
+    V1_2(is_04::v1_2_x::Sender),
+    V1_3(is_04::v1_3_x::Sender),
 }
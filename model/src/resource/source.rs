@@ -1,22 +1,49 @@
 use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 use crate::{
+    error::Result,
     resource::{Device, Format},
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonObject},
+    ResourceBuilder, ResourceCore, ResourceCoreBuilder,
+};
+
+/// A channel of an audio source, listed from IS-04 v1.1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioChannel {
+    pub label: String,
+    /// e.g. `L` or `R`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+impl AudioChannel {
+    pub fn new<S: Into<String>>(label: S) -> Self {
+        Self {
+            label: label.into(),
+            symbol: None,
+        }
+    }
+}
 
 #[must_use]
 pub struct SourceBuilder {
-    core: ResourceCoreBuilder,
+    pub(super) core: ResourceCoreBuilder,
     format: Format,
     device_id: Uuid,
     parents: Vec<Uuid>,
+    channels: Vec<AudioChannel>,
 }
 
 impl SourceBuilder {
@@ -26,11 +53,24 @@ impl SourceBuilder {
             format,
             device_id: device.core.id,
             parents: Vec::new(),
+            channels: Vec::new(),
         }
     }
 
-    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
-        self.core = self.core.description(description);
+    /// See [`ResourceBuilder::description`].
+    pub fn description<S: Into<String>>(self, description: S) -> Self {
+        ResourceBuilder::description(self, description)
+    }
+
+    /// Add a source this source is derived from.
+    pub fn parent(mut self, source: &Source) -> Self {
+        self.parents.push(source.core.id);
+        self
+    }
+
+    /// Add a channel of an audio source.
+    pub fn channel(mut self, channel: AudioChannel) -> Self {
+        self.channels.push(channel);
         self
     }
 
@@ -41,6 +81,7 @@ impl SourceBuilder {
             format: self.format,
             device_id: self.device_id,
             parents: self.parents,
+            channels: self.channels,
         }
     }
 }
@@ -51,6 +92,8 @@ pub struct Source {
     pub format: Format,
     pub device_id: Uuid,
     pub parents: Vec<Uuid>,
+    /// Channels of an audio source
+    pub channels: Vec<AudioChannel>,
 }
 
 impl Source {
@@ -58,6 +101,21 @@ impl Source {
         SourceBuilder::new(label, device, format)
     }
 
+    /// Build from the JSON representation defined by the given IS-04 version.
+    pub fn from_json(api: &APIVersion, json: &serde_json::Value) -> Result<Self> {
+        json::check_version(api)?;
+        let object = JsonObject::new(json)?;
+
+        // Audio channels are only present from v1.1
+        Ok(Source {
+            core: object.core(true)?,
+            format: object.parse("format")?,
+            device_id: object.uuid("device_id")?,
+            parents: object.uuid_array("parents")?,
+            channels: object.opt_deserialize("channels")?.unwrap_or_default(),
+        })
+    }
+
     #[must_use]
     pub fn to_json(&self, api: &APIVersion) -> SourceJson {
         match *api {
@@ -86,6 +144,35 @@ impl Source {
                     parents,
                 })
             }
+            V1_1 | V1_2 | V1_3 => {
+                let parents = self
+                    .parents
+                    .iter()
+                    .map(|id| Value::from(id.to_string()))
+                    .collect();
+
+                let mut json = json::core_json(&self.core);
+                json.insert("caps".to_owned(), Value::Object(Map::new()));
+                json.insert(
+                    "device_id".to_owned(),
+                    Value::from(self.device_id.to_string()),
+                );
+                json.insert("parents".to_owned(), Value::Array(parents));
+                json.insert("clock_name".to_owned(), Value::Null);
+                json.insert("format".to_owned(), Value::from(self.format.to_string()));
+                if self.format == Format::Audio {
+                    json.insert(
+                        "channels".to_owned(),
+                        serde_json::to_value(&self.channels).expect("Channels are valid JSON"),
+                    );
+                }
+
+                match *api {
+                    V1_1 => SourceJson::V1_1(json::to_schema(json)),
+                    V1_2 => SourceJson::V1_2(json::to_schema(json)),
+                    _ => SourceJson::V1_3(json::to_schema(json)),
+                }
+            }
             _ => panic!("Unsupported API"),
         }
     }
@@ -95,4 +182,7 @@ impl Source {
 #[serde(untagged)]
 pub enum SourceJson {
     V1_0(is_04::v1_0_x::Source),
+    V1_1(is_04::v1_1_x::Source),
+    V1_2(is_04::v1_2_x::Source),
+    V1_3(is_04::v1_3_x::Source),
 }
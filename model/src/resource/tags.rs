@@ -0,0 +1,334 @@
+//! Typed access to well-known tags.
+//!
+//! - BCP-002-01 group hints, which group senders and receivers, e.g.
+//!   "SDI out 1" made up of "video" and "audio" members.
+//! - BCP-002-02 asset distinguishing information for nodes and devices.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::error::{Error, Result};
+
+use super::{ResourceCore, ResourceCoreBuilder};
+
+/// BCP-002-01 natural grouping tag.
+pub const GROUP_HINT_TAG: &str = "urn:x-nmos:tag:grouphint/v1.0";
+
+pub const ASSET_MANUFACTURER_TAG: &str = "urn:x-nmos:tag:asset:manufacturer/v1.0";
+pub const ASSET_PRODUCT_TAG: &str = "urn:x-nmos:tag:asset:product/v1.0";
+pub const ASSET_INSTANCE_ID_TAG: &str = "urn:x-nmos:tag:asset:instance-id/v1.0";
+pub const ASSET_FUNCTION_TAG: &str = "urn:x-nmos:tag:asset:function/v1.0";
+
+/// Whether a group is made up of resources on one device or across the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupScope {
+    Device,
+    Node,
+}
+
+impl Default for GroupScope {
+    fn default() -> Self {
+        GroupScope::Device
+    }
+}
+
+/// A BCP-002-01 group hint, serialised as `<group>:<role>[:<scope>]`.
+/// Group names and roles must not contain `:`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GroupHint {
+    pub group: String,
+    pub role: String,
+    pub scope: GroupScope,
+}
+
+impl GroupHint {
+    pub fn new<G: Into<String>, R: Into<String>>(group: G, role: R) -> Self {
+        Self {
+            group: group.into(),
+            role: role.into(),
+            scope: GroupScope::Device,
+        }
+    }
+
+    #[must_use]
+    pub fn scope(mut self, scope: GroupScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Every well-formed group hint in the tags, ignoring malformed values.
+    #[must_use]
+    pub fn from_tags(tags: &BTreeMap<String, Vec<String>>) -> Vec<GroupHint> {
+        tags.get(GROUP_HINT_TAG)
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value.parse().ok())
+            .collect()
+    }
+}
+
+impl fmt::Display for GroupHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Device scope is the default and is left implicit
+        match self.scope {
+            GroupScope::Device => write!(f, "{}:{}", self.group, self.role),
+            GroupScope::Node => write!(f, "{}:{}:node", self.group, self.role),
+        }
+    }
+}
+
+impl FromStr for GroupHint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidGroupHint(s.to_owned());
+
+        let mut parts = s.split(':');
+        let group = parts.next().filter(|g| !g.is_empty()).ok_or_else(invalid)?;
+        let role = parts.next().filter(|r| !r.is_empty()).ok_or_else(invalid)?;
+        let scope = match parts.next() {
+            None | Some("device") => GroupScope::Device,
+            Some("node") => GroupScope::Node,
+            Some(_) => return Err(invalid()),
+        };
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(GroupHint::new(group, role).scope(scope))
+    }
+}
+
+/// BCP-002-02 asset distinguishing information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetInfo {
+    pub manufacturer: String,
+    pub product: String,
+    pub instance_id: String,
+    pub functions: Vec<String>,
+}
+
+impl AssetInfo {
+    pub fn new<M, P, I>(manufacturer: M, product: P, instance_id: I) -> Self
+    where
+        M: Into<String>,
+        P: Into<String>,
+        I: Into<String>,
+    {
+        Self {
+            manufacturer: manufacturer.into(),
+            product: product.into(),
+            instance_id: instance_id.into(),
+            functions: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn function<S: Into<String>>(mut self, function: S) -> Self {
+        self.functions.push(function.into());
+        self
+    }
+
+    /// Read asset information, which requires exactly one manufacturer,
+    /// product and instance id.
+    #[must_use]
+    pub fn from_tags(tags: &BTreeMap<String, Vec<String>>) -> Option<AssetInfo> {
+        let single = |key: &str| match tags.get(key).map(Vec::as_slice) {
+            Some([value]) => Some(value.clone()),
+            _ => None,
+        };
+
+        Some(AssetInfo {
+            manufacturer: single(ASSET_MANUFACTURER_TAG)?,
+            product: single(ASSET_PRODUCT_TAG)?,
+            instance_id: single(ASSET_INSTANCE_ID_TAG)?,
+            functions: tags.get(ASSET_FUNCTION_TAG).cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Write the asset tags, replacing any existing ones.
+    pub fn apply(&self, tags: &mut BTreeMap<String, Vec<String>>) {
+        tags.insert(
+            ASSET_MANUFACTURER_TAG.to_owned(),
+            vec![self.manufacturer.clone()],
+        );
+        tags.insert(ASSET_PRODUCT_TAG.to_owned(), vec![self.product.clone()]);
+        tags.insert(
+            ASSET_INSTANCE_ID_TAG.to_owned(),
+            vec![self.instance_id.clone()],
+        );
+
+        if self.functions.is_empty() {
+            tags.remove(ASSET_FUNCTION_TAG);
+        } else {
+            tags.insert(ASSET_FUNCTION_TAG.to_owned(), self.functions.clone());
+        }
+    }
+}
+
+impl ResourceCoreBuilder {
+    pub fn add_group_hint(self, hint: &GroupHint) -> Self {
+        self.add_tag(GROUP_HINT_TAG, hint.to_string())
+    }
+
+    pub fn asset(mut self, asset: &AssetInfo) -> Self {
+        asset.apply(&mut self.tags);
+        self
+    }
+}
+
+impl ResourceCore {
+    #[must_use]
+    pub fn group_hints(&self) -> Vec<GroupHint> {
+        GroupHint::from_tags(&self.tags)
+    }
+
+    /// Add a group hint unless already present.
+    pub fn add_group_hint(&mut self, hint: &GroupHint) {
+        let value = hint.to_string();
+        let values = self.tags.entry(GROUP_HINT_TAG.to_owned()).or_default();
+        if !values.contains(&value) {
+            values.push(value);
+            self.bump_version();
+        }
+    }
+
+    /// Remove every hint for the given group.
+    pub fn remove_group_hint(&mut self, group: &str) {
+        let values = match self.tags.get_mut(GROUP_HINT_TAG) {
+            Some(values) => values,
+            None => return,
+        };
+
+        let len = values.len();
+        values.retain(|value| {
+            value
+                .parse::<GroupHint>()
+                .map_or(true, |hint| hint.group != group)
+        });
+
+        if values.len() != len {
+            if values.is_empty() {
+                self.tags.remove(GROUP_HINT_TAG);
+            }
+            self.bump_version();
+        }
+    }
+
+    #[must_use]
+    pub fn asset(&self) -> Option<AssetInfo> {
+        AssetInfo::from_tags(&self.tags)
+    }
+
+    pub fn set_asset(&mut self, asset: &AssetInfo) {
+        asset.apply(&mut self.tags);
+        self.bump_version();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(hints: &[&str]) -> BTreeMap<String, Vec<String>> {
+        let mut tags = BTreeMap::new();
+        tags.insert(
+            GROUP_HINT_TAG.to_owned(),
+            hints.iter().map(|hint| hint.to_string()).collect(),
+        );
+        tags
+    }
+
+    #[test]
+    fn parse_group_hint() {
+        assert_eq!(
+            "Input 1:Video".parse::<GroupHint>().unwrap(),
+            GroupHint::new("Input 1", "Video")
+        );
+        assert_eq!(
+            "Input 1:Video:device".parse::<GroupHint>().unwrap(),
+            GroupHint::new("Input 1", "Video")
+        );
+        assert_eq!(
+            "Input 1:Video:node".parse::<GroupHint>().unwrap(),
+            GroupHint::new("Input 1", "Video").scope(GroupScope::Node)
+        );
+    }
+
+    #[test]
+    fn malformed_group_hints() {
+        for value in [
+            "",
+            "Input 1",
+            "Input 1:",
+            ":Video",
+            "::",
+            "Input 1:Video:",
+            "Input 1:Video:global",
+            "Input 1:Video:node:extra",
+        ] {
+            assert!(
+                matches!(value.parse::<GroupHint>(), Err(Error::InvalidGroupHint(v)) if v == value),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for hint in [
+            GroupHint::new("Input 1", "Video"),
+            GroupHint::new("Input 1", "Audio").scope(GroupScope::Node),
+        ] {
+            assert_eq!(hint.to_string().parse::<GroupHint>().unwrap(), hint);
+        }
+        assert_eq!(GroupHint::new("A", "B").to_string(), "A:B");
+        assert_eq!(
+            GroupHint::new("A", "B").scope(GroupScope::Node).to_string(),
+            "A:B:node"
+        );
+    }
+
+    #[test]
+    fn from_tags_skips_malformed() {
+        let hints = GroupHint::from_tags(&tags(&["Input 1:Video", "bad", "Input 2:Audio:node"]));
+        assert_eq!(
+            hints,
+            vec![
+                GroupHint::new("Input 1", "Video"),
+                GroupHint::new("Input 2", "Audio").scope(GroupScope::Node),
+            ]
+        );
+        assert!(GroupHint::from_tags(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn remove_group_hint_keeps_malformed() {
+        let mut core = ResourceCoreBuilder::new("Sender")
+            .tag(
+                GROUP_HINT_TAG,
+                vec!["Input 1:Video", "bad", "Input 2:Video"],
+            )
+            .build();
+        let version = core.version;
+
+        core.remove_group_hint("Input 1");
+        assert_eq!(core.tags[GROUP_HINT_TAG], vec!["bad", "Input 2:Video"]);
+        assert!(core.version > version);
+
+        // Nothing removed leaves the version alone
+        let version = core.version;
+        core.remove_group_hint("Input 3");
+        assert_eq!(core.version, version);
+    }
+
+    #[test]
+    fn add_group_hint_once() {
+        let mut core = ResourceCoreBuilder::new("Sender").build();
+        let hint = GroupHint::new("Input 1", "Video");
+        core.add_group_hint(&hint);
+        core.add_group_hint(&hint);
+        assert_eq!(core.group_hints(), vec![hint]);
+    }
+}
@@ -0,0 +1,874 @@
+//! RFC 4566 session descriptions for RTP senders and receivers.
+//!
+//! Generated media descriptions follow SMPTE ST 2110: raw video (-20), PCM
+//! audio (-30) and ancillary data (-40). A sender with two enabled legs is
+//! described as an ST 2022-7 duplication group.
+//!
+//! Parsing extracts what a receiver needs to join a stream, so receivers can
+//! be configured from a transport file.
+
+use std::{error::Error as StdError, fmt, fmt::Write, net::IpAddr, str::FromStr};
+
+use crate::{
+    connection::{Auto, RtpReceiverParams, RtpSenderParams, SenderEndpoint},
+    resource::{Flow, Rational, Sender},
+};
+
+/// Payload type used for every generated media description.
+pub const PAYLOAD_TYPE: u8 = 96;
+
+/// Time to live for IPv4 multicast connections.
+const MULTICAST_TTL: u8 = 64;
+
+/// Media identifiers of the legs of a duplication group.
+const DUP_MIDS: [&str; 2] = ["PRIMARY", "SECONDARY"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdpError {
+    /// The flow has no media type to describe
+    MissingMediaType,
+    /// The flow lacks attributes required by its media type
+    MissingAttributes(&'static str),
+    /// A transport parameter has not been resolved from `"auto"`
+    Unresolved(&'static str),
+    /// Transport parameters are not RTP parameters
+    InvalidParams(String),
+    /// No leg has RTP enabled
+    NoEnabledLegs,
+    /// A line of a session description could not be parsed
+    Parse { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for SdpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdpError::MissingMediaType => write!(f, "Flow has no media type"),
+            SdpError::MissingAttributes(media) => {
+                write!(f, "Flow is missing {} attributes", media)
+            }
+            SdpError::Unresolved(param) => write!(f, "Transport parameter {} is auto", param),
+            SdpError::InvalidParams(reason) => {
+                write!(f, "Invalid transport parameters: {}", reason)
+            }
+            SdpError::NoEnabledLegs => write!(f, "No leg has RTP enabled"),
+            SdpError::Parse { line, reason } => {
+                write!(
+                    f,
+                    "Invalid session description at line {}: {}",
+                    line, reason
+                )
+            }
+        }
+    }
+}
+
+impl StdError for SdpError {}
+
+fn resolved<T: Copy>(value: &Auto<T>, param: &'static str) -> Result<T, SdpError> {
+    value.value().copied().ok_or(SdpError::Unresolved(param))
+}
+
+fn address_type(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "IP4",
+        IpAddr::V6(_) => "IP6",
+    }
+}
+
+/// Connection address, with the TTL IPv4 multicast requires.
+fn connection_address(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) if v4.is_multicast() => format!("{}/{}", ip, MULTICAST_TTL),
+        _ => ip.to_string(),
+    }
+}
+
+/// The `rtpmap` and `fmtp` values for a flow, along with the media type.
+struct Encoding {
+    media: String,
+    rtpmap: String,
+    fmtp: Option<String>,
+    ptime: Option<&'static str>,
+}
+
+fn encoding(flow: &Flow) -> Result<Encoding, SdpError> {
+    let media_type = flow
+        .media_type
+        .as_deref()
+        .ok_or(SdpError::MissingMediaType)?;
+    let (media, subtype) = media_type
+        .split_once('/')
+        .ok_or(SdpError::MissingMediaType)?;
+
+    match media_type {
+        "video/raw" => {
+            let video = flow
+                .video
+                .as_ref()
+                .ok_or(SdpError::MissingAttributes("video"))?;
+            let rate = flow
+                .grain_rate
+                .ok_or(SdpError::MissingAttributes("grain rate"))?;
+
+            let mut fmtp = format!(
+                "sampling={}; width={}; height={}; exactframerate={}; depth={}; TCS=SDR; \
+                 colorimetry={}; PM=2110GPM; SSN=ST2110-20:2017; TP=2110TPN;",
+                video.color_sampling.as_deref().unwrap_or("YCbCr-4:2:2"),
+                video.frame_width,
+                video.frame_height,
+                rate,
+                video.component_depth.unwrap_or(10),
+                video.colorspace,
+            );
+            if video.interlace_mode != "progressive" {
+                fmtp.push_str(" interlace;");
+            }
+
+            Ok(Encoding {
+                media: media.to_owned(),
+                rtpmap: String::from("raw/90000"),
+                fmtp: Some(fmtp),
+                ptime: None,
+            })
+        }
+        "audio/L16" | "audio/L24" | "audio/L32" => {
+            let audio = flow
+                .audio
+                .as_ref()
+                .ok_or(SdpError::MissingAttributes("audio"))?;
+            let Rational {
+                numerator,
+                denominator,
+            } = audio.sample_rate;
+
+            Ok(Encoding {
+                media: media.to_owned(),
+                rtpmap: format!(
+                    "{}/{}/{}",
+                    subtype,
+                    numerator / denominator.max(1),
+                    audio.channel_count.unwrap_or(1)
+                ),
+                fmtp: None,
+                ptime: Some("1"),
+            })
+        }
+        // Ancillary data and anything else clocked like video
+        _ => Ok(Encoding {
+            media: media.to_owned(),
+            rtpmap: format!("{}/90000", subtype),
+            fmtp: None,
+            ptime: None,
+        }),
+    }
+}
+
+/// Describe a sender's flow as sent with the given endpoint, normally the
+/// active one with every `"auto"` parameter resolved.
+pub fn sender_sdp(
+    sender: &Sender,
+    flow: &Flow,
+    endpoint: &SenderEndpoint,
+) -> Result<String, SdpError> {
+    let encoding = encoding(flow)?;
+
+    let legs = endpoint
+        .transport_params
+        .iter()
+        .map(RtpSenderParams::from_params)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| SdpError::InvalidParams(err.to_string()))?;
+    let legs: Vec<_> = legs.iter().filter(|leg| leg.rtp_enabled).collect();
+
+    let first = legs.first().ok_or(SdpError::NoEnabledLegs)?;
+    let origin = resolved(&first.source_ip, "source_ip")?;
+
+    // Writing to a string cannot fail
+    let mut sdp = String::new();
+    let version = sender.core.version.secs();
+    let _ = write!(
+        sdp,
+        "v=0\r\no=- {} {} IN {} {}\r\ns={}\r\n",
+        version,
+        version,
+        address_type(&origin),
+        origin,
+        sender.core.label,
+    );
+    if !sender.core.description.is_empty() {
+        let _ = write!(sdp, "i={}\r\n", sender.core.description);
+    }
+    sdp.push_str("t=0 0\r\n");
+
+    let duplicated = legs.len() > 1;
+    if duplicated {
+        let _ = write!(sdp, "a=group:DUP {}\r\n", DUP_MIDS.join(" "));
+    }
+
+    for (leg, params) in legs.iter().enumerate() {
+        let source_ip = resolved(&params.source_ip, "source_ip")?;
+        let destination_ip = resolved(&params.destination_ip, "destination_ip")?;
+        let destination_port = resolved(&params.destination_port, "destination_port")?;
+
+        let _ = write!(
+            sdp,
+            "m={} {} RTP/AVP {}\r\nc=IN {} {}\r\n",
+            encoding.media,
+            destination_port,
+            PAYLOAD_TYPE,
+            address_type(&destination_ip),
+            connection_address(&destination_ip),
+        );
+        if destination_ip.is_multicast() {
+            let _ = write!(
+                sdp,
+                "a=source-filter: incl IN {} {} {}\r\n",
+                address_type(&destination_ip),
+                destination_ip,
+                source_ip,
+            );
+        }
+        let _ = write!(sdp, "a=rtpmap:{} {}\r\n", PAYLOAD_TYPE, encoding.rtpmap);
+        if let Some(fmtp) = &encoding.fmtp {
+            let _ = write!(sdp, "a=fmtp:{} {}\r\n", PAYLOAD_TYPE, fmtp);
+        }
+        if let Some(ptime) = encoding.ptime {
+            let _ = write!(sdp, "a=ptime:{}\r\n", ptime);
+        }
+        sdp.push_str("a=ts-refclk:ptp=IEEE1588-2008:traceable\r\na=mediaclk:direct=0\r\n");
+        if duplicated {
+            if let Some(mid) = DUP_MIDS.get(leg) {
+                let _ = write!(sdp, "a=mid:{}\r\n", mid);
+            }
+        }
+    }
+
+    Ok(sdp)
+}
+
+/// A payload format of a media description, from `rtpmap` and `fmtp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadFormat {
+    pub payload_type: u8,
+    /// Encoding name, e.g. `raw` or `L24`
+    pub encoding: String,
+    pub clock_rate: u32,
+    pub channels: Option<u32>,
+    /// Format specific parameters, e.g. `sampling=YCbCr-4:2:2; width=1920`
+    pub fmtp: Option<String>,
+}
+
+/// A media description, one per leg of a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaDescription {
+    /// Media type, e.g. `video` or `audio`
+    pub media: String,
+    pub port: u16,
+    /// Destination address, from the media or session connection line
+    pub connection: Option<IpAddr>,
+    /// Source address included by a source filter
+    pub source: Option<IpAddr>,
+    pub formats: Vec<PayloadFormat>,
+    /// Media identifier, naming the leg in a duplication group
+    pub mid: Option<String>,
+}
+
+/// The parts of a session description relevant to receiving it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDescription {
+    /// Media descriptions, ordered by any ST 2022-7 duplication group
+    pub media: Vec<MediaDescription>,
+    /// Whether the media form an ST 2022-7 duplication group
+    pub duplicated: bool,
+}
+
+/// Address from a connection line, e.g. `IN IP4 239.0.0.1/64`.
+fn parse_connection(value: &str) -> Option<IpAddr> {
+    let parts: Vec<_> = value.split_whitespace().collect();
+    let (kind, address) = match parts.as_slice() {
+        ["IN", kind, address] => (*kind, *address),
+        _ => return None,
+    };
+    let address: IpAddr = address.split('/').next()?.parse().ok()?;
+    (address_type(&address) == kind).then(|| address)
+}
+
+/// Source from a source filter, e.g. `incl IN IP4 239.0.0.1 10.0.0.1`, or
+/// `Some(None)` for an exclusive filter. `None` if the filter is malformed.
+fn parse_source_filter(value: &str) -> Option<Option<IpAddr>> {
+    let parts: Vec<_> = value.split_whitespace().collect();
+    match parts.as_slice() {
+        ["incl", "IN", _, _, source, ..] => source.parse().ok().map(Some),
+        ["excl", "IN", _, _, _, ..] => Some(None),
+        _ => None,
+    }
+}
+
+impl SessionDescription {
+    /// Receiver parameters for each leg of the session.
+    #[must_use]
+    pub fn receiver_params(&self) -> Vec<RtpReceiverParams> {
+        self.media
+            .iter()
+            .map(|media| RtpReceiverParams {
+                source_ip: media.source,
+                multicast_ip: media.connection.filter(IpAddr::is_multicast),
+                interface_ip: Auto::Auto,
+                destination_port: Auto::Value(media.port),
+                rtp_enabled: true,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = SdpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut session_connection = None;
+        let mut group = None;
+        let mut media: Vec<MediaDescription> = Vec::new();
+
+        for (index, line) in s.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |reason| SdpError::Parse {
+                line: index + 1,
+                reason,
+            };
+
+            let (kind, value) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected <type>=<value>"))?;
+            if index == 0 && (kind, value) != ("v", "0") {
+                return Err(err("expected v=0"));
+            }
+
+            match kind {
+                "m" => {
+                    let parts: Vec<_> = value.split_whitespace().collect();
+                    let (name, port, formats) = match parts.as_slice() {
+                        [name, port, _, formats @ ..] if !formats.is_empty() => {
+                            (name, port, formats)
+                        }
+                        _ => return Err(err("expected m=<media> <port> <proto> <fmt>")),
+                    };
+                    let port = port
+                        .split('/')
+                        .next()
+                        .and_then(|port| port.parse().ok())
+                        .ok_or_else(|| err("invalid port"))?;
+                    let formats = formats
+                        .iter()
+                        .map(|format| {
+                            format.parse().map(|payload_type| PayloadFormat {
+                                payload_type,
+                                encoding: String::new(),
+                                clock_rate: 0,
+                                channels: None,
+                                fmtp: None,
+                            })
+                        })
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| err("invalid payload type"))?;
+
+                    media.push(MediaDescription {
+                        media: (*name).to_owned(),
+                        port,
+                        connection: None,
+                        source: None,
+                        formats,
+                        mid: None,
+                    });
+                }
+                "c" => {
+                    let address =
+                        parse_connection(value).ok_or_else(|| err("invalid connection"))?;
+                    match media.last_mut() {
+                        Some(media) => media.connection = Some(address),
+                        None => session_connection = Some(address),
+                    }
+                }
+                "a" => {
+                    let (attribute, value) = value.split_once(':').unwrap_or((value, ""));
+                    match (attribute, media.last_mut()) {
+                        ("group", None) => {
+                            let mut parts = value.split_whitespace();
+                            if parts.next() == Some("DUP") {
+                                group = Some(parts.map(ToOwned::to_owned).collect::<Vec<_>>());
+                            }
+                        }
+                        ("mid", Some(media)) => media.mid = Some(value.trim().to_owned()),
+                        ("source-filter", Some(media)) => {
+                            media.source = parse_source_filter(value)
+                                .ok_or_else(|| err("invalid source filter"))?;
+                        }
+                        ("rtpmap", Some(media)) => {
+                            let (payload_type, encoding) = value
+                                .split_once(' ')
+                                .ok_or_else(|| err("expected rtpmap:<pt> <encoding>"))?;
+                            let mut parts = encoding.trim().split('/');
+                            let format = payload_type
+                                .parse()
+                                .ok()
+                                .and_then(|pt: u8| {
+                                    media.formats.iter_mut().find(|f| f.payload_type == pt)
+                                })
+                                .ok_or_else(|| err("rtpmap for unknown payload type"))?;
+
+                            format.encoding = parts
+                                .next()
+                                .filter(|encoding| !encoding.is_empty())
+                                .ok_or_else(|| err("missing encoding name"))?
+                                .to_owned();
+                            format.clock_rate = parts
+                                .next()
+                                .and_then(|rate| rate.parse().ok())
+                                .ok_or_else(|| err("invalid clock rate"))?;
+                            format.channels = match parts.next() {
+                                Some(channels) => {
+                                    Some(channels.parse().map_err(|_| err("invalid channels"))?)
+                                }
+                                None => None,
+                            };
+                        }
+                        ("fmtp", Some(media)) => {
+                            let (payload_type, params) = value
+                                .split_once(' ')
+                                .ok_or_else(|| err("expected fmtp:<pt> <params>"))?;
+                            let format = payload_type
+                                .parse()
+                                .ok()
+                                .and_then(|pt: u8| {
+                                    media.formats.iter_mut().find(|f| f.payload_type == pt)
+                                })
+                                .ok_or_else(|| err("fmtp for unknown payload type"))?;
+                            format.fmtp = Some(params.trim().to_owned());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if media.is_empty() {
+            return Err(SdpError::Parse {
+                line: s.lines().count(),
+                reason: "no media descriptions",
+            });
+        }
+
+        for media in &mut media {
+            if media.connection.is_none() {
+                media.connection = session_connection;
+            }
+        }
+
+        // Order legs as listed by the duplication group
+        let duplicated = match group {
+            Some(mids) if media.len() > 1 => {
+                let position = |media: &MediaDescription| {
+                    media
+                        .mid
+                        .as_ref()
+                        .and_then(|mid| mids.iter().position(|m| m == mid))
+                        .unwrap_or(usize::MAX)
+                };
+                media.sort_by_key(position);
+                true
+            }
+            _ => false,
+        };
+
+        Ok(SessionDescription { media, duplicated })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::connection::SenderConnection;
+    use crate::resource::{
+        AudioAttributes, Device, DeviceType, Format, Node, Source, Transport, VideoAttributes,
+    };
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        Ipv4Addr::new(a, b, c, d).into()
+    }
+
+    fn video_flow(device: &Device) -> Flow {
+        let source = Source::builder("Source", device, Format::Video).build();
+        Flow::builder("Flow", &source)
+            .media_type("video/raw")
+            .grain_rate(Rational::new(50, 1))
+            .video(VideoAttributes::new(1920, 1080))
+            .build()
+    }
+
+    /// A sender with an active endpoint sending each leg to the given group.
+    fn sender(flow: &Flow, device: &Device, legs: &[(IpAddr, IpAddr)]) -> (Sender, SenderEndpoint) {
+        let sender = Sender::builder("Sender", device, flow, Transport::RtpMulticast).build();
+        let mut endpoint = SenderConnection::new(&sender, legs.len()).active;
+        for (params, (source_ip, destination_ip)) in endpoint.transport_params.iter_mut().zip(legs)
+        {
+            leg(*source_ip, *destination_ip, 5004).apply(params);
+        }
+
+        (sender, endpoint)
+    }
+
+    fn leg(source_ip: IpAddr, destination_ip: IpAddr, port: u16) -> RtpSenderParams {
+        RtpSenderParams {
+            source_ip: Auto::Value(source_ip),
+            destination_ip: Auto::Value(destination_ip),
+            source_port: Auto::Value(port),
+            destination_port: Auto::Value(port),
+            rtp_enabled: true,
+        }
+    }
+
+    fn device() -> Device {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        Device::builder("Device", &node, DeviceType::Generic).build()
+    }
+
+    #[test]
+    fn sender_video() {
+        let device = device();
+        let flow = video_flow(&device);
+        let (sender, endpoint) = sender(&flow, &device, &[(v4(10, 0, 0, 1), v4(239, 0, 0, 1))]);
+
+        let sdp = sender_sdp(&sender, &flow, &endpoint).unwrap();
+        assert!(sdp.starts_with("v=0\r\no=- "));
+        assert!(sdp.contains("m=video 5004 RTP/AVP 96\r\nc=IN IP4 239.0.0.1/64\r\n"));
+        assert!(sdp.contains("a=source-filter: incl IN IP4 239.0.0.1 10.0.0.1\r\n"));
+        assert!(sdp.contains("a=rtpmap:96 raw/90000\r\n"));
+        assert!(sdp.contains("width=1920; height=1080; exactframerate=50;"));
+        assert!(!sdp.contains("a=group:DUP"));
+        assert!(!sdp.contains("a=mid:"));
+    }
+
+    #[test]
+    fn sender_audio() {
+        let device = device();
+        let source = Source::builder("Source", &device, Format::Audio).build();
+        let flow = Flow::builder("Flow", &source)
+            .audio(AudioAttributes {
+                channel_count: Some(2),
+                ..AudioAttributes::new(Rational::new(48000, 1))
+            })
+            .media_type("audio/L24")
+            .build();
+        let (sender, endpoint) = sender(&flow, &device, &[(v4(10, 0, 0, 1), v4(10, 0, 0, 2))]);
+
+        let sdp = sender_sdp(&sender, &flow, &endpoint).unwrap();
+        assert!(sdp.contains("m=audio 5004 RTP/AVP 96\r\nc=IN IP4 10.0.0.2\r\n"));
+        assert!(sdp.contains("a=rtpmap:96 L24/48000/2\r\na=ptime:1\r\n"));
+        // Unicast has no source filter
+        assert!(!sdp.contains("a=source-filter"));
+    }
+
+    #[test]
+    fn sender_errors() {
+        let device = device();
+        let flow = video_flow(&device);
+        let (sender, mut endpoint) = sender(&flow, &device, &[(v4(10, 0, 0, 1), v4(239, 0, 0, 1))]);
+
+        let source = Source::builder("Source", &device, Format::Data).build();
+        let data = Flow::builder("Flow", &source).build();
+        assert_eq!(
+            sender_sdp(&sender, &data, &endpoint),
+            Err(SdpError::MissingMediaType)
+        );
+
+        endpoint.transport_params[0].insert("destination_ip".to_owned(), "auto".into());
+        assert_eq!(
+            sender_sdp(&sender, &flow, &endpoint),
+            Err(SdpError::Unresolved("destination_ip"))
+        );
+
+        endpoint.transport_params[0].insert("rtp_enabled".to_owned(), false.into());
+        assert_eq!(
+            sender_sdp(&sender, &flow, &endpoint),
+            Err(SdpError::NoEnabledLegs)
+        );
+    }
+
+    #[test]
+    fn round_trip_multicast() {
+        let device = device();
+        let flow = video_flow(&device);
+        let (sender, endpoint) = sender(&flow, &device, &[(v4(10, 0, 0, 1), v4(239, 0, 0, 1))]);
+
+        let sdp: SessionDescription = sender_sdp(&sender, &flow, &endpoint)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(!sdp.duplicated);
+        assert_eq!(sdp.media.len(), 1);
+
+        let media = &sdp.media[0];
+        assert_eq!(media.media, "video");
+        assert_eq!(media.port, 5004);
+        assert_eq!(media.connection, Some(v4(239, 0, 0, 1)));
+        assert_eq!(media.source, Some(v4(10, 0, 0, 1)));
+        assert_eq!(media.formats[0].encoding, "raw");
+        assert_eq!(media.formats[0].clock_rate, 90000);
+
+        assert_eq!(
+            sdp.receiver_params(),
+            vec![RtpReceiverParams {
+                source_ip: Some(v4(10, 0, 0, 1)),
+                multicast_ip: Some(v4(239, 0, 0, 1)),
+                interface_ip: Auto::Auto,
+                destination_port: Auto::Value(5004),
+                rtp_enabled: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn round_trip_duplicated() {
+        let device = device();
+        let flow = video_flow(&device);
+        let (sender, endpoint) = sender(
+            &flow,
+            &device,
+            &[
+                (v4(10, 0, 0, 1), v4(239, 0, 0, 1)),
+                (v4(10, 0, 1, 1), v4(239, 0, 1, 1)),
+            ],
+        );
+
+        let generated = sender_sdp(&sender, &flow, &endpoint).unwrap();
+        assert!(generated.contains("a=group:DUP PRIMARY SECONDARY\r\n"));
+
+        let sdp: SessionDescription = generated.parse().unwrap();
+        assert!(sdp.duplicated);
+        let legs: Vec<_> = sdp
+            .media
+            .iter()
+            .map(|media| (media.mid.as_deref(), media.connection, media.source))
+            .collect();
+        assert_eq!(
+            legs,
+            vec![
+                (
+                    Some("PRIMARY"),
+                    Some(v4(239, 0, 0, 1)),
+                    Some(v4(10, 0, 0, 1))
+                ),
+                (
+                    Some("SECONDARY"),
+                    Some(v4(239, 0, 1, 1)),
+                    Some(v4(10, 0, 1, 1))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_leg_not_described() {
+        let device = device();
+        let flow = video_flow(&device);
+        let (sender, mut endpoint) = sender(
+            &flow,
+            &device,
+            &[
+                (v4(10, 0, 0, 1), v4(239, 0, 0, 1)),
+                (v4(10, 0, 1, 1), v4(239, 0, 1, 1)),
+            ],
+        );
+        endpoint.transport_params[0].insert("rtp_enabled".to_owned(), false.into());
+
+        let sdp: SessionDescription = sender_sdp(&sender, &flow, &endpoint)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(!sdp.duplicated);
+        assert_eq!(sdp.media.len(), 1);
+        assert_eq!(sdp.media[0].connection, Some(v4(239, 0, 1, 1)));
+    }
+
+    const MULTICAST: &str = "v=0\r\n\
+        o=- 1 1 IN IP4 10.0.0.1\r\n\
+        s=Camera 1\r\n\
+        t=0 0\r\n\
+        m=video 5004 RTP/AVP 96\r\n\
+        c=IN IP4 239.100.0.1/64\r\n\
+        a=source-filter: incl IN IP4 239.100.0.1 10.0.0.1\r\n\
+        a=rtpmap:96 raw/90000\r\n\
+        a=fmtp:96 sampling=YCbCr-4:2:2; width=1920; height=1080\r\n";
+
+    #[test]
+    fn parse_multicast() {
+        let sdp: SessionDescription = MULTICAST.parse().unwrap();
+        assert!(!sdp.duplicated);
+        assert_eq!(
+            sdp.media,
+            vec![MediaDescription {
+                media: String::from("video"),
+                port: 5004,
+                connection: Some(v4(239, 100, 0, 1)),
+                source: Some(v4(10, 0, 0, 1)),
+                formats: vec![PayloadFormat {
+                    payload_type: 96,
+                    encoding: String::from("raw"),
+                    clock_rate: 90000,
+                    channels: None,
+                    fmtp: Some(String::from(
+                        "sampling=YCbCr-4:2:2; width=1920; height=1080"
+                    )),
+                }],
+                mid: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_unicast() {
+        // Session level connection, and no source filter
+        let sdp: SessionDescription = "v=0\n\
+            o=- 1 1 IN IP4 10.0.0.1\n\
+            s=Microphone\n\
+            c=IN IP4 10.0.0.2\n\
+            t=0 0\n\
+            m=audio 5006 RTP/AVP 97\n\
+            a=rtpmap:97 L24/48000/2\n"
+            .parse()
+            .unwrap();
+
+        let media = &sdp.media[0];
+        assert_eq!(media.connection, Some(v4(10, 0, 0, 2)));
+        assert_eq!(media.source, None);
+        assert_eq!(media.formats[0].encoding, "L24");
+        assert_eq!(media.formats[0].clock_rate, 48000);
+        assert_eq!(media.formats[0].channels, Some(2));
+
+        assert_eq!(
+            sdp.receiver_params(),
+            vec![RtpReceiverParams {
+                source_ip: None,
+                multicast_ip: None,
+                interface_ip: Auto::Auto,
+                destination_port: Auto::Value(5006),
+                rtp_enabled: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_two_legs() {
+        // Legs are ordered by the duplication group, not as listed
+        let sdp: SessionDescription = "v=0\r\n\
+            o=- 1 1 IN IP4 10.0.0.1\r\n\
+            s=Camera 1\r\n\
+            t=0 0\r\n\
+            a=group:DUP primary secondary\r\n\
+            m=video 5006 RTP/AVP 96\r\n\
+            c=IN IP4 239.101.0.1/64\r\n\
+            a=source-filter: incl IN IP4 239.101.0.1 10.0.1.1\r\n\
+            a=rtpmap:96 raw/90000\r\n\
+            a=mid:secondary\r\n\
+            m=video 5004 RTP/AVP 96\r\n\
+            c=IN IP4 239.100.0.1/64\r\n\
+            a=source-filter: incl IN IP4 239.100.0.1 10.0.0.1\r\n\
+            a=rtpmap:96 raw/90000\r\n\
+            a=mid:primary\r\n"
+            .parse()
+            .unwrap();
+        assert!(sdp.duplicated);
+
+        let legs: Vec<_> = sdp
+            .receiver_params()
+            .into_iter()
+            .map(|leg| (leg.source_ip, leg.multicast_ip, leg.destination_port))
+            .collect();
+        assert_eq!(
+            legs,
+            vec![
+                (
+                    Some(v4(10, 0, 0, 1)),
+                    Some(v4(239, 100, 0, 1)),
+                    Auto::Value(5004)
+                ),
+                (
+                    Some(v4(10, 0, 1, 1)),
+                    Some(v4(239, 101, 0, 1)),
+                    Auto::Value(5006)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let line = |sdp: &str| match sdp.parse::<SessionDescription>() {
+            Err(SdpError::Parse { line, .. }) => line,
+            other => panic!("Expected a parse error, got {:?}", other),
+        };
+        let replace = |from: &str, to: &str| MULTICAST.replace(from, to);
+
+        assert_eq!(line("s=Not a session\r\n"), 1);
+        assert_eq!(line(&replace("s=Camera 1", "Camera 1")), 3);
+
+        // Malformed media lines
+        assert_eq!(line(&replace("m=video 5004 RTP/AVP 96", "m=video 5004")), 5);
+        assert_eq!(
+            line(&replace("m=video 5004 RTP/AVP 96", "m=video 5004 RTP/AVP")),
+            5
+        );
+        assert_eq!(
+            line(&replace(
+                "m=video 5004 RTP/AVP 96",
+                "m=video port RTP/AVP 96"
+            )),
+            5
+        );
+        assert_eq!(
+            line(&replace(
+                "m=video 5004 RTP/AVP 96",
+                "m=video 5004 RTP/AVP raw"
+            )),
+            5
+        );
+
+        // Malformed connection lines
+        assert_eq!(line(&replace("IN IP4 239.100.0.1/64", "IN IP4")), 6);
+        assert_eq!(
+            line(&replace("IN IP4 239.100.0.1/64", "IN IP4 example.com")),
+            6
+        );
+        assert_eq!(
+            line(&replace("IN IP4 239.100.0.1/64", "IN IP6 239.100.0.1")),
+            6
+        );
+        assert_eq!(
+            line(&replace("IN IP4 239.100.0.1/64", "ATM NSAP 239.100.0.1")),
+            6
+        );
+
+        assert_eq!(line(&replace("incl IN IP4", "incl")), 7);
+
+        // Malformed rtpmap lines
+        assert_eq!(line(&replace("rtpmap:96 raw/90000", "rtpmap:96")), 8);
+        assert_eq!(
+            line(&replace("rtpmap:96 raw/90000", "rtpmap:97 raw/90000")),
+            8
+        );
+        assert_eq!(line(&replace("rtpmap:96 raw/90000", "rtpmap:96 /90000")), 8);
+        assert_eq!(line(&replace("rtpmap:96 raw/90000", "rtpmap:96 raw")), 8);
+        assert_eq!(
+            line(&replace("rtpmap:96 raw/90000", "rtpmap:96 L24/48000/two")),
+            8
+        );
+
+        // Nothing to receive
+        assert_eq!(line("v=0\r\ns=Empty\r\n"), 2);
+    }
+}
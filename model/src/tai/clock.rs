@@ -0,0 +1,86 @@
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{leap, TaiTime};
+
+/// Source of TAI time.
+pub trait TaiClock: Send + Sync {
+    fn now(&self) -> io::Result<TaiTime>;
+}
+
+/// System clock, assumed to be UTC, corrected using the current leap second
+/// table.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl TaiClock for SystemClock {
+    fn now(&self) -> io::Result<TaiTime> {
+        let now = SystemTime::now();
+        let since_epoch = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        Ok(TaiTime::from_duration(
+            since_epoch + leap::current().offset_at_utc(now),
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use phc::PhcClock;
+
+#[cfg(target_os = "linux")]
+mod phc {
+    use std::{fs::File, io, os::unix::io::AsRawFd, path::Path};
+
+    use super::TaiClock;
+    use crate::tai::TaiTime;
+
+    /// Linux PTP hardware clock, e.g. `/dev/ptp0`. A PHC disciplined by
+    /// `ptp4l` runs on the PTP timescale, which is TAI.
+    #[derive(Debug)]
+    pub struct PhcClock {
+        device: File,
+    }
+
+    impl PhcClock {
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            Ok(Self {
+                device: File::open(path)?,
+            })
+        }
+
+        /// Dynamic POSIX clock id for the open device, see `FD_TO_CLOCKID` in
+        /// the kernel's `posix-timers.h`.
+        fn clock_id(&self) -> libc::clockid_t {
+            let fd = self.device.as_raw_fd();
+            ((!fd) << 3) | 3
+        }
+    }
+
+    impl TaiClock for PhcClock {
+        fn now(&self) -> io::Result<TaiTime> {
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+
+            // Safety: `ts` is a valid timespec and the clock id refers to a
+            // file descriptor owned by `self`
+            let ret = unsafe { libc::clock_gettime(self.clock_id(), &mut ts) };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let secs = u64::try_from(ts.tv_sec)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let nanos = u32::try_from(ts.tv_nsec)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            TaiTime::new(secs, nanos)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid PHC time"))
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use super::TaiTime;
+
+/// Seconds between the NTP epoch (1900) used by IERS files and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Table shared by all conversions between UTC and TAI
+static CURRENT: Lazy<RwLock<Arc<LeapSecondTable>>> =
+    Lazy::new(|| RwLock::new(Arc::new(LeapSecondTable::builtin())));
+
+/// Leap seconds known at the time of writing, as published in IERS Bulletin C,
+/// given as (UTC seconds since the Unix epoch, TAI-UTC offset).
+const BUILTIN: &[(u64, u32)] = &[
+    (63_072_000, 10),    // 1972-01-01
+    (78_796_800, 11),    // 1972-07-01
+    (94_694_400, 12),    // 1973-01-01
+    (126_230_400, 13),   // 1974-01-01
+    (157_766_400, 14),   // 1975-01-01
+    (189_302_400, 15),   // 1976-01-01
+    (220_924_800, 16),   // 1977-01-01
+    (252_460_800, 17),   // 1978-01-01
+    (283_996_800, 18),   // 1979-01-01
+    (315_532_800, 19),   // 1980-01-01
+    (362_793_600, 20),   // 1981-07-01
+    (394_329_600, 21),   // 1982-07-01
+    (425_865_600, 22),   // 1983-07-01
+    (489_024_000, 23),   // 1985-07-01
+    (567_993_600, 24),   // 1988-01-01
+    (631_152_000, 25),   // 1990-01-01
+    (662_688_000, 26),   // 1991-01-01
+    (709_948_800, 27),   // 1992-07-01
+    (741_484_800, 28),   // 1993-07-01
+    (773_020_800, 29),   // 1994-07-01
+    (820_454_400, 30),   // 1996-01-01
+    (867_715_200, 31),   // 1997-07-01
+    (915_148_800, 32),   // 1999-01-01
+    (1_136_073_600, 33), // 2006-01-01
+    (1_230_768_000, 34), // 2009-01-01
+    (1_341_100_800, 35), // 2012-07-01
+    (1_435_708_800, 36), // 2015-07-01
+    (1_483_228_800, 37), // 2017-01-01
+];
+
+/// A change in the TAI-UTC offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeapSecond {
+    /// UTC instant the offset takes effect, in seconds since the Unix epoch
+    pub utc: u64,
+    /// TAI-UTC offset in seconds from that instant
+    pub offset: u32,
+}
+
+/// Ordered table of TAI-UTC offsets. Times before the first entry use the
+/// first offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeapSecondTable {
+    entries: Vec<LeapSecond>,
+    expires: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+pub enum LeapSecondError {
+    Io(io::Error),
+    InvalidLine(usize),
+    Unordered(usize),
+    Empty,
+}
+
+impl fmt::Display for LeapSecondError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LeapSecondError::Io(e) => fmt::Display::fmt(e, f),
+            LeapSecondError::InvalidLine(line) => write!(f, "invalid entry on line {}", line),
+            LeapSecondError::Unordered(line) => {
+                write!(f, "entry on line {} is not in chronological order", line)
+            }
+            LeapSecondError::Empty => write!(f, "no leap second entries"),
+        }
+    }
+}
+
+impl Error for LeapSecondError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LeapSecondError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LeapSecondError {
+    fn from(e: io::Error) -> Self {
+        LeapSecondError::Io(e)
+    }
+}
+
+fn parse_ntp_seconds(s: &str) -> Option<u64> {
+    s.parse::<u64>().ok()?.checked_sub(NTP_UNIX_OFFSET)
+}
+
+impl LeapSecondTable {
+    /// Table compiled into the library.
+    #[must_use]
+    pub fn builtin() -> Self {
+        Self {
+            entries: BUILTIN
+                .iter()
+                .map(|&(utc, offset)| LeapSecond { utc, offset })
+                .collect(),
+            expires: None,
+        }
+    }
+
+    /// Create a table from entries in chronological order.
+    pub fn new(entries: Vec<LeapSecond>) -> Result<Self, LeapSecondError> {
+        if entries.is_empty() {
+            return Err(LeapSecondError::Empty);
+        }
+        if let Some(i) = entries.windows(2).position(|w| w[0].utc >= w[1].utc) {
+            return Err(LeapSecondError::Unordered(i + 2));
+        }
+
+        Ok(Self {
+            entries,
+            expires: None,
+        })
+    }
+
+    /// Parse the IERS `leap-seconds.list` format, where each entry is an NTP
+    /// timestamp followed by the TAI-UTC offset, and `#@` gives the expiry.
+    pub fn parse_iers(s: &str) -> Result<Self, LeapSecondError> {
+        let mut entries: Vec<LeapSecond> = Vec::new();
+        let mut expires = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            if let Some(expiry) = line.strip_prefix("#@") {
+                let expiry = parse_ntp_seconds(expiry.trim())
+                    .ok_or(LeapSecondError::InvalidLine(line_number))?;
+                expires = Some(UNIX_EPOCH + Duration::from_secs(expiry));
+                continue;
+            }
+
+            // Strip comments
+            let data = line.split('#').next().unwrap_or_default().trim();
+            if data.is_empty() {
+                continue;
+            }
+
+            let mut fields = data.split_whitespace();
+            let entry = match (fields.next(), fields.next(), fields.next()) {
+                (Some(ntp), Some(offset), None) => parse_ntp_seconds(ntp)
+                    .zip(offset.parse().ok())
+                    .map(|(utc, offset)| LeapSecond { utc, offset }),
+                _ => None,
+            };
+            let entry = entry.ok_or(LeapSecondError::InvalidLine(line_number))?;
+
+            if let Some(last) = entries.last() {
+                if last.utc >= entry.utc {
+                    return Err(LeapSecondError::Unordered(line_number));
+                }
+            }
+            entries.push(entry);
+        }
+
+        let mut table = Self::new(entries)?;
+        table.expires = expires;
+        Ok(table)
+    }
+
+    /// Load an IERS `leap-seconds.list` file.
+    pub fn load_iers<P: AsRef<Path>>(path: P) -> Result<Self, LeapSecondError> {
+        Self::parse_iers(&fs::read_to_string(path)?)
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[LeapSecond] {
+        &self.entries
+    }
+
+    /// Expiry date given by the source file, if any.
+    #[must_use]
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Whether the source file's expiry date has passed at `now`, after which
+    /// a leap second may have been announced that the table does not include.
+    /// Tables without an expiry date, such as the builtin one, never expire.
+    #[must_use]
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    /// TAI-UTC offset in effect at the given UTC instant.
+    #[must_use]
+    pub fn offset_at_utc(&self, time: SystemTime) -> Duration {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.utc <= secs)
+            .unwrap_or(&self.entries[0]);
+
+        Duration::from_secs(u64::from(entry.offset))
+    }
+
+    /// TAI-UTC offset in effect at the given TAI instant.
+    #[must_use]
+    pub fn offset_at_tai(&self, time: TaiTime) -> Duration {
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.utc + u64::from(entry.offset) <= time.secs())
+            .unwrap_or(&self.entries[0]);
+
+        Duration::from_secs(u64::from(entry.offset))
+    }
+}
+
+impl Default for LeapSecondTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Table currently used for conversions between UTC and TAI.
+#[must_use]
+pub fn current() -> Arc<LeapSecondTable> {
+    CURRENT.read().expect("Leap second table poisoned").clone()
+}
+
+/// Replace the table used for conversions between UTC and TAI, warning if
+/// it has already expired.
+pub fn set_current(table: LeapSecondTable) {
+    if table.is_expired(SystemTime::now()) {
+        warn!("Leap second table has expired, conversions may be out by a leap second");
+    }
+    *CURRENT.write().expect("Leap second table poisoned") = Arc::new(table);
+}
+
+/// Reload the current table from an IERS `leap-seconds.list` file.
+pub fn refresh_from_file<P: AsRef<Path>>(path: P) -> Result<(), LeapSecondError> {
+    set_current(LeapSecondTable::load_iers(path)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IERS: &str = "\
+#	This file is a subset of the IERS leap-seconds.list
+#$	 3676924800
+#@	3960057600
+#
+2272060800	10	# 1 Jan 1972
+2287785600	11	# 1 Jul 1972
+
+3692217600	37	# 1 Jan 2017
+";
+
+    fn utc(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn parse_iers() {
+        let table = LeapSecondTable::parse_iers(IERS).unwrap();
+        assert_eq!(
+            table.entries(),
+            &[
+                LeapSecond {
+                    utc: 63_072_000,
+                    offset: 10
+                },
+                LeapSecond {
+                    utc: 78_796_800,
+                    offset: 11
+                },
+                LeapSecond {
+                    utc: 1_483_228_800,
+                    offset: 37
+                },
+            ]
+        );
+        assert_eq!(table.expires(), Some(utc(1_751_068_800)));
+    }
+
+    #[test]
+    fn parse_iers_unordered() {
+        let unordered = "2287785600 11\n2272060800 10\n";
+        assert!(matches!(
+            LeapSecondTable::parse_iers(unordered),
+            Err(LeapSecondError::Unordered(2))
+        ));
+
+        let duplicate = "2272060800 10\n2272060800 11\n";
+        assert!(matches!(
+            LeapSecondTable::parse_iers(duplicate),
+            Err(LeapSecondError::Unordered(2))
+        ));
+    }
+
+    #[test]
+    fn parse_iers_bad_lines() {
+        let cases = [
+            "2272060800\n",
+            "2272060800 10 11\n",
+            "2272060800 ten\n",
+            "2272060800 -10\n",
+            "1000 10\n",
+            "#@ soon\n2272060800 10\n",
+        ];
+        for input in cases {
+            assert!(
+                matches!(
+                    LeapSecondTable::parse_iers(input),
+                    Err(LeapSecondError::InvalidLine(1))
+                ),
+                "{:?}",
+                input
+            );
+        }
+
+        assert!(matches!(
+            LeapSecondTable::parse_iers("2272060800 10\nbad\n"),
+            Err(LeapSecondError::InvalidLine(2))
+        ));
+        assert!(matches!(
+            LeapSecondTable::parse_iers("# only comments\n"),
+            Err(LeapSecondError::Empty)
+        ));
+    }
+
+    #[test]
+    fn offset_at_utc() {
+        let table = LeapSecondTable::builtin();
+        let offset = |secs| table.offset_at_utc(utc(secs)).as_secs();
+
+        // Before the first entry the first offset applies
+        assert_eq!(offset(0), 10);
+        assert_eq!(offset(1_483_228_799), 36);
+        assert_eq!(offset(1_483_228_800), 37);
+    }
+
+    #[test]
+    fn offset_at_tai() {
+        let table = LeapSecondTable::builtin();
+        let offset = |secs| {
+            table
+                .offset_at_tai(TaiTime::new(secs, 0).unwrap())
+                .as_secs()
+        };
+
+        // 2016-12-31T23:59:59 UTC, then the leap second 23:59:60
+        assert_eq!(offset(1_483_228_835), 36);
+        assert_eq!(offset(1_483_228_836), 36);
+        // 2017-01-01T00:00:00 UTC
+        assert_eq!(offset(1_483_228_837), 37);
+    }
+
+    #[test]
+    fn is_expired() {
+        let table = LeapSecondTable::parse_iers(IERS).unwrap();
+        assert!(!table.is_expired(utc(1_751_068_799)));
+        assert!(table.is_expired(utc(1_751_068_800)));
+        assert!(!LeapSecondTable::builtin().is_expired(SystemTime::now()));
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    cmp,
+    error::Error,
+    fmt,
+    ops::{Add, Sub},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{info, warn};
+
+pub use clock::{SystemClock, TaiClock};
+pub use leap::{LeapSecond, LeapSecondError, LeapSecondTable};
+
+#[cfg(target_os = "linux")]
+pub use clock::PhcClock;
+
+mod clock;
+pub mod leap;
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Largest value of the 48 bit PTP seconds field
+const PTP_SECONDS_MAX: u64 = (1 << 48) - 1;
+
+/// Most recent value returned by `TaiTime::now`, in nanoseconds since the
+/// TAI epoch. Used to keep versions strictly increasing.
+static LAST_NOW: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the clock set with `set_clock` last failed to be read, so that
+/// falling back to the system clock is only logged once per outage.
+static CLOCK_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Clock used by `TaiTime::now`
+static CLOCK: Lazy<RwLock<Arc<dyn TaiClock>>> = Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+/// Replace the clock used by `TaiTime::now`, e.g. with a `PhcClock` so that
+/// versions and activation times line up with PTP.
+pub fn set_clock(clock: Arc<dyn TaiClock>) {
+    *CLOCK.write().expect("TAI clock poisoned") = clock;
+}
+
+/// Time since the TAI epoch (1970-01-01T00:00:00 TAI), as used by NMOS
+/// resource versions and IS-05 activation times.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaiTime {
+    secs: u64,
+    nanos: u32,
+}
+
+/// PTP timestamp as carried in IEEE 1588 messages. PTP uses the TAI epoch,
+/// so no leap second correction is required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtpTimestamp {
+    pub seconds: u64,
+    pub nanoseconds: u32,
+}
+
+impl TaiTime {
+    pub const ZERO: TaiTime = TaiTime { secs: 0, nanos: 0 };
+
+    /// Create a new time, returning `None` if `nanos` is not less than one
+    /// second.
+    #[must_use]
+    pub fn new(secs: u64, nanos: u32) -> Option<TaiTime> {
+        if nanos < NANOS_PER_SEC {
+            Some(TaiTime { secs, nanos })
+        } else {
+            None
+        }
+    }
+
+    /// Current time from the clock set with `set_clock`, falling back to the
+    /// system clock with a warning if it cannot be read. Successive calls are
+    /// guaranteed to return strictly increasing values, even within the same
+    /// nanosecond.
+    #[must_use]
+    pub fn now() -> TaiTime {
+        let clock = CLOCK.read().expect("TAI clock poisoned").clone();
+        let now = match clock.now() {
+            Ok(now) => {
+                if CLOCK_FAILED.swap(false, Ordering::Relaxed) {
+                    info!("TAI clock recovered");
+                }
+                now
+            }
+            Err(e) => {
+                if !CLOCK_FAILED.swap(true, Ordering::Relaxed) {
+                    warn!("Cannot read TAI clock, falling back to system clock: {}", e);
+                }
+                SystemClock.now().expect("System time before Unix epoch")
+            }
+        };
+
+        let now = u64::try_from(now.as_duration().as_nanos()).expect("System time out of range");
+        let last = LAST_NOW
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(cmp::max(now, last + 1))
+            })
+            .expect("Update closure always returns Some");
+
+        TaiTime::from_nanos(cmp::max(now, last + 1))
+    }
+
+    fn from_nanos(nanos: u64) -> TaiTime {
+        TaiTime {
+            secs: nanos / u64::from(NANOS_PER_SEC),
+            nanos: (nanos % u64::from(NANOS_PER_SEC)) as u32,
+        }
+    }
+
+    #[must_use]
+    pub fn secs(&self) -> u64 {
+        self.secs
+    }
+
+    #[must_use]
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Create from a duration since the TAI epoch.
+    #[must_use]
+    pub fn from_duration(duration: Duration) -> TaiTime {
+        TaiTime {
+            secs: duration.as_secs(),
+            nanos: duration.subsec_nanos(),
+        }
+    }
+
+    /// Duration since the TAI epoch.
+    #[must_use]
+    pub fn as_duration(&self) -> Duration {
+        Duration::new(self.secs, self.nanos)
+    }
+
+    /// Convert from UTC system time using the current leap second table,
+    /// returning `None` for times before the Unix epoch.
+    #[must_use]
+    pub fn from_system_time(time: SystemTime) -> Option<TaiTime> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+        let offset = leap::current().offset_at_utc(time);
+        Some(TaiTime::from_duration(since_epoch + offset))
+    }
+
+    /// Convert to UTC system time using the current leap second table,
+    /// saturating at the Unix epoch.
+    #[must_use]
+    pub fn to_system_time(&self) -> SystemTime {
+        let offset = leap::current().offset_at_tai(*self);
+        let since_epoch = self.as_duration().checked_sub(offset).unwrap_or_default();
+        UNIX_EPOCH + since_epoch
+    }
+
+    /// Convert from a PTP timestamp, returning `None` if either field is out
+    /// of range.
+    #[must_use]
+    pub fn from_ptp(timestamp: PtpTimestamp) -> Option<TaiTime> {
+        if timestamp.seconds > PTP_SECONDS_MAX {
+            return None;
+        }
+        TaiTime::new(timestamp.seconds, timestamp.nanoseconds)
+    }
+
+    /// Convert to a PTP timestamp, returning `None` if the seconds do not fit
+    /// in 48 bits.
+    #[must_use]
+    pub fn to_ptp(&self) -> Option<PtpTimestamp> {
+        if self.secs > PTP_SECONDS_MAX {
+            return None;
+        }
+        Some(PtpTimestamp {
+            seconds: self.secs,
+            nanoseconds: self.nanos,
+        })
+    }
+
+    #[must_use]
+    pub fn checked_add(&self, duration: Duration) -> Option<TaiTime> {
+        self.as_duration()
+            .checked_add(duration)
+            .map(TaiTime::from_duration)
+    }
+
+    #[must_use]
+    pub fn checked_sub(&self, duration: Duration) -> Option<TaiTime> {
+        self.as_duration()
+            .checked_sub(duration)
+            .map(TaiTime::from_duration)
+    }
+
+    /// Time elapsed from `earlier` to `self`, or `None` if `earlier` is later.
+    #[must_use]
+    pub fn duration_since(&self, earlier: TaiTime) -> Option<Duration> {
+        self.as_duration().checked_sub(earlier.as_duration())
+    }
+}
+
+impl From<Duration> for TaiTime {
+    fn from(duration: Duration) -> Self {
+        TaiTime::from_duration(duration)
+    }
+}
+
+impl From<TaiTime> for Duration {
+    fn from(time: TaiTime) -> Self {
+        time.as_duration()
+    }
+}
+
+impl Add<Duration> for TaiTime {
+    type Output = TaiTime;
+
+    fn add(self, rhs: Duration) -> TaiTime {
+        self.checked_add(rhs)
+            .expect("Overflow when adding duration to TAI time")
+    }
+}
+
+impl Sub<Duration> for TaiTime {
+    type Output = TaiTime;
+
+    fn sub(self, rhs: Duration) -> TaiTime {
+        self.checked_sub(rhs)
+            .expect("Overflow when subtracting duration from TAI time")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaiParseError {
+    MissingSeparator,
+    InvalidSeconds,
+    InvalidNanoseconds,
+}
+
+impl fmt::Display for TaiParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaiParseError::MissingSeparator => write!(f, "expected <seconds>:<nanoseconds>"),
+            TaiParseError::InvalidSeconds => write!(f, "invalid seconds"),
+            TaiParseError::InvalidNanoseconds => write!(f, "invalid nanoseconds"),
+        }
+    }
+}
+
+impl Error for TaiParseError {}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl FromStr for TaiTime {
+    type Err = TaiParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (secs, nanos) = s.split_once(':').ok_or(TaiParseError::MissingSeparator)?;
+
+        // Parsing integers alone would also accept a leading '+'
+        if !is_digits(secs) {
+            return Err(TaiParseError::InvalidSeconds);
+        }
+        if !is_digits(nanos) {
+            return Err(TaiParseError::InvalidNanoseconds);
+        }
+
+        let secs = secs.parse().map_err(|_| TaiParseError::InvalidSeconds)?;
+        let nanos = nanos
+            .parse()
+            .map_err(|_| TaiParseError::InvalidNanoseconds)?;
+
+        TaiTime::new(secs, nanos).ok_or(TaiParseError::InvalidNanoseconds)
+    }
+}
+
+impl fmt::Debug for TaiTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.secs, self.nanos)
+    }
+}
+
+impl fmt::Display for TaiTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.secs, self.nanos)
+    }
+}
+
+impl Serialize for TaiTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TaiTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let time: TaiTime = "1439299836:10".parse().unwrap();
+        assert_eq!(time, TaiTime::new(1_439_299_836, 10).unwrap());
+        assert_eq!(time.to_string(), "1439299836:10");
+        assert_eq!("0:0".parse(), Ok(TaiTime::ZERO));
+        assert_eq!(
+            "1:999999999".parse::<TaiTime>().unwrap().nanos(),
+            999_999_999
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("", TaiParseError::MissingSeparator),
+            ("1439299836", TaiParseError::MissingSeparator),
+            (":0", TaiParseError::InvalidSeconds),
+            ("+1:0", TaiParseError::InvalidSeconds),
+            ("-1:0", TaiParseError::InvalidSeconds),
+            ("1.5:0", TaiParseError::InvalidSeconds),
+            ("18446744073709551616:0", TaiParseError::InvalidSeconds),
+            ("1:", TaiParseError::InvalidNanoseconds),
+            ("1:+0", TaiParseError::InvalidNanoseconds),
+            ("1:0:0", TaiParseError::InvalidNanoseconds),
+            ("1:1000000000", TaiParseError::InvalidNanoseconds),
+            (" 1:0", TaiParseError::InvalidSeconds),
+        ];
+        for (input, err) in cases {
+            assert_eq!(input.parse::<TaiTime>(), Err(err), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let time = TaiTime::new(1_439_299_836, 123_456_789).unwrap();
+        let json = serde_json::to_string(&time).unwrap();
+        assert_eq!(json, "\"1439299836:123456789\"");
+        assert_eq!(serde_json::from_str::<TaiTime>(&json).unwrap(), time);
+
+        assert!(serde_json::from_str::<TaiTime>("\"1:1000000000\"").is_err());
+        assert!(serde_json::from_str::<TaiTime>("1439299836").is_err());
+    }
+
+    #[test]
+    fn now_is_strictly_increasing() {
+        let first = TaiTime::now();
+        let second = TaiTime::now();
+        assert!(second > first);
+    }
+}
@@ -1,36 +1,88 @@
 use std::{
+    error::Error,
     fmt::{self, Display},
+    num::ParseIntError,
     str::FromStr,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct APIVersion {
     pub major: u8,
     pub minor: u8,
 }
 
-impl FromStr for APIVersion {
-    type Err = std::num::ParseIntError;
+impl APIVersion {
+    #[must_use]
+    pub const fn new(major: u8, minor: u8) -> Self {
+        Self { major, minor }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut versions = s.trim_matches('v').split('.');
+    /// Versions present in both lists, in ascending order without duplicates.
+    #[must_use]
+    pub fn intersect(a: &[APIVersion], b: &[APIVersion]) -> Vec<APIVersion> {
+        let mut common: Vec<APIVersion> = a.iter().filter(|v| b.contains(v)).copied().collect();
+        common.sort_unstable();
+        common.dedup();
+        common
+    }
+
+    /// Highest version present in both lists, if any.
+    #[must_use]
+    pub fn highest_common(a: &[APIVersion], b: &[APIVersion]) -> Option<APIVersion> {
+        a.iter().filter(|v| b.contains(v)).max().copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionParseError {
+    MissingPrefix,
+    Malformed,
+    InvalidNumber(ParseIntError),
+}
 
-        // TODO: Proper error handling
-        assert!(versions.clone().count() == 2);
+impl Display for VersionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionParseError::MissingPrefix => write!(f, "version must start with 'v'"),
+            VersionParseError::Malformed => write!(f, "version must be of the form vX.Y"),
+            VersionParseError::InvalidNumber(e) => write!(f, "invalid version number: {}", e),
+        }
+    }
+}
+
+impl Error for VersionParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VersionParseError::InvalidNumber(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseIntError> for VersionParseError {
+    fn from(e: ParseIntError) -> Self {
+        VersionParseError::InvalidNumber(e)
+    }
+}
 
-        let major = match versions.next() {
-            Some(v) => v.parse::<u8>()?,
-            None => panic!("Missing major version"),
-        };
+impl FromStr for APIVersion {
+    type Err = VersionParseError;
 
-        let minor = match versions.next() {
-            Some(v) => v.parse::<u8>()?,
-            None => panic!("Missing major version"),
-        };
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .strip_prefix('v')
+            .ok_or(VersionParseError::MissingPrefix)?;
+        let (major, minor) = s.split_once('.').ok_or(VersionParseError::Malformed)?;
 
-        assert!(versions.next().is_none());
+        // Reject anything beyond major and minor, e.g. "v1.0.1"
+        if minor.contains('.') {
+            return Err(VersionParseError::Malformed);
+        }
 
-        Ok(Self { major, minor })
+        Ok(Self {
+            major: major.parse()?,
+            minor: minor.parse()?,
+        })
     }
 }
 
@@ -43,5 +95,85 @@ impl Display for APIVersion {
 pub mod is_04 {
     use super::APIVersion;
 
-    pub const V1_0: APIVersion = APIVersion { major: 1, minor: 0 };
+    pub const V1_0: APIVersion = APIVersion::new(1, 0);
+    pub const V1_1: APIVersion = APIVersion::new(1, 1);
+    pub const V1_2: APIVersion = APIVersion::new(1, 2);
+    pub const V1_3: APIVersion = APIVersion::new(1, 3);
+
+    /// Every IS-04 version, in ascending order.
+    pub const ALL: &[APIVersion] = &[V1_0, V1_1, V1_2, V1_3];
+}
+
+pub mod is_05 {
+    use super::APIVersion;
+
+    pub const V1_0: APIVersion = APIVersion::new(1, 0);
+    pub const V1_1: APIVersion = APIVersion::new(1, 1);
+
+    /// Every IS-05 version, in ascending order.
+    pub const ALL: &[APIVersion] = &[V1_0, V1_1];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_04::{V1_0, V1_1, V1_2, V1_3};
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!("v1.3".parse(), Ok(V1_3));
+        assert_eq!("v12.0".parse(), Ok(APIVersion::new(12, 0)));
+        assert_eq!(V1_2.to_string(), "v1.2");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "1.0".parse::<APIVersion>(),
+            Err(VersionParseError::MissingPrefix)
+        );
+        assert_eq!(
+            "v1".parse::<APIVersion>(),
+            Err(VersionParseError::Malformed)
+        );
+        assert_eq!(
+            "v1.0.1".parse::<APIVersion>(),
+            Err(VersionParseError::Malformed)
+        );
+        assert!(matches!(
+            "v1.x".parse::<APIVersion>(),
+            Err(VersionParseError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            "v.1".parse::<APIVersion>(),
+            Err(VersionParseError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            "v1.256".parse::<APIVersion>(),
+            Err(VersionParseError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn highest_common() {
+        assert_eq!(
+            APIVersion::highest_common(&[V1_0, V1_2, V1_3], &[V1_1, V1_2]),
+            Some(V1_2)
+        );
+        assert_eq!(
+            APIVersion::highest_common(&[V1_3, V1_0], is_04::ALL),
+            Some(V1_3)
+        );
+        assert_eq!(APIVersion::highest_common(&[V1_0], &[V1_1, V1_2]), None);
+        assert_eq!(APIVersion::highest_common(&[], is_04::ALL), None);
+    }
+
+    #[test]
+    fn intersect() {
+        assert_eq!(
+            APIVersion::intersect(&[V1_3, V1_0, V1_3, V1_1], &[V1_1, V1_3]),
+            vec![V1_1, V1_3]
+        );
+        assert!(APIVersion::intersect(&[V1_0], &[V1_1]).is_empty());
+    }
 }
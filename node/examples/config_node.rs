@@ -0,0 +1,36 @@
+use nmos_model::resource::ResourceBundle;
+use nmos_node::Node;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+#[tokio::main]
+async fn main() {
+    // Set up logging output
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Set default subscriber");
+
+    // Load resources from file given on the command line
+    let path = std::env::args().nth(1).unwrap_or_else(|| {
+        String::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/config_node.toml"
+        ))
+    });
+
+    let resources = match ResourceBundle::from_file(&path) {
+        Ok(resources) => resources,
+        Err(e) => {
+            println!("Failed to load {}: {}", path, e);
+            return;
+        }
+    };
+
+    // Create node
+    let node = Node::builder_from_resources(resources).build();
+
+    if let Err(e) = node.start().await {
+        println!("Node error: {:?}", e);
+    }
+}
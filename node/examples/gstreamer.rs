@@ -1,27 +1,37 @@
+use std::net::IpAddr;
+use std::sync::Mutex;
+
 use gst::{prelude::*, Pipeline};
 use gstreamer as gst;
+use nmos_model::connection::{Auto, ReceiverEndpoint, RtpReceiverParams, TransportParams};
 use nmos_model::resource;
-use nmos_node::Node;
-use tracing::Level;
+use nmos_node::{async_trait, ActivationError, ConnectionHandler, Node};
+use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
+use uuid::Uuid;
+
+/// Test stream format, 8 bit 4:2:2 raw video
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAME_RATE: i64 = 25;
 
 fn create_pipeline() -> Result<Pipeline, Box<dyn std::error::Error>> {
-    // Create VP8 RTP video test pipeline
+    // Create raw RTP video test pipeline
     let pipeline = gst::Pipeline::default();
 
     let src = gst::ElementFactory::make("videotestsrc").build()?;
+    let filter = gst::ElementFactory::make("capsfilter").build()?;
     let q1 = gst::ElementFactory::make("queue").build()?;
-    let enc = gst::ElementFactory::make("vp8enc").build()?;
+    let pay = gst::ElementFactory::make("rtpvrawpay").build()?;
     let q2 = gst::ElementFactory::make("queue").build()?;
-    let pay = gst::ElementFactory::make("rtpvp8pay").build()?;
     let rtpbin = gst::ElementFactory::make("rtpbin").build()?;
     let sink = gst::ElementFactory::make("udpsink").build()?;
 
-    pipeline.add_many(&[&src, &q1, &enc, &q2, &pay, &rtpbin, &sink])?;
+    pipeline.add_many(&[&src, &filter, &q1, &pay, &q2, &rtpbin, &sink])?;
 
-    src.link(&q1)?;
-    q1.link(&enc)?;
-    enc.link(&pay)?;
+    src.link(&filter)?;
+    filter.link(&q1)?;
+    q1.link(&pay)?;
     pay.link(&q2)?;
 
     let srcpad = q2.static_pad("src").unwrap();
@@ -32,9 +42,14 @@ fn create_pipeline() -> Result<Pipeline, Box<dyn std::error::Error>> {
     let sinkpad = sink.static_pad("sink").unwrap();
     srcpad.link(&sinkpad)?;
 
-    // Encoder properties
-    enc.set_property("keyframe-max-dist", 30i32);
-    enc.set_property("deadline", 1i64);
+    // Video format, matching the flow advertised by the node
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("format", "UYVY")
+        .field("width", WIDTH as i32)
+        .field("height", HEIGHT as i32)
+        .field("framerate", gst::Fraction::new(FRAME_RATE as i32, 1))
+        .build();
+    filter.set_property("caps", caps);
 
     // UDP sink properties
     sink.set_property("host", "0.0.0.0");
@@ -43,9 +58,105 @@ fn create_pipeline() -> Result<Pipeline, Box<dyn std::error::Error>> {
     Ok(pipeline)
 }
 
+fn create_receive_pipeline(
+    address: IpAddr,
+    port: u16,
+) -> Result<Pipeline, Box<dyn std::error::Error>> {
+    // Create raw RTP video receive pipeline
+    let pipeline = gst::Pipeline::default();
+
+    let src = gst::ElementFactory::make("udpsrc").build()?;
+    let jitter = gst::ElementFactory::make("rtpjitterbuffer").build()?;
+    let depay = gst::ElementFactory::make("rtpvrawdepay").build()?;
+    let conv = gst::ElementFactory::make("videoconvert").build()?;
+    let sink = gst::ElementFactory::make("autovideosink").build()?;
+
+    pipeline.add_many(&[&src, &jitter, &depay, &conv, &sink])?;
+    gst::Element::link_many(&[&src, &jitter, &depay, &conv, &sink])?;
+
+    // UDP source properties, joining the group if multicast
+    let caps = gst::Caps::builder("application/x-rtp")
+        .field("media", "video")
+        .field("clock-rate", 90000i32)
+        .field("encoding-name", "RAW")
+        .field("sampling", "YCbCr-4:2:2")
+        .field("depth", "8")
+        .field("width", WIDTH.to_string())
+        .field("height", HEIGHT.to_string())
+        .field("colorimetry", "BT709-2")
+        .build();
+    src.set_property("address", address.to_string());
+    src.set_property("port", i32::from(port));
+    src.set_property("caps", caps);
+
+    Ok(pipeline)
+}
+
+/// Runs a receive pipeline for whatever the receiver is connected to.
+#[derive(Default)]
+struct ReceiverHandler {
+    pipeline: Mutex<Option<Pipeline>>,
+}
+
+#[async_trait]
+impl ConnectionHandler for ReceiverHandler {
+    async fn activate_receiver(
+        &self,
+        receiver_id: Uuid,
+        endpoint: &ReceiverEndpoint,
+    ) -> Result<Vec<TransportParams>, ActivationError> {
+        let mut pipeline = self.pipeline.lock().unwrap();
+
+        // Stop receiving from any previous sender
+        if let Some(pipeline) = pipeline.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+
+        let params = endpoint
+            .transport_params
+            .first()
+            .map(RtpReceiverParams::from_params)
+            .transpose()
+            .map_err(|err| ActivationError(err.to_string()))?;
+        let mut params = match params {
+            Some(params) if endpoint.master_enable && params.rtp_enabled => params,
+            _ => {
+                info!("Receiver {} disabled", receiver_id);
+                return Ok(endpoint.transport_params.clone());
+            }
+        };
+
+        // Join the multicast group, or listen on the interface for unicast
+        let address = match (params.multicast_ip, params.interface_ip.value()) {
+            (Some(group), _) => group,
+            (None, Some(interface)) => *interface,
+            (None, None) => IpAddr::from([0, 0, 0, 0]),
+        };
+        let port = match params.destination_port {
+            Auto::Value(port) => port,
+            Auto::Auto => return Err(ActivationError(String::from("No destination port"))),
+        };
+
+        let receive = create_receive_pipeline(address, port)
+            .map_err(|err| ActivationError(err.to_string()))?;
+        receive
+            .set_state(gst::State::Playing)
+            .map_err(|err| ActivationError(err.to_string()))?;
+        info!("Receiver {} listening on {}:{}", receiver_id, address, port);
+        *pipeline = Some(receive);
+
+        // Report the address actually listened on
+        params.interface_ip = Auto::Value(address);
+        let mut transport_params = endpoint.transport_params.clone();
+        params.apply(&mut transport_params[0]);
+
+        Ok(transport_params)
+    }
+}
+
 fn create_node() -> Node {
     // Create NMOS node
-    let node = resource::Node::builder("GStreamer test node", "http://127.0.0.1:3000/test").build();
+    let node = resource::Node::builder("GStreamer test node", "http://127.0.0.1:3000/").build();
     let device = resource::Device::builder(
         "GStreamer test device",
         &node,
@@ -58,7 +169,15 @@ fn create_node() -> Node {
         resource::Source::builder("GStreamer test source", &device, resource::Format::Video)
             .description("SMPTE video test stream")
             .build();
-    let flow = resource::Flow::builder("GStreamer VP8 test flow", &source).build();
+    let flow = resource::Flow::builder("GStreamer raw video test flow", &source)
+        .media_type("video/raw")
+        .grain_rate(resource::Rational::new(FRAME_RATE, 1))
+        .video(resource::VideoAttributes {
+            color_sampling: Some(String::from("YCbCr-4:2:2")),
+            component_depth: Some(8),
+            ..resource::VideoAttributes::new(WIDTH, HEIGHT)
+        })
+        .build();
 
     // Create sender
     let sender = resource::Sender::builder(
@@ -67,7 +186,15 @@ fn create_node() -> Node {
         &flow,
         resource::Transport::RtpUnicast,
     )
-    .manifest("file:///path/to/sdp/file")
+    .build();
+
+    // Create receiver, connected by a controller through IS-05
+    let receiver = resource::Receiver::builder(
+        "GStreamer test receiver",
+        &device,
+        resource::Format::Video,
+        resource::Transport::RtpUnicast,
+    )
     .build();
 
     let mut bundle = resource::ResourceBundle::new();
@@ -76,8 +203,11 @@ fn create_node() -> Node {
     bundle.insert_source(source);
     bundle.insert_flow(flow);
     bundle.insert_sender(sender);
+    bundle.insert_receiver(receiver);
 
-    Node::builder_from_resources(bundle).build()
+    Node::builder_from_resources(bundle)
+        .connection_handler(ReceiverHandler::default())
+        .build()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
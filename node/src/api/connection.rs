@@ -0,0 +1,711 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use nmos_model::connection::{
+    self, ActivationMode, ActivationPatch, ConnectionError, ReceiverEndpoint, ReceiverPatch,
+    SenderEndpoint, SenderPatch,
+};
+use nmos_model::resource::{DeviceControl, NodeService, Transport};
+use nmos_model::tai::TaiTime;
+use nmos_model::version::{is_05, APIVersion};
+use nmos_model::Model;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use super::ServiceError;
+use crate::event_handler::ActivationError;
+use crate::manifest::{manifest_path, ManifestStore};
+use crate::persist::Persistence;
+use crate::scheduler::Scheduler;
+
+const CONTROL_TYPE: &str = "urn:x-nmos:control:sr-ctrl";
+
+fn parse_api_version(api: &str) -> Result<APIVersion, ServiceError> {
+    let api = match APIVersion::from_str(api) {
+        Ok(api) => api,
+        Err(err) => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(format!("API version badly formed: {}", err)),
+            ))
+        }
+    };
+
+    if !is_05::ALL.contains(&api) {
+        return Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            Some(format!("Unsupported API: {}", api)),
+        ));
+    }
+
+    Ok(api)
+}
+
+fn parse_patch<T: DeserializeOwned>(body: Value) -> Result<T, ServiceError> {
+    serde_json::from_value(body).map_err(|err| {
+        ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            Some(format!("Invalid staged parameters: {}", err)),
+        )
+    })
+}
+
+fn bad_request<E: ToString>(err: E) -> ServiceError {
+    ServiceError::new(StatusCode::BAD_REQUEST, Some(err.to_string()))
+}
+
+fn not_found(kind: &str, id: &Uuid) -> ServiceError {
+    ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("{} {} does not exist", kind, id)),
+    )
+}
+
+/// IS-05 reports the RTP transport without the unicast/multicast subclass.
+fn transport_type(transport: &Transport) -> String {
+    match transport {
+        Transport::RtpUnicast | Transport::RtpMulticast => Transport::Rtp.to_string(),
+        transport => transport.to_string(),
+    }
+}
+
+/// Advertise the connection API served at `base_url` on every node, and on
+/// every device with senders or receivers managed by it.
+pub fn advertise(model: &mut Model, base_url: &str) {
+    let mut device_ids: Vec<Uuid> = model
+        .sender_connections
+        .keys()
+        .filter_map(|id| model.senders.get(id).map(|s| s.device_id))
+        .chain(
+            model
+                .receiver_connections
+                .keys()
+                .filter_map(|id| model.receivers.get(id).map(|r| r.device_id)),
+        )
+        .collect();
+    device_ids.sort_unstable();
+    device_ids.dedup();
+
+    if device_ids.is_empty() {
+        return;
+    }
+
+    let href = base_url.trim_end_matches('/');
+
+    for node in model.nodes.values_mut() {
+        for api in is_05::ALL {
+            let type_ = format!("{}/{}", CONTROL_TYPE, api);
+            if !node.services.iter().any(|s| s.type_ == type_) {
+                node.services.push(NodeService {
+                    href: format!("{}/x-nmos/connection/{}/", href, api),
+                    type_,
+                });
+            }
+        }
+    }
+
+    for id in device_ids {
+        let device = match model.devices.get_mut(&id) {
+            Some(device) => device,
+            None => continue,
+        };
+
+        for api in is_05::ALL {
+            let type_ = format!("{}/{}", CONTROL_TYPE, api);
+            if !device.controls.iter().any(|c| c.type_ == type_) {
+                device.controls.push(DeviceControl {
+                    href: format!("{}/x-nmos/connection/{}/", href, api),
+                    type_,
+                });
+            }
+        }
+    }
+}
+
+pub async fn get_api(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api)?;
+
+    Ok(Json(json!(["bulk/", "single/"])))
+}
+
+pub async fn get_single(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api)?;
+
+    Ok(Json(json!(["receivers/", "senders/"])))
+}
+
+pub async fn get_senders(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Vec<String>>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+
+    let mut senders: Vec<_> = model
+        .sender_connections
+        .iter()
+        .filter(|(_, c)| connection::supports_transport(&c.transport, &api))
+        .map(|(id, _)| format!("{}/", id))
+        .collect();
+    senders.sort();
+
+    Ok(Json(senders))
+}
+
+pub async fn get_receivers(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Vec<String>>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+
+    let mut receivers: Vec<_> = model
+        .receiver_connections
+        .iter()
+        .filter(|(_, c)| connection::supports_transport(&c.transport, &api))
+        .map(|(id, _)| format!("{}/", id))
+        .collect();
+    receivers.sort();
+
+    Ok(Json(receivers))
+}
+
+/// Look up a sender's connection state, hiding senders the requested API
+/// version does not manage.
+macro_rules! sender_connection {
+    ($model:expr, $api:expr, $id:expr) => {
+        match $model.sender_connections.get(&$id) {
+            Some(c) if connection::supports_transport(&c.transport, &$api) => c,
+            _ => return Err(not_found("Sender", &$id)),
+        }
+    };
+}
+
+macro_rules! receiver_connection {
+    ($model:expr, $api:expr, $id:expr) => {
+        match $model.receiver_connections.get(&$id) {
+            Some(c) if connection::supports_transport(&c.transport, &$api) => c,
+            _ => return Err(not_found("Receiver", &$id)),
+        }
+    };
+}
+
+pub async fn get_sender(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    sender_connection!(model, api, id);
+
+    // Transport type is only defined from v1.1
+    if api >= is_05::V1_1 {
+        Ok(Json(json!([
+            "active/",
+            "constraints/",
+            "staged/",
+            "transportfile/",
+            "transporttype/"
+        ])))
+    } else {
+        Ok(Json(json!([
+            "active/",
+            "constraints/",
+            "staged/",
+            "transportfile/"
+        ])))
+    }
+}
+
+pub async fn get_receiver(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    receiver_connection!(model, api, id);
+
+    if api >= is_05::V1_1 {
+        Ok(Json(json!([
+            "active/",
+            "constraints/",
+            "staged/",
+            "transporttype/"
+        ])))
+    } else {
+        Ok(Json(json!(["active/", "constraints/", "staged/"])))
+    }
+}
+
+pub async fn get_sender_constraints(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = sender_connection!(model, api, id);
+
+    Ok(Json(json!(connection.constraints)))
+}
+
+pub async fn get_receiver_constraints(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = receiver_connection!(model, api, id);
+
+    Ok(Json(json!(connection.constraints)))
+}
+
+pub async fn get_sender_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<SenderEndpoint>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = sender_connection!(model, api, id);
+
+    Ok(Json(connection.staged.clone()))
+}
+
+pub async fn get_receiver_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<ReceiverEndpoint>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = receiver_connection!(model, api, id);
+
+    Ok(Json(connection.staged.clone()))
+}
+
+pub async fn get_sender_active(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<SenderEndpoint>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = sender_connection!(model, api, id);
+
+    Ok(Json(connection.active.clone()))
+}
+
+pub async fn get_receiver_active(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<ReceiverEndpoint>, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    let connection = receiver_connection!(model, api, id);
+
+    Ok(Json(connection.active.clone()))
+}
+
+/// Locked endpoints are reported with their own status code.
+fn connection_error(err: ConnectionError) -> ServiceError {
+    match err {
+        ConnectionError::Locked => ServiceError::new(StatusCode::LOCKED, Some(err.to_string())),
+        err => bad_request(err),
+    }
+}
+
+/// What to do with the staged endpoint once a patch is applied.
+enum Request {
+    None,
+    Immediate,
+    Scheduled(ActivationMode, TaiTime),
+    Cancel,
+}
+
+impl From<Option<ActivationPatch>> for Request {
+    fn from(activation: Option<ActivationPatch>) -> Self {
+        match activation {
+            None | Some(ActivationPatch { mode: None, .. }) => Request::None,
+            Some(ActivationPatch {
+                mode: Some(None), ..
+            }) => Request::Cancel,
+            Some(ActivationPatch {
+                mode: Some(Some(ActivationMode::Immediate)),
+                ..
+            }) => Request::Immediate,
+            Some(ActivationPatch {
+                mode: Some(Some(mode)),
+                requested_time,
+            }) => Request::Scheduled(
+                mode,
+                requested_time.expect("Scheduled activations are checked for a time"),
+            ),
+        }
+    }
+}
+
+fn activation_failed(err: ActivationError) -> ServiceError {
+    ServiceError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(format!("Activation failed: {}", err)),
+    )
+}
+
+/// Stage a patch on a sender, carrying out any activation it requests.
+async fn stage_sender(
+    model: &RwLock<Model>,
+    scheduler: &Scheduler,
+    api: &APIVersion,
+    id: Uuid,
+    body: Value,
+) -> Result<(StatusCode, SenderEndpoint), ServiceError> {
+    let patch: SenderPatch = parse_patch(body)?;
+
+    let (previous, generation) = {
+        let mut model = model.write().await;
+        sender_connection!(model, api, id);
+        let connection = model
+            .sender_connections
+            .get_mut(&id)
+            .expect("Connection exists");
+
+        let previous = connection.staged.clone();
+        match Request::from(connection.stage(patch).map_err(connection_error)?) {
+            Request::None => return Ok((StatusCode::OK, connection.staged.clone())),
+            Request::Cancel => {
+                connection.cancel();
+                scheduler.reschedule();
+                return Ok((StatusCode::OK, connection.staged.clone()));
+            }
+            Request::Scheduled(mode, requested_time) => {
+                connection
+                    .schedule(mode, requested_time, TaiTime::now())
+                    .map_err(connection_error)?;
+                scheduler.reschedule();
+                return Ok((StatusCode::ACCEPTED, connection.staged.clone()));
+            }
+            Request::Immediate => (previous, connection.generation),
+        }
+    };
+
+    // The model must not be locked while the application is consulted
+    let activation = match scheduler.activate_sender(id, generation).await {
+        Ok(activation) => activation,
+        Err(err) => {
+            // A failed request has no effect, so the patch is undone too
+            if let Some(connection) = model.write().await.sender_connections.get_mut(&id) {
+                connection.revert(previous, generation);
+            }
+            return Err(activation_failed(err));
+        }
+    };
+
+    let model = model.read().await;
+    let connection = sender_connection!(model, api, id);
+    let mut staged = connection.staged.clone();
+    staged.activation = activation;
+
+    Ok((StatusCode::OK, staged))
+}
+
+/// Stage a patch on a receiver, carrying out any activation it requests.
+async fn stage_receiver(
+    model: &RwLock<Model>,
+    scheduler: &Scheduler,
+    api: &APIVersion,
+    id: Uuid,
+    body: Value,
+) -> Result<(StatusCode, ReceiverEndpoint), ServiceError> {
+    let patch: ReceiverPatch = parse_patch(body)?;
+
+    let (previous, generation) = {
+        let mut model = model.write().await;
+        receiver_connection!(model, api, id);
+        let connection = model
+            .receiver_connections
+            .get_mut(&id)
+            .expect("Connection exists");
+
+        let previous = connection.staged.clone();
+        match Request::from(connection.stage(patch).map_err(connection_error)?) {
+            Request::None => return Ok((StatusCode::OK, connection.staged.clone())),
+            Request::Cancel => {
+                connection.cancel();
+                scheduler.reschedule();
+                return Ok((StatusCode::OK, connection.staged.clone()));
+            }
+            Request::Scheduled(mode, requested_time) => {
+                connection
+                    .schedule(mode, requested_time, TaiTime::now())
+                    .map_err(connection_error)?;
+                scheduler.reschedule();
+                return Ok((StatusCode::ACCEPTED, connection.staged.clone()));
+            }
+            Request::Immediate => (previous, connection.generation),
+        }
+    };
+
+    // The model must not be locked while the application is consulted
+    let activation = match scheduler.activate_receiver(id, generation).await {
+        Ok(activation) => activation,
+        Err(err) => {
+            // A failed request has no effect, so the patch is undone too
+            if let Some(connection) = model.write().await.receiver_connections.get_mut(&id) {
+                connection.revert(previous, generation);
+            }
+            return Err(activation_failed(err));
+        }
+    };
+
+    let model = model.read().await;
+    let connection = receiver_connection!(model, api, id);
+    let mut staged = connection.staged.clone();
+    staged.activation = activation;
+
+    Ok((StatusCode::OK, staged))
+}
+
+pub async fn patch_sender_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+    Extension(scheduler): Extension<Arc<Scheduler>>,
+    Extension(persistence): Extension<Arc<Persistence>>,
+    Json(body): Json<Value>,
+) -> Result<(StatusCode, Json<SenderEndpoint>), ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let (status, staged) = stage_sender(&model, &scheduler, &api, id, body).await?;
+    persistence.changed(&model).await;
+
+    Ok((status, Json(staged)))
+}
+
+pub async fn patch_receiver_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+    Extension(scheduler): Extension<Arc<Scheduler>>,
+    Extension(persistence): Extension<Arc<Persistence>>,
+    Json(body): Json<Value>,
+) -> Result<(StatusCode, Json<ReceiverEndpoint>), ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let (status, staged) = stage_receiver(&model, &scheduler, &api, id, body).await?;
+    persistence.changed(&model).await;
+
+    Ok((status, Json(staged)))
+}
+
+pub async fn get_bulk(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api)?;
+
+    Ok(Json(json!(["receivers/", "senders/"])))
+}
+
+/// Entry of a bulk request, holding a patch for a single sender or receiver.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BulkItem {
+    id: Uuid,
+    params: Value,
+}
+
+/// Result of a single entry of a bulk request.
+#[derive(Serialize)]
+pub struct BulkResult {
+    /// The entry's id as given, so malformed entries can still be matched up
+    id: Value,
+    code: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<String>,
+}
+
+impl BulkResult {
+    fn new<T>(id: Uuid, result: Result<(StatusCode, T), ServiceError>) -> Self {
+        let id = Value::from(id.to_string());
+        match result {
+            Ok((status, _)) => Self {
+                id,
+                code: status.as_u16(),
+                error: None,
+                debug: None,
+            },
+            Err(err) => Self::error(id, &err),
+        }
+    }
+
+    fn error(id: Value, err: &ServiceError) -> Self {
+        Self {
+            id,
+            code: err.status().as_u16(),
+            error: Some(err.status().to_string()),
+            debug: err.debug().map(ToOwned::to_owned),
+        }
+    }
+}
+
+/// Parse each entry of a bulk request on its own, so a malformed entry is
+/// reported in its result rather than failing the whole request.
+fn parse_bulk(body: Value) -> Result<Vec<Result<BulkItem, BulkResult>>, ServiceError> {
+    let items = match body {
+        Value::Array(items) => items,
+        _ => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Invalid bulk request: expected an array")),
+            ))
+        }
+    };
+
+    let items = items
+        .into_iter()
+        .map(|item| {
+            let id = item.get("id").cloned().unwrap_or(Value::Null);
+            serde_json::from_value(item).map_err(|err| {
+                let err = ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    Some(format!("Invalid bulk entry: {}", err)),
+                );
+                BulkResult::error(id, &err)
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Stage each entry in turn, so later entries see the effect of earlier
+/// ones. Results are returned in request order.
+pub async fn post_bulk_senders(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+    Extension(scheduler): Extension<Arc<Scheduler>>,
+    Extension(persistence): Extension<Arc<Persistence>>,
+    Json(body): Json<Value>,
+) -> Result<Json<Vec<BulkResult>>, ServiceError> {
+    let api = parse_api_version(&api)?;
+    let items = parse_bulk(body)?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        let result = match item {
+            Ok(item) => BulkResult::new(
+                item.id,
+                stage_sender(&model, &scheduler, &api, item.id, item.params).await,
+            ),
+            Err(result) => result,
+        };
+        results.push(result);
+    }
+    persistence.changed(&model).await;
+
+    Ok(Json(results))
+}
+
+/// Stage each entry in turn, so later entries see the effect of earlier
+/// ones. Results are returned in request order.
+pub async fn post_bulk_receivers(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+    Extension(scheduler): Extension<Arc<Scheduler>>,
+    Extension(persistence): Extension<Arc<Persistence>>,
+    Json(body): Json<Value>,
+) -> Result<Json<Vec<BulkResult>>, ServiceError> {
+    let api = parse_api_version(&api)?;
+    let items = parse_bulk(body)?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        let result = match item {
+            Ok(item) => BulkResult::new(
+                item.id,
+                stage_receiver(&model, &scheduler, &api, item.id, item.params).await,
+            ),
+            Err(result) => result,
+        };
+        results.push(result);
+    }
+    persistence.changed(&model).await;
+
+    Ok(Json(results))
+}
+
+pub async fn get_sender_transporttype(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<String>, ServiceError> {
+    let api = parse_api_version(&api)?;
+    if api < is_05::V1_1 {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Transport type is not defined in {}", api)),
+        ));
+    }
+
+    let model = model.read().await;
+    let connection = sender_connection!(model, api, id);
+
+    Ok(Json(transport_type(&connection.transport)))
+}
+
+pub async fn get_receiver_transporttype(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+) -> Result<Json<String>, ServiceError> {
+    let api = parse_api_version(&api)?;
+    if api < is_05::V1_1 {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Transport type is not defined in {}", api)),
+        ));
+    }
+
+    let model = model.read().await;
+    let connection = receiver_connection!(model, api, id);
+
+    Ok(Json(transport_type(&connection.transport)))
+}
+
+pub async fn get_sender_transportfile(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<RwLock<Model>>>,
+    Extension(manifests): Extension<Arc<ManifestStore>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api)?;
+
+    let model = model.read().await;
+    sender_connection!(model, api, id);
+    let sender = match model.senders.get(&id) {
+        Some(sender) => sender,
+        None => return Err(not_found("Sender", &id)),
+    };
+
+    if let Some(manifest) = manifests.get(&id) {
+        return Ok(([(header::CONTENT_TYPE, manifest.media_type)], manifest.data).into_response());
+    }
+
+    // Point at a manifest hosted elsewhere
+    if !sender.manifest_href.is_empty() && !sender.manifest_href.ends_with(&manifest_path(&id)) {
+        return Ok(Redirect::temporary(&sender.manifest_href).into_response());
+    }
+
+    Err(ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("Sender {} has no transport file", id)),
+    ))
+}
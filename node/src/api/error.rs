@@ -15,6 +15,14 @@ impl ServiceError {
     pub fn new(status: StatusCode, debug: Option<String>) -> Self {
         Self { status, debug }
     }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn debug(&self) -> Option<&str> {
+        self.debug.as_deref()
+    }
 }
 
 impl IntoResponse for ServiceError {
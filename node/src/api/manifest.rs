@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::ServiceError;
+use crate::manifest::ManifestStore;
+
+fn not_found(id: &Uuid) -> ServiceError {
+    ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("Sender {} has no manifest", id)),
+    )
+}
+
+pub async fn get_senders(Extension(manifests): Extension<Arc<ManifestStore>>) -> Json<Value> {
+    let senders: Vec<String> = manifests
+        .senders()
+        .iter()
+        .map(|id| format!("{}/", id))
+        .collect();
+
+    Json(json!(senders))
+}
+
+pub async fn get_sender(
+    Path(id): Path<Uuid>,
+    Extension(manifests): Extension<Arc<ManifestStore>>,
+) -> Result<Json<Value>, ServiceError> {
+    if manifests.get(&id).is_none() {
+        return Err(not_found(&id));
+    }
+
+    Ok(Json(json!(["manifest"])))
+}
+
+pub async fn get_sender_manifest(
+    Path(id): Path<Uuid>,
+    Extension(manifests): Extension<Arc<ManifestStore>>,
+) -> Result<Response, ServiceError> {
+    let manifest = manifests.get(&id).ok_or_else(|| not_found(&id))?;
+
+    Ok(([(header::CONTENT_TYPE, manifest.media_type)], manifest.data).into_response())
+}
@@ -1,4 +1,6 @@
+mod connection;
 mod error;
+mod manifest;
 mod node;
 mod registration;
 
@@ -9,8 +11,13 @@ use std::task::Context;
 use std::task::Poll;
 
 use axum::{
-    body::Body, extract::OriginalUri, http::Request, http::StatusCode, response::Response,
-    routing::get, Extension, Json, Router,
+    body::Body,
+    extract::OriginalUri,
+    http::Request,
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
 };
 use axum_extra::routing::RouterExt;
 use error::ServiceError;
@@ -20,11 +27,17 @@ use serde_json::json;
 use tokio::sync::RwLock;
 use tower::Service;
 
+use crate::manifest::ManifestStore;
+use crate::persist::Persistence;
+use crate::scheduler::Scheduler;
+
 use self::node::{
-    get_device, get_devices, get_flow, get_flows, get_receiver, get_receivers, get_self,
-    get_sender, get_senders, get_source, get_sources,
+    get_api, get_device, get_devices, get_flow, get_flows, get_receiver, get_receivers, get_self,
+    get_sender, get_senders, get_source, get_sources, get_versions,
 };
 
+pub use connection::advertise as advertise_connection_api;
+pub use node::advertise as advertise_node_api;
 pub use registration::RegistrationApi;
 
 #[derive(Debug, Clone)]
@@ -33,27 +46,30 @@ pub struct NodeApi {
 }
 
 impl NodeApi {
-    pub fn new(model: Arc<RwLock<Model>>) -> Self {
+    pub fn new(
+        model: Arc<RwLock<Model>>,
+        scheduler: Arc<Scheduler>,
+        manifests: Arc<ManifestStore>,
+        persistence: Arc<Persistence>,
+    ) -> Self {
         let router = Router::new()
             .route(
                 "/",
                 get(|| async { Json(json!(["x-manifest/", "x-nmos/"])) }),
             )
-            .route_with_tsr("/x-nmos", get(|| async { Json(json!(["node/"])) }))
-            .route_with_tsr("/x-nmos/node", get(|| async { Json(json!(["v1.0/"])) }))
             .route_with_tsr(
-                "/x-nmos/node/v1.0",
-                get(|| async {
-                    Json(json!([
-                        "devices/",
-                        "flows/",
-                        "receivers/",
-                        "self/",
-                        "senders/",
-                        "sources/"
-                    ]))
-                }),
+                "/x-nmos",
+                get(|| async { Json(json!(["connection/", "node/"])) }),
+            )
+            .route_with_tsr("/x-manifest", get(|| async { Json(json!(["senders/"])) }))
+            .route_with_tsr("/x-manifest/senders", get(manifest::get_senders))
+            .route_with_tsr("/x-manifest/senders/:id", get(manifest::get_sender))
+            .route(
+                "/x-manifest/senders/:id/manifest",
+                get(manifest::get_sender_manifest),
             )
+            .route_with_tsr("/x-nmos/node", get(get_versions))
+            .route_with_tsr("/x-nmos/node/:api", get(get_api))
             .route_with_tsr("/x-nmos/node/:api/self", get(get_self))
             .route_with_tsr("/x-nmos/node/:api/devices", get(get_devices))
             .route("/x-nmos/node/:api/devices/:id", get(get_device))
@@ -65,8 +81,81 @@ impl NodeApi {
             .route("/x-nmos/node/:api/sources/:id", get(get_source))
             .route_with_tsr("/x-nmos/node/:api/flows", get(get_flows))
             .route("/x-nmos/node/:api/flows/:id", get(get_flow))
+            .route_with_tsr(
+                "/x-nmos/connection",
+                get(|| async { Json(json!(["v1.0/", "v1.1/"])) }),
+            )
+            .route_with_tsr("/x-nmos/connection/:api", get(connection::get_api))
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single",
+                get(connection::get_single),
+            )
+            .route_with_tsr("/x-nmos/connection/:api/bulk", get(connection::get_bulk))
+            .route_with_tsr(
+                "/x-nmos/connection/:api/bulk/senders",
+                post(connection::post_bulk_senders),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/bulk/receivers",
+                post(connection::post_bulk_receivers),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/senders",
+                get(connection::get_senders),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/senders/:id",
+                get(connection::get_sender),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/senders/:id/constraints",
+                get(connection::get_sender_constraints),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/senders/:id/staged",
+                get(connection::get_sender_staged).patch(connection::patch_sender_staged),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/senders/:id/active",
+                get(connection::get_sender_active),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/transportfile",
+                get(connection::get_sender_transportfile),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/transporttype",
+                get(connection::get_sender_transporttype),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/receivers",
+                get(connection::get_receivers),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/receivers/:id",
+                get(connection::get_receiver),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/receivers/:id/constraints",
+                get(connection::get_receiver_constraints),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/receivers/:id/staged",
+                get(connection::get_receiver_staged).patch(connection::patch_receiver_staged),
+            )
+            .route_with_tsr(
+                "/x-nmos/connection/:api/single/receivers/:id/active",
+                get(connection::get_receiver_active),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/transporttype",
+                get(connection::get_receiver_transporttype),
+            )
             .fallback(fallback_handler)
-            .layer(Extension(model));
+            .layer(Extension(model))
+            .layer(Extension(scheduler))
+            .layer(Extension(manifests))
+            .layer(Extension(persistence));
 
         Self { router }
     }
@@ -92,3 +181,328 @@ impl Service<Request<Body>> for NodeApi {
         Box::pin(self.router.call(req))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::body::to_bytes;
+    use nmos_model::connection::{ReceiverEndpoint, RtpPool, TransportParams};
+    use nmos_model::resource::{
+        Device, DeviceType, Flow, Format, Node, Receiver, ResourceBundle, Sender, Source, Transport,
+    };
+    use nmos_model::version::is_04;
+    use serde_json::Value;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{async_trait, ActivationError, ConnectionHandler};
+
+    struct Ids {
+        device: String,
+        source: String,
+        flow: String,
+        sender: String,
+        mqtt_sender: String,
+        receiver: String,
+    }
+
+    struct Reject;
+
+    #[async_trait]
+    impl ConnectionHandler for Reject {
+        async fn activate_receiver(
+            &self,
+            _receiver_id: Uuid,
+            _endpoint: &ReceiverEndpoint,
+        ) -> Result<Vec<TransportParams>, ActivationError> {
+            Err(ActivationError(String::from("Rejected")))
+        }
+    }
+
+    fn node_api() -> (NodeApi, Ids) {
+        node_api_with(None)
+    }
+
+    fn node_api_with(handler: Option<Arc<dyn ConnectionHandler>>) -> (NodeApi, Ids) {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let mut device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        let flow = Flow::builder("Flow", &source).build();
+        let sender = Sender::builder("Sender", &device, &flow, Transport::Rtp).build();
+        let mqtt_sender = Sender::builder("MQTT", &device, &flow, Transport::Mqtt).build();
+        let receiver =
+            Receiver::builder("Receiver", &device, Format::Video, Transport::Rtp).build();
+        device.senders = vec![sender.core.id, mqtt_sender.core.id];
+        device.receivers = vec![receiver.core.id];
+
+        let ids = Ids {
+            device: device.core.id.to_string(),
+            source: source.core.id.to_string(),
+            flow: flow.core.id.to_string(),
+            sender: sender.core.id.to_string(),
+            mqtt_sender: mqtt_sender.core.id.to_string(),
+            receiver: receiver.core.id.to_string(),
+        };
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_source(source);
+        bundle.insert_flow(flow);
+        bundle.insert_sender(sender);
+        bundle.insert_sender(mqtt_sender);
+        bundle.insert_receiver(receiver);
+        let mut model = Model::from_resources(bundle);
+        advertise_node_api(&mut model, "http://127.0.0.1:3000/");
+
+        let model = Arc::new(RwLock::new(model));
+        let manifests = Arc::new(ManifestStore::new("http://127.0.0.1:3000/"));
+        let persistence = Arc::new(Persistence::new(None));
+        let scheduler = Arc::new(Scheduler::new(
+            model.clone(),
+            None,
+            handler,
+            Arc::new(RtpPool::new(vec![[127, 0, 0, 1].into()])),
+            manifests.clone(),
+            persistence.clone(),
+            Arc::new(Notify::new()),
+        ));
+
+        (NodeApi::new(model, scheduler, manifests, persistence), ids)
+    }
+
+    async fn get(api: &NodeApi, path: &str) -> (StatusCode, Value) {
+        send(api, Request::get(path).body(Body::empty()).unwrap()).await
+    }
+
+    async fn patch(api: &NodeApi, path: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::patch(path)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        send(api, request).await
+    }
+
+    async fn post(api: &NodeApi, path: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(path)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        send(api, request).await
+    }
+
+    async fn send(api: &NodeApi, request: Request<Body>) -> (StatusCode, Value) {
+        let response = api.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn version_listings() {
+        let (api, _) = node_api();
+
+        let (status, json) = get(&api, "/x-nmos/node").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json, json!(["v1.0/", "v1.1/", "v1.2/", "v1.3/"]));
+
+        for version in is_04::ALL {
+            let (status, json) = get(&api, &format!("/x-nmos/node/{}", version)).await;
+            assert_eq!(status, StatusCode::OK, "{}", version);
+            assert_eq!(json.as_array().map(Vec::len), Some(6), "{}", version);
+        }
+
+        for path in [
+            "/x-nmos/node/v2.0",
+            "/x-nmos/node/v1.3x",
+            "/x-nmos/node/v2.0/self",
+        ] {
+            let (status, _) = get(&api, path).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn self_per_version() {
+        let (api, _) = node_api();
+
+        let (status, json) = get(&api, "/x-nmos/node/v1.0/self").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(json.get("api").is_none());
+
+        for version in ["v1.1", "v1.2", "v1.3"] {
+            let (status, json) = get(&api, &format!("/x-nmos/node/{}/self", version)).await;
+            assert_eq!(status, StatusCode::OK, "{}", version);
+            assert_eq!(
+                json["api"]["versions"],
+                json!(["v1.0", "v1.1", "v1.2", "v1.3"]),
+                "{}",
+                version
+            );
+            assert_eq!(
+                json["api"]["endpoints"],
+                json!([{"host": "127.0.0.1", "port": 3000, "protocol": "http"}]),
+                "{}",
+                version
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn single_resources() {
+        let (api, ids) = node_api();
+
+        for version in is_04::ALL {
+            for (kind, id) in [
+                ("devices", &ids.device),
+                ("sources", &ids.source),
+                ("flows", &ids.flow),
+                ("senders", &ids.sender),
+                ("receivers", &ids.receiver),
+            ] {
+                let path = format!("/x-nmos/node/{}/{}/{}", version, kind, id);
+                let (status, json) = get(&api, &path).await;
+                assert_eq!(status, StatusCode::OK, "{}", path);
+                assert_eq!(json["id"], **id, "{}", path);
+                assert_eq!(
+                    json.get("description").is_some(),
+                    kind != "devices" || *version >= is_04::V1_1,
+                    "{}",
+                    path
+                );
+
+                let path = format!("/x-nmos/node/{}/{}/{}", version, kind, Uuid::nil());
+                let (status, _) = get(&api, &path).await;
+                assert_eq!(status, StatusCode::NOT_FOUND, "{}", path);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn transport_visibility() {
+        let (api, ids) = node_api();
+
+        let path = |version: &str| format!("/x-nmos/node/{}/senders/{}", version, ids.mqtt_sender);
+        assert_eq!(get(&api, &path("v1.2")).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(get(&api, &path("v1.3")).await.0, StatusCode::OK);
+
+        let (_, json) = get(&api, "/x-nmos/node/v1.2/senders").await;
+        assert_eq!(json.as_array().map(Vec::len), Some(1));
+        let (_, json) = get(&api, "/x-nmos/node/v1.3/senders").await;
+        assert_eq!(json.as_array().map(Vec::len), Some(2));
+    }
+
+    #[tokio::test]
+    async fn rejected_patch_is_undone() {
+        let (api, ids) = node_api_with(Some(Arc::new(Reject)));
+        let path = format!(
+            "/x-nmos/connection/v1.1/single/receivers/{}/staged",
+            ids.receiver
+        );
+        let (_, before) = get(&api, &path).await;
+
+        let (status, _) = patch(
+            &api,
+            &path,
+            json!({
+                "master_enable": true,
+                "activation": {"mode": "activate_immediate"}
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let (_, after) = get(&api, &path).await;
+        assert_eq!(after, before);
+        assert_eq!(after["master_enable"], false);
+
+        // Staging without activating is unaffected
+        let (status, staged) = patch(&api, &path, json!({"master_enable": true})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(staged["master_enable"], true);
+    }
+
+    #[tokio::test]
+    async fn bulk_results_per_entry() {
+        let (api, ids) = node_api();
+        let missing = Uuid::new_v4().to_string();
+
+        let (status, json) = post(
+            &api,
+            "/x-nmos/connection/v1.1/bulk/receivers",
+            json!([
+                {"id": ids.receiver, "params": {"master_enable": true}},
+                {"id": missing, "params": {"master_enable": true}},
+                {"id": "not-a-uuid", "params": {}},
+                {"id": ids.receiver},
+                {"id": ids.receiver, "params": {"master_enable": "yes"}},
+                42
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let results = json.as_array().unwrap();
+        let codes: Vec<_> = results
+            .iter()
+            .map(|r| r["code"].as_u64().unwrap())
+            .collect();
+        assert_eq!(codes, [200, 404, 400, 400, 400, 400]);
+        let result_ids: Vec<_> = results.iter().map(|r| r["id"].clone()).collect();
+        assert_eq!(
+            result_ids,
+            [
+                json!(ids.receiver),
+                json!(missing),
+                json!("not-a-uuid"),
+                json!(ids.receiver),
+                json!(ids.receiver),
+                Value::Null
+            ]
+        );
+        assert!(results[0].get("error").is_none());
+        assert!(results[1..].iter().all(|r| r["error"].is_string()));
+
+        // Valid entries are staged despite the others
+        let path = format!(
+            "/x-nmos/connection/v1.1/single/receivers/{}/staged",
+            ids.receiver
+        );
+        assert_eq!(get(&api, &path).await.1["master_enable"], true);
+    }
+
+    #[tokio::test]
+    async fn bulk_entries_in_order() {
+        let (api, ids) = node_api();
+
+        let (status, json) = post(
+            &api,
+            "/x-nmos/connection/v1.1/bulk/senders",
+            json!([
+                {"id": ids.sender, "params": {"master_enable": true}},
+                {"id": ids.sender, "params": {"master_enable": false}},
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json.as_array().map(Vec::len), Some(2));
+
+        let path = format!(
+            "/x-nmos/connection/v1.1/single/senders/{}/staged",
+            ids.sender
+        );
+        assert_eq!(get(&api, &path).await.1["master_enable"], false);
+    }
+
+    #[tokio::test]
+    async fn bulk_rejects_non_array() {
+        let (api, _) = node_api();
+
+        for body in [json!({}), json!("senders"), Value::Null] {
+            let (status, _) = post(&api, "/x-nmos/connection/v1.1/bulk/senders", body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+}
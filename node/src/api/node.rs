@@ -4,24 +4,49 @@ use std::sync::Arc;
 use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::{Extension, Json};
-use nmos_model::resource::{DeviceJson, FlowJson, NodeJson, ReceiverJson, SenderJson, SourceJson};
-use nmos_model::version::is_04::V1_0;
+use nmos_model::resource::{
+    DeviceJson, FlowJson, NodeEndpoint, NodeJson, ReceiverJson, SenderJson, SourceJson,
+};
+use nmos_model::version::is_04;
 use nmos_model::version::APIVersion;
 use nmos_model::Model;
+use reqwest::Url;
+use serde_json::{json, Value};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use super::ServiceError;
 
-const SUPPORTED_API_VERSIONS: &[APIVersion] = &[V1_0];
+pub const SUPPORTED_API_VERSIONS: &[APIVersion] = is_04::ALL;
+
+/// Advertise the node API versions served, and the endpoint they are served
+/// at from `base_url`, on every node.
+pub fn advertise(model: &mut Model, base_url: &str) {
+    let endpoint = Url::parse(base_url).ok().and_then(|url| {
+        Some(NodeEndpoint {
+            host: url.host_str()?.to_owned(),
+            port: url.port_or_known_default()?,
+            protocol: url.scheme().to_owned(),
+        })
+    });
+
+    for node in model.nodes.values_mut() {
+        node.api_versions = SUPPORTED_API_VERSIONS.to_vec();
+        if let Some(endpoint) = &endpoint {
+            if !node.api_endpoints.contains(endpoint) {
+                node.api_endpoints.push(endpoint.clone());
+            }
+        }
+    }
+}
 
 fn parse_api_version(api: &str) -> Result<APIVersion, ServiceError> {
     let api = match APIVersion::from_str(api) {
         Ok(api) => api,
-        Err(_) => {
+        Err(err) => {
             return Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
-                Some(String::from("API version badly formed")),
+                Some(format!("API version badly formed: {}", err)),
             ))
         }
     };
@@ -36,6 +61,28 @@ fn parse_api_version(api: &str) -> Result<APIVersion, ServiceError> {
     Ok(api)
 }
 
+pub async fn get_versions() -> Json<Value> {
+    let versions: Vec<String> = SUPPORTED_API_VERSIONS
+        .iter()
+        .map(|api| format!("{}/", api))
+        .collect();
+
+    Json(json!(versions))
+}
+
+pub async fn get_api(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api)?;
+
+    Ok(Json(json!([
+        "devices/",
+        "flows/",
+        "receivers/",
+        "self/",
+        "senders/",
+        "sources/"
+    ])))
+}
+
 pub async fn get_self(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<RwLock<Model>>>,
@@ -65,7 +112,7 @@ pub async fn get_devices(
     let devices: Vec<_> = model
         .devices
         .values()
-        .map(|device| device.to_json(&api))
+        .map(|device| model.device_json(device, &api))
         .collect();
 
     Ok(Json(devices))
@@ -80,7 +127,7 @@ pub async fn get_device(
     let model = model.read().await;
 
     let device = match model.devices.get(&id) {
-        Some(d) => d.to_json(&api),
+        Some(d) => model.device_json(d, &api),
 
         None => {
             return Err(ServiceError::new(
@@ -104,6 +151,7 @@ pub async fn get_receivers(
     let receivers: Vec<_> = model
         .receivers
         .values()
+        .filter(|receiver| receiver.transport.supports(&api))
         .map(|receiver| receiver.to_json(&api))
         .collect();
 
@@ -118,9 +166,10 @@ pub async fn get_receiver(
 
     let model = model.read().await;
 
+    // Receivers with a transport unknown to this version are hidden
     let receiver = match model.receivers.get(&id) {
-        Some(r) => r.to_json(&api),
-        None => {
+        Some(r) if r.transport.supports(&api) => r.to_json(&api),
+        _ => {
             return Err(ServiceError::new(
                 StatusCode::NOT_FOUND,
                 Some(format!("Receiver {} does not exist", id)),
@@ -142,6 +191,7 @@ pub async fn get_senders(
     let senders: Vec<_> = model
         .senders
         .values()
+        .filter(|sender| sender.transport.supports(&api))
         .map(|sender| sender.to_json(&api))
         .collect();
 
@@ -156,9 +206,10 @@ pub async fn get_sender(
 
     let model = model.read().await;
 
+    // Senders with a transport unknown to this version are hidden
     let sender = match model.senders.get(&id) {
-        Some(s) => s.to_json(&api),
-        None => {
+        Some(s) if s.transport.supports(&api) => s.to_json(&api),
+        _ => {
             return Err(ServiceError::new(
                 StatusCode::NOT_FOUND,
                 Some(format!("Sender {} does not exist", id)),
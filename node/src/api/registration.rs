@@ -1,186 +1,412 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
-use nmos_model::{resource, Model};
-use tokio::sync::RwLock;
-use tracing::info;
+use nmos_model::{version::APIVersion, Model};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{Notify, RwLock};
+use tokio::time::{self, Instant};
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
+use super::node::SUPPORTED_API_VERSIONS;
 use crate::mdns::NmosMdnsRegistry;
 
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Resource types in the order they must be registered, parents first.
+const RESOURCE_TYPES: [&str; 6] = ["node", "device", "source", "flow", "sender", "receiver"];
+
+/// JSON of each resource as last registered, by id.
+#[derive(Debug, Default)]
+pub struct Registered(HashMap<Uuid, (&'static str, Value)>);
+
 pub struct RegistrationApi;
 
 impl RegistrationApi {
-    async fn register_node(
+    /// POST a resource to the registry's resource endpoint.
+    async fn register<T: Serialize>(
         client: &reqwest::Client,
         url: &reqwest::Url,
-        node: &resource::Node,
+        type_: &str,
+        data: &T,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant0,
-        };
+        let post_request = json!({ "type": type_, "data": data });
 
-        // TODO: Must find better way of representing multiple API
-        // version in JSON. For now this will look like a mess.
-        let resource::NodeJson::V1_0(node_json) = node.to_json(&V1_0);
-
-        // Construct POST request
-        let node_post_request = RegistrationapiResourcePostRequestHealthVariant0 {
-            data: Some(node_json),
-            type_: Some(String::from("node")),
-        };
-        let post_request = RegistrationapiResourcePostRequest::Variant0(node_post_request);
-
-        client.post(url.clone()).json(&post_request).send().await?;
+        client
+            .post(url.clone())
+            .json(&post_request)
+            .send()
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
 
-    async fn register_device(
+    /// DELETE a resource no longer in the model.
+    async fn unregister(
         client: &reqwest::Client,
-        url: &reqwest::Url,
-        device: &resource::Device,
+        base: &reqwest::Url,
+        type_: &str,
+        id: &Uuid,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant1,
-        };
-
-        let resource::DeviceJson::V1_0(device_json) = device.to_json(&V1_0);
-        let device_post_request = RegistrationapiResourcePostRequestHealthVariant1 {
-            data: Some(device_json),
-            type_: Some(String::from("device")),
-        };
-        let post_request = RegistrationapiResourcePostRequest::Variant1(device_post_request);
-
-        client.post(url.clone()).json(&post_request).send().await?;
+        let url = base.join(&format!("resource/{}s/{}", type_, id))?;
+        client.delete(url).send().await?.error_for_status()?;
 
         Ok(())
     }
 
-    async fn register_source(
-        client: &reqwest::Client,
-        url: &reqwest::Url,
-        source: &resource::Source,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant4,
-        };
-
-        let resource::SourceJson::V1_0(source_json) = source.to_json(&V1_0);
-        let source_post_request = RegistrationapiResourcePostRequestHealthVariant4 {
-            data: Some(source_json),
-            type_: Some(String::from("source")),
-        };
-        let post_request = RegistrationapiResourcePostRequest::Variant4(source_post_request);
-
-        client.post(url.clone()).json(&post_request).send().await?;
+    /// JSON of every resource visible at `api`, in registration order.
+    fn resources(model: &Model, api: &APIVersion) -> Vec<(&'static str, Uuid, Value)> {
+        fn entry<T: Serialize>(
+            type_: &'static str,
+            id: Uuid,
+            json: T,
+        ) -> (&'static str, Uuid, Value) {
+            let json = serde_json::to_value(json).expect("Resources are valid JSON");
+            (type_, id, json)
+        }
 
-        Ok(())
+        let mut resources = Vec::new();
+        resources.extend(
+            model
+                .nodes
+                .values()
+                .take(1)
+                .map(|node| entry("node", node.core.id, node.to_json(api))),
+        );
+        resources.extend(
+            model
+                .devices
+                .values()
+                .map(|device| entry("device", device.core.id, model.device_json(device, api))),
+        );
+        resources.extend(
+            model
+                .sources
+                .values()
+                .map(|source| entry("source", source.core.id, source.to_json(api))),
+        );
+        resources.extend(
+            model
+                .flows
+                .values()
+                .map(|flow| entry("flow", flow.core.id, flow.to_json(api))),
+        );
+        resources.extend(
+            model
+                .senders
+                .values()
+                .filter(|sender| sender.transport.supports(api))
+                .map(|sender| entry("sender", sender.core.id, sender.to_json(api))),
+        );
+        resources.extend(
+            model
+                .receivers
+                .values()
+                .filter(|receiver| receiver.transport.supports(api))
+                .map(|receiver| entry("receiver", receiver.core.id, receiver.to_json(api))),
+        );
+
+        resources
     }
 
-    async fn register_flow(
+    /// Register every resource, returning the API version negotiated with the
+    /// registry for use by later requests such as heartbeats, along with what
+    /// was registered.
+    pub async fn register_resources(
         client: &reqwest::Client,
-        url: &reqwest::Url,
-        flow: &resource::Flow,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant5,
-        };
-
-        let resource::FlowJson::V1_0(flow_json) = flow.to_json(&V1_0);
-        let flow_post_request = RegistrationapiResourcePostRequestHealthVariant5 {
-            data: Some(flow_json),
-            type_: Some(String::from("flow")),
-        };
-        let post_request = RegistrationapiResourcePostRequest::Variant5(flow_post_request);
-
-        client.post(url.clone()).json(&post_request).send().await?;
+        model: Arc<RwLock<Model>>,
+        registry: &NmosMdnsRegistry,
+    ) -> Result<(APIVersion, Registered), Box<dyn std::error::Error>> {
+        let api = APIVersion::highest_common(&registry.api_ver, SUPPORTED_API_VERSIONS)
+            .ok_or("Registry does not support a common API version")?;
+        let base = &registry.api_url(&api);
 
-        Ok(())
-    }
+        info!("Attempting to register with {}", base);
 
-    async fn register_sender(
-        client: &reqwest::Client,
-        url: &reqwest::Url,
-        sender: &resource::Sender,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant2,
-        };
+        // Resource endpoint
+        let resource_url = &base.join("resource").unwrap();
 
-        let resource::SenderJson::V1_0(sender_json) = sender.to_json(&V1_0);
-        let sender_post_request = RegistrationapiResourcePostRequestHealthVariant2 {
-            data: Some(sender_json),
-            type_: Some(String::from("sender")),
+        let resources = {
+            let model = model.read().await;
+
+            for sender in model.senders.values() {
+                if !sender.transport.supports(&api) {
+                    warn!(
+                        "Not registering sender {}: transport {} is not supported by {}",
+                        sender.core.id, sender.transport, api
+                    );
+                }
+            }
+            for receiver in model.receivers.values() {
+                if !receiver.transport.supports(&api) {
+                    warn!(
+                        "Not registering receiver {}: transport {} is not supported by {}",
+                        receiver.core.id, receiver.transport, api
+                    );
+                }
+            }
+
+            Self::resources(&model, &api)
         };
-        let post_request = RegistrationapiResourcePostRequest::Variant2(sender_post_request);
 
-        client.post(url.clone()).json(&post_request).send().await?;
+        // Register resources in order
+        let mut registered = Registered::default();
+        for (type_, id, json) in resources {
+            Self::register(client, resource_url, type_, &json).await?;
+            registered.0.insert(id, (type_, json));
+        }
 
-        Ok(())
+        Ok((api, registered))
     }
 
-    async fn register_receiver(
+    /// Register resources whose JSON changed since they were last registered,
+    /// and delete those removed from the model.
+    pub async fn update_resources(
         client: &reqwest::Client,
-        url: &reqwest::Url,
-        receiver: &resource::Receiver,
+        model: &RwLock<Model>,
+        registry: &NmosMdnsRegistry,
+        api: &APIVersion,
+        registered: &mut Registered,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        use nmos_model::version::is_04::V1_0;
-        use nmos_schema::is_04::v1_0_x::{
-            RegistrationapiResourcePostRequest, RegistrationapiResourcePostRequestHealthVariant3,
-        };
+        let base = &registry.api_url(api);
+        let resource_url = &base.join("resource").unwrap();
 
-        let resource::ReceiverJson::V1_0(receiver_json) = receiver.to_json(&V1_0);
-        let receiver_post_request = RegistrationapiResourcePostRequestHealthVariant3 {
-            data: Some(receiver_json),
-            type_: Some(String::from("receiver")),
-        };
-        let post_request = RegistrationapiResourcePostRequest::Variant3(receiver_post_request);
+        let resources = Self::resources(&*model.read().await, api);
+        let ids: HashSet<Uuid> = resources.iter().map(|(_, id, _)| *id).collect();
 
-        client.post(url.clone()).json(&post_request).send().await?;
+        for (type_, id, json) in resources {
+            if registered.0.get(&id).map(|(_, last)| last) == Some(&json) {
+                continue;
+            }
+            Self::register(client, resource_url, type_, &json).await?;
+            registered.0.insert(id, (type_, json));
+        }
+
+        // Children before their parents
+        let mut removed: Vec<(&'static str, Uuid)> = registered
+            .0
+            .iter()
+            .filter(|(id, _)| !ids.contains(id))
+            .map(|(id, (type_, _))| (*type_, *id))
+            .collect();
+        removed.sort_by_key(|(type_, _)| {
+            let position = RESOURCE_TYPES.iter().position(|t| t == type_);
+            std::cmp::Reverse(position)
+        });
+        for (type_, id) in removed {
+            Self::unregister(client, base, type_, &id).await?;
+            registered.0.remove(&id);
+        }
 
         Ok(())
     }
 
-    pub async fn register_resources(
+    /// Send heartbeats, registering changes to the model as they are
+    /// signalled through `model_changed`, until the registry stops responding.
+    pub async fn maintain(
         client: &reqwest::Client,
         model: Arc<RwLock<Model>>,
         registry: &NmosMdnsRegistry,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let base = &registry.url.join("v1.0/").unwrap();
-
-        info!("Attempting to register with {}", base);
-
-        // Resource endpoint
-        let resource_url = &base.join("resource").unwrap();
+        api: &APIVersion,
+        mut registered: Registered,
+        model_changed: &Notify,
+    ) {
+        // Get heartbeat endpoint from node id, at the negotiated version
+        let heartbeat_url = {
+            let nodes = &model.read().await.nodes;
+            let node_id = *nodes.iter().next().unwrap().0;
+
+            let base = &registry.api_url(api);
+            base.join(&format!("health/nodes/{}", node_id)).unwrap()
+        };
 
-        // Get read-only model
-        let model = model.read().await;
+        loop {
+            match client.post(heartbeat_url.clone()).send().await {
+                Ok(res) => {
+                    if !res.status().is_success() {
+                        error!("Heartbeat error");
+                        return;
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to send heartbeat: {}", err);
+                    return;
+                }
+            }
+
+            // Register changes until the next heartbeat is due
+            let next_heartbeat = Instant::now() + HEARTBEAT_INTERVAL;
+            while time::timeout_at(next_heartbeat, model_changed.notified())
+                .await
+                .is_ok()
+            {
+                let updated =
+                    Self::update_resources(client, &model, registry, api, &mut registered).await;
+                if let Err(err) = updated {
+                    error!("Failed to update registration: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+}
 
-        // Get node
-        let node = model.nodes.iter().next().unwrap().1;
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Extension, Json, Router, Server};
+    use nmos_model::connection::{ReceiverPatch, RtpPool};
+    use nmos_model::resource::{
+        Device, DeviceType, Format, Node, Receiver, ResourceBundle, Transport,
+    };
+    use nmos_model::version::is_04::V1_3;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::manifest::ManifestStore;
+    use crate::persist::Persistence;
+    use crate::scheduler::Scheduler;
+
+    /// Bodies of the resources POSTed to the mock registry.
+    type Posts = Arc<Mutex<Vec<Value>>>;
+
+    /// Serve a registry on a local port, recording registered resources.
+    fn mock_registry() -> (NmosMdnsRegistry, Posts) {
+        let posts = Posts::default();
+        let app = Router::new()
+            .route(
+                "/x-nmos/registration/:api/resource",
+                post(
+                    |Extension(posts): Extension<Posts>, Json(body): Json<Value>| async move {
+                        posts.lock().await.push(body);
+                        StatusCode::CREATED
+                    },
+                ),
+            )
+            .route(
+                "/x-nmos/registration/:api/health/nodes/:id",
+                post(|| async { StatusCode::OK }),
+            )
+            .layer(Extension(posts.clone()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/x-nmos/registration/",
+            listener.local_addr().unwrap()
+        );
+        let server = Server::from_tcp(listener).unwrap();
+        tokio::spawn(server.serve(app.into_make_service()));
+
+        let registry = NmosMdnsRegistry {
+            api_proto: String::from("http"),
+            api_ver: vec![V1_3],
+            api_auth: false,
+            pri: 0,
+            url: url.parse().unwrap(),
+        };
+        (registry, posts)
+    }
 
-        // Register resources in order
-        Self::register_node(client, resource_url, node).await?;
-        for device in model.devices.values() {
-            Self::register_device(client, resource_url, device).await?;
-        }
-        for source in model.sources.values() {
-            Self::register_source(client, resource_url, source).await?;
-        }
-        for flow in model.flows.values() {
-            Self::register_flow(client, resource_url, flow).await?;
-        }
-        for sender in model.senders.values() {
-            Self::register_sender(client, resource_url, sender).await?;
-        }
-        for receiver in model.receivers.values() {
-            Self::register_receiver(client, resource_url, receiver).await?;
-        }
+    /// Data of the resources of the given type registered so far.
+    async fn registered(posts: &Posts, type_: &str) -> Vec<Value> {
+        posts
+            .lock()
+            .await
+            .iter()
+            .filter(|post| post["type"] == type_)
+            .map(|post| post["data"].clone())
+            .collect()
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn activation_updates_registration() {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let receiver =
+            Receiver::builder("Receiver", &device, Format::Video, Transport::Rtp).build();
+        let id = receiver.core.id;
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_receiver(receiver);
+        let model = Arc::new(RwLock::new(Model::from_resources(bundle)));
+
+        let (registry, posts) = mock_registry();
+        let client = reqwest::Client::new();
+        let (api, registered_resources) =
+            RegistrationApi::register_resources(&client, model.clone(), &registry)
+                .await
+                .unwrap();
+        assert_eq!(api, V1_3);
+        assert_eq!(posts.lock().await.len(), 3);
+
+        let model_changed = Arc::new(Notify::new());
+        tokio::spawn({
+            let model = model.clone();
+            let model_changed = model_changed.clone();
+            async move {
+                RegistrationApi::maintain(
+                    &client,
+                    model,
+                    &registry,
+                    &api,
+                    registered_resources,
+                    &model_changed,
+                )
+                .await;
+            }
+        });
+
+        // Connect the receiver
+        let sender_id = Uuid::new_v4();
+        let generation = {
+            let patch: ReceiverPatch =
+                serde_json::from_value(json!({"sender_id": sender_id, "master_enable": true}))
+                    .unwrap();
+            let mut model = model.write().await;
+            let connection = model.receiver_connections.get_mut(&id).unwrap();
+            connection.stage(patch).unwrap();
+            connection.generation
+        };
+        let scheduler = Scheduler::new(
+            model.clone(),
+            None,
+            None,
+            Arc::new(RtpPool::new(vec![[127, 0, 0, 1].into()])),
+            Arc::new(ManifestStore::new("http://127.0.0.1:3000/")),
+            Arc::new(Persistence::new(None)),
+            model_changed,
+        );
+        scheduler.activate_receiver(id, generation).await.unwrap();
+
+        let subscribed = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let receivers = registered(&posts, "receiver").await;
+                if receivers.len() > 1 {
+                    return receivers;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Receiver was not registered again");
+        assert_eq!(subscribed.len(), 2);
+        assert_eq!(subscribed[1]["id"], id.to_string());
+        assert_eq!(
+            subscribed[1]["subscription"]["sender_id"],
+            sender_id.to_string()
+        );
+        assert_eq!(subscribed[1]["subscription"]["active"], true);
+
+        // Unchanged resources are not registered again
+        assert_eq!(registered(&posts, "node").await.len(), 1);
+        assert_eq!(registered(&posts, "device").await.len(), 1);
     }
 }
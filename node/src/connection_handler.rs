@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use nmos_model::connection::{ReceiverEndpoint, SenderEndpoint, TransportParams};
+use uuid::Uuid;
+
+use crate::event_handler::ActivationError;
+
+/// Applies IS-05 activations to the application's media pipeline.
+///
+/// Each method is called with the endpoint about to become active, its
+/// `"auto"` RTP parameters already resolved. The returned transport
+/// parameters, one entry per leg, become the active parameters reported by
+/// `/active`, so values the pipeline chose itself can be filled in. Returning
+/// an error leaves the active endpoint unchanged.
+///
+/// The [`EventHandler`](crate::EventHandler) activation hooks are called
+/// afterwards, and only if the activation is accepted.
+#[async_trait]
+pub trait ConnectionHandler: Send + Sync {
+    async fn activate_sender(
+        &self,
+        _sender_id: Uuid,
+        endpoint: &SenderEndpoint,
+    ) -> Result<Vec<TransportParams>, ActivationError> {
+        Ok(endpoint.transport_params.clone())
+    }
+
+    async fn activate_receiver(
+        &self,
+        _receiver_id: Uuid,
+        endpoint: &ReceiverEndpoint,
+    ) -> Result<Vec<TransportParams>, ActivationError> {
+        Ok(endpoint.transport_params.clone())
+    }
+}
@@ -1,4 +1,47 @@
+use std::error::Error as StdError;
+use std::fmt;
+
 use async_trait::async_trait;
+use nmos_model::connection::{ReceiverEndpoint, SenderEndpoint};
+use uuid::Uuid;
+
+/// Reason an application refused or failed to apply an activation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivationError(pub String);
+
+impl fmt::Display for ActivationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
 
+impl StdError for ActivationError {}
+
+/// Hooks for IS-05 activations, called once the
+/// [`ConnectionHandler`](crate::ConnectionHandler) has accepted one, with the
+/// transport parameters it returned.
+///
+/// Neither handler is called with the model locked, so both may read or
+/// modify it.
 #[async_trait]
-pub trait EventHandler: Send + Sync {}
+pub trait EventHandler: Send + Sync {
+    /// Called with the endpoint about to become active on a sender. Returning
+    /// an error leaves the active endpoint unchanged.
+    async fn activate_sender(
+        &self,
+        _sender_id: Uuid,
+        _endpoint: &SenderEndpoint,
+    ) -> Result<(), ActivationError> {
+        Ok(())
+    }
+
+    /// Called with the endpoint about to become active on a receiver.
+    /// Returning an error leaves the active endpoint unchanged.
+    async fn activate_receiver(
+        &self,
+        _receiver_id: Uuid,
+        _endpoint: &ReceiverEndpoint,
+    ) -> Result<(), ActivationError> {
+        Ok(())
+    }
+}
@@ -1,33 +1,74 @@
-use std::{collections::BinaryHeap, sync::Arc, thread, time::Duration};
+use std::{
+    collections::BinaryHeap,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 use axum::{http::Method, Server};
-pub use event_handler::EventHandler;
+pub use connection_handler::ConnectionHandler;
+pub use event_handler::{ActivationError, EventHandler};
 use mdns::MdnsContext;
-use nmos_model::{resource::ResourceBundle, Model};
+use nmos_model::{
+    connection::{RtpPool, RtpResolver},
+    resource::ResourceBundle,
+    Model,
+};
 use tokio::{
     runtime::Runtime,
-    sync::{mpsc, Mutex, RwLock},
+    sync::{mpsc, Mutex, Notify, RwLock},
 };
 use tower::{make::Shared, ServiceBuilder};
 use tower_http::cors::{self, CorsLayer};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 mod api;
+mod connection_handler;
 mod error;
 mod event_handler;
+mod manifest;
 mod mdns;
+mod persist;
+mod scheduler;
 
 pub use async_trait::async_trait;
 pub use error::Error as NmosError;
+pub use manifest::{Manifest, ManifestStore};
 
-use api::{NodeApi, RegistrationApi};
+use api::{advertise_connection_api, advertise_node_api, NodeApi, RegistrationApi};
 use mdns::{NmosMdnsConfig, NmosMdnsEvent, NmosMdnsRegistry};
+use persist::Persistence;
+use scheduler::Scheduler;
 
 #[derive(Default)]
 #[must_use]
 pub struct NodeBuilder {
     model: Model,
     event_handler: Option<Arc<dyn EventHandler>>,
+    connection_handler: Option<Arc<dyn ConnectionHandler>>,
+    rtp_resolver: Option<Arc<dyn RtpResolver>>,
+    persist_path: Option<PathBuf>,
+    bind_addr: Option<SocketAddr>,
+    base_url: Option<String>,
+}
+
+/// Address other hosts can most likely reach this one on, found by asking
+/// the OS which address it would route from. Nothing is sent.
+fn primary_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// First of the multicast groups allocated by the default RTP pool, a /24 in
+/// the organisation-local scope 239.192.0.0/14 chosen from the node id, so
+/// nodes left with the default rarely pick the same groups.
+fn default_multicast_range(model: &Model) -> Ipv4Addr {
+    let id = model.nodes.keys().next().copied().unwrap_or_default();
+    let bytes = id.as_bytes();
+    Ipv4Addr::new(239, 192 + bytes[0] % 4, bytes[1], 1)
 }
 
 impl NodeBuilder {
@@ -35,6 +76,11 @@ impl NodeBuilder {
         Self {
             model,
             event_handler: None,
+            connection_handler: None,
+            rtp_resolver: None,
+            persist_path: None,
+            bind_addr: None,
+            base_url: None,
         }
     }
 
@@ -42,6 +88,11 @@ impl NodeBuilder {
         Self {
             model: Model::from_resources(resource_bundle),
             event_handler: None,
+            connection_handler: None,
+            rtp_resolver: None,
+            persist_path: None,
+            bind_addr: None,
+            base_url: None,
         }
     }
 
@@ -50,25 +101,154 @@ impl NodeBuilder {
         self
     }
 
+    /// Apply IS-05 activations to the application's media pipeline.
+    pub fn connection_handler<H: ConnectionHandler + 'static>(mut self, handler: H) -> Self {
+        self.connection_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Choose values for `"auto"` RTP parameters on activation. By default
+    /// the first leg uses the address the APIs are reached at as its
+    /// interface, and sender destinations are allocated from 254 multicast
+    /// groups in 239.192.0.0/14 picked from the node id. Activations which
+    /// would leave parameters of an enabled leg as `"auto"` are rejected.
+    pub fn rtp_resolver<R: RtpResolver + 'static>(mut self, resolver: R) -> Self {
+        self.rtp_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Persist ids, labels and connection state to the given file, restoring
+    /// them from it on build. Connections which were active are activated
+    /// again through the handlers when the node starts.
+    pub fn persist<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.persist_path = Some(path.into());
+        self
+    }
+
+    /// Serve the HTTP APIs on the given address, `0.0.0.0:3000` by default.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind_addr = Some(addr);
+        self
+    }
+
+    /// URL the HTTP APIs are reached at, e.g. `http://192.168.1.10:3000/`,
+    /// used for the control hrefs advertised in IS-04. By default this is
+    /// built from the bind address, or the host's primary address if bound
+    /// to all interfaces.
+    pub fn base_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
     pub fn build(self) -> Node {
+        let mut model = self.model;
+
+        let bind_addr = self
+            .bind_addr
+            .unwrap_or_else(|| ([0, 0, 0, 0], 3000).into());
+        let host_ip = if bind_addr.ip().is_unspecified() {
+            primary_ip().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        } else {
+            bind_addr.ip()
+        };
+        let base_url = self
+            .base_url
+            .unwrap_or_else(|| format!("http://{}/", SocketAddr::new(host_ip, bind_addr.port())));
+
+        // Restore saved state before anything can observe the model
+        let mut persistence = Persistence::new(self.persist_path);
+        if let Err(err) = persistence.restore(&mut model) {
+            error!("Failed to restore model state: {}", err);
+        }
+        let persistence = Arc::new(persistence);
+
+        advertise_node_api(&mut model, &base_url);
+        advertise_connection_api(&mut model, &base_url);
+
+        // SDP for senders whose parameters are already resolved
+        let manifests = Arc::new(ManifestStore::new(&base_url));
+        manifests.update_all(&mut model);
+
+        let first_group = default_multicast_range(&model);
+
         // Wrap model in Arc
-        let model = Arc::new(RwLock::new(self.model));
+        let model = Arc::new(RwLock::new(model));
+
+        // Signalled when resources change, to update the registry
+        let model_changed = Arc::new(Notify::new());
+
+        // Scheduler applies IS-05 activations on behalf of the API
+        let resolver = self.rtp_resolver.unwrap_or_else(|| {
+            Arc::new(RtpPool::new(vec![host_ip]).multicast_range(first_group, 254))
+        });
+        let scheduler = Arc::new(Scheduler::new(
+            model.clone(),
+            self.event_handler.clone(),
+            self.connection_handler,
+            resolver,
+            manifests.clone(),
+            persistence.clone(),
+            model_changed.clone(),
+        ));
 
         // Make service
-        let service = NodeApi::new(model.clone());
+        let service = NodeApi::new(
+            model.clone(),
+            scheduler.clone(),
+            manifests.clone(),
+            persistence.clone(),
+        );
 
         Node {
             _event_handler: self.event_handler,
+            scheduler,
             model,
+            manifests,
             service,
+            persistence,
+            model_changed,
+            bind_addr,
         }
     }
 }
 
+/// Access to a node's model which keeps saved state up to date, usable
+/// after the node is started.
+#[derive(Clone)]
+pub struct NodeHandle {
+    model: Arc<RwLock<Model>>,
+    persistence: Arc<Persistence>,
+    model_changed: Arc<Notify>,
+}
+
+impl NodeHandle {
+    #[must_use]
+    pub fn model(&self) -> Arc<RwLock<Model>> {
+        self.model.clone()
+    }
+
+    /// Modify the model, e.g. to change labels or tags, then save the result
+    /// to the persistence file and register the changes.
+    pub async fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Model) -> R,
+    {
+        let result = f(&mut *self.model.write().await);
+        self.persistence.changed(&self.model).await;
+        self.model_changed.notify_one();
+        result
+    }
+}
+
 pub struct Node {
     _event_handler: Option<Arc<dyn EventHandler>>,
+    scheduler: Arc<Scheduler>,
     model: Arc<RwLock<Model>>,
+    manifests: Arc<ManifestStore>,
     service: NodeApi,
+    persistence: Arc<Persistence>,
+    model_changed: Arc<Notify>,
+    bind_addr: SocketAddr,
 }
 
 impl Node {
@@ -80,11 +260,29 @@ impl Node {
         NodeBuilder::from_resources(resource_bundle)
     }
 
+    /// The node's model. Changes made through it directly are not saved or
+    /// registered, use [`NodeHandle::update`] instead.
     #[must_use]
     pub fn model(&self) -> Arc<RwLock<Model>> {
         self.model.clone()
     }
 
+    #[must_use]
+    pub fn handle(&self) -> NodeHandle {
+        NodeHandle {
+            model: self.model.clone(),
+            persistence: self.persistence.clone(),
+            model_changed: self.model_changed.clone(),
+        }
+    }
+
+    /// Manifests served under `/x-manifest`, for providing a sender's
+    /// manifest in place of generated SDP.
+    #[must_use]
+    pub fn manifests(&self) -> Arc<ManifestStore> {
+        self.manifests.clone()
+    }
+
     pub async fn start(self) -> error::Result<()> {
         info!("Starting nmos-rs node");
 
@@ -119,8 +317,9 @@ impl Node {
 
             while let Some(event) = rx.recv().await {
                 if let NmosMdnsEvent::Discovery(_, Ok(discovery)) = event {
-                    if let Some(registry) = NmosMdnsRegistry::parse(&discovery) {
-                        registries.lock().await.push(registry);
+                    match NmosMdnsRegistry::parse(&discovery) {
+                        Ok(registry) => registries.lock().await.push(registry),
+                        Err(err) => warn!("Ignoring registry {}: {}", discovery.name(), err),
                     }
                 }
             }
@@ -130,13 +329,12 @@ impl Node {
         let app = ServiceBuilder::new()
             .layer(
                 CorsLayer::new()
-                    .allow_methods([Method::GET, Method::POST])
+                    .allow_methods([Method::GET, Method::POST, Method::PATCH])
                     .allow_origin(cors::Any),
             )
             .service(self.service);
 
-        let addr = ([0, 0, 0, 0], 3000).into();
-        let http_server = Server::bind(&addr).serve(Shared::new(app));
+        let http_server = Server::bind(&self.bind_addr).serve(Shared::new(app));
 
         // Registry connection thread
         let registration = async {
@@ -157,48 +355,45 @@ impl Node {
                 };
 
                 // Attempt to register
-                match RegistrationApi::register_resources(&client, self.model.clone(), &registry)
-                    .await
+                let (api, registered) = match RegistrationApi::register_resources(
+                    &client,
+                    self.model.clone(),
+                    &registry,
+                )
+                .await
                 {
-                    Ok(_) => info!("Registration successful"),
+                    Ok(registration) => {
+                        info!("Registration successful");
+                        registration
+                    }
                     Err(err) => {
                         error!("Failed to register with registry: {}", err);
                         continue;
                     }
-                }
-
-                // Get heartbeat endpoint from node id
-                let heartbeat_url = {
-                    let nodes = &self.model.read().await.nodes;
-                    let node_id = *nodes.iter().next().unwrap().0;
-
-                    let base = &registry.url.join("v1.0/").unwrap();
-                    base.join(&format!("health/nodes/{}", node_id)).unwrap()
                 };
 
-                // Send heartbeat every 5 seconds
-                loop {
-                    match client.post(heartbeat_url.clone()).send().await {
-                        Ok(res) => {
-                            if !res.status().is_success() {
-                                error!("Heartbeat error");
-                                break;
-                            }
-                        }
-                        Err(err) => {
-                            error!("Failed to send heartbeat: {}", err);
-                            break;
-                        }
-                    }
-                    tokio::time::sleep(Duration::from_secs(5)).await;
-                }
+                // Heartbeat and register changes until the registry is lost
+                RegistrationApi::maintain(
+                    &client,
+                    self.model.clone(),
+                    &registry,
+                    &api,
+                    registered,
+                    &self.model_changed,
+                )
+                .await;
             }
         };
 
+        // Re-establish restored connections, then record the state
+        self.scheduler.resume().await;
+        self.persistence.changed(&self.model).await;
+
         tokio::select! {
             _ = mdns_receiver => {}
             _ = http_server => {}
             _ = registration => {}
+            _ = self.scheduler.run() => {}
         };
 
         Ok(())
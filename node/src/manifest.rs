@@ -0,0 +1,320 @@
+//! Sender manifests served under `/x-manifest`.
+//!
+//! SDP is generated for RTP senders whenever they are activated. The
+//! application may instead provide a manifest for a sender, which is served
+//! as is. A sender's `manifest_href` points here only while it has a
+//! manifest, unless the application gave it one hosted elsewhere.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use nmos_model::connection::{TransportParams, SDP_MEDIA_TYPE};
+use nmos_model::sdp::{self, SdpError};
+use nmos_model::Model;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Content type, e.g. `application/sdp`
+    pub media_type: String,
+    pub data: String,
+}
+
+impl Manifest {
+    pub fn sdp<S: Into<String>>(data: S) -> Self {
+        Self {
+            media_type: SDP_MEDIA_TYPE.to_owned(),
+            data: data.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Entry {
+    Provided(Manifest),
+    /// SDP along with the active parameters it was generated from
+    Generated(Manifest, Vec<TransportParams>),
+}
+
+/// Path of a sender's manifest below the node's href.
+pub(crate) fn manifest_path(id: &Uuid) -> String {
+    format!("/x-manifest/senders/{}/manifest", id)
+}
+
+#[derive(Debug)]
+pub struct ManifestStore {
+    /// URL the HTTP APIs are reached at, without a trailing slash
+    base_url: String,
+    manifests: RwLock<HashMap<Uuid, Entry>>,
+}
+
+impl ManifestStore {
+    #[must_use]
+    pub(crate) fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            manifests: RwLock::default(),
+        }
+    }
+
+    fn href(&self, sender_id: &Uuid) -> String {
+        format!("{}{}", self.base_url, manifest_path(sender_id))
+    }
+
+    /// Point a sender's `manifest_href` at its manifest once one is
+    /// available, or clear it once none is, bumping the version either way.
+    /// `replace` also overrides an href the application set.
+    fn advertise(&self, model: &mut Model, sender_id: &Uuid, available: bool, replace: bool) {
+        let href = self.href(sender_id);
+        let sender = match model.senders.get_mut(sender_id) {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        if available {
+            if replace || sender.manifest_href.is_empty() || sender.manifest_href == href {
+                sender.manifest_href = href;
+                sender.core.bump_version();
+            }
+        } else if sender.manifest_href == href {
+            sender.manifest_href.clear();
+            sender.core.bump_version();
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, sender_id: &Uuid) -> Option<Manifest> {
+        let manifests = self.manifests.read().expect("Manifest store poisoned");
+        match manifests.get(sender_id)? {
+            Entry::Provided(manifest) | Entry::Generated(manifest, _) => Some(manifest.clone()),
+        }
+    }
+
+    /// Ids of senders with a manifest, sorted.
+    #[must_use]
+    pub fn senders(&self) -> Vec<Uuid> {
+        let manifests = self.manifests.read().expect("Manifest store poisoned");
+        let mut ids: Vec<_> = manifests.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Serve the given manifest for a sender instead of generated SDP,
+    /// pointing its `manifest_href` here and bumping its version. Use with
+    /// [`NodeHandle::update`](crate::NodeHandle::update) once the node is
+    /// started.
+    pub fn set(&self, model: &mut Model, sender_id: Uuid, manifest: Manifest) {
+        self.manifests
+            .write()
+            .expect("Manifest store poisoned")
+            .insert(sender_id, Entry::Provided(manifest));
+
+        self.advertise(model, &sender_id, true, true);
+    }
+
+    /// Remove a manifest provided for a sender, returning to generated SDP.
+    pub fn remove(&self, model: &mut Model, sender_id: &Uuid) {
+        let removed = self
+            .manifests
+            .write()
+            .expect("Manifest store poisoned")
+            .remove(sender_id);
+
+        if removed.is_some() {
+            self.update(model, sender_id);
+        }
+    }
+
+    /// Regenerate SDP for a sender from its active endpoint, unless the
+    /// application provided a manifest. The sender's version is bumped when
+    /// its SDP changes so registries pick it up.
+    pub(crate) fn update(&self, model: &mut Model, sender_id: &Uuid) {
+        let mut manifests = self.manifests.write().expect("Manifest store poisoned");
+
+        let params = match (
+            manifests.get(sender_id),
+            model.sender_connections.get(sender_id),
+        ) {
+            (Some(Entry::Provided(_)), _) | (_, None) => return,
+            (Some(Entry::Generated(_, params)), Some(connection))
+                if *params == connection.active.transport_params =>
+            {
+                return
+            }
+            (_, Some(connection)) => connection.active.transport_params.clone(),
+        };
+
+        let sender = match model.senders.get(sender_id) {
+            Some(sender) => sender,
+            None => return,
+        };
+        let generated = match model.flows.get(&sender.flow_id) {
+            Some(flow) => {
+                sdp::sender_sdp(sender, flow, &model.sender_connections[sender_id].active)
+            }
+            None => Err(SdpError::InvalidParams(format!(
+                "Flow {} not found",
+                sender.flow_id
+            ))),
+        };
+
+        let available = match generated {
+            Ok(data) => {
+                manifests.insert(*sender_id, Entry::Generated(Manifest::sdp(data), params));
+                true
+            }
+            Err(err) => {
+                debug!("No SDP for sender {}: {}", sender_id, err);
+                manifests.remove(sender_id);
+                false
+            }
+        };
+        drop(manifests);
+
+        self.advertise(model, sender_id, available, false);
+    }
+
+    /// Generate SDP for every connection managed sender.
+    pub(crate) fn update_all(&self, model: &mut Model) {
+        let sender_ids: Vec<Uuid> = model.sender_connections.keys().copied().collect();
+        for sender_id in sender_ids {
+            self.update(model, &sender_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nmos_model::connection::{Auto, RtpSenderParams};
+    use nmos_model::resource::{
+        Device, DeviceType, Flow, Format, Node, Rational, ResourceBundle, Sender, Source,
+        Transport, VideoAttributes,
+    };
+
+    use super::*;
+
+    const BASE_URL: &str = "http://127.0.0.1:3000/";
+
+    fn model(manifest_href: Option<&str>) -> (Model, Uuid) {
+        let node = Node::builder("Node", BASE_URL).build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let source = Source::builder("Source", &device, Format::Video).build();
+        let flow = Flow::builder("Flow", &source)
+            .media_type("video/raw")
+            .grain_rate(Rational::new(25, 1))
+            .video(VideoAttributes::new(1920, 1080))
+            .build();
+        let mut sender = Sender::builder("Sender", &device, &flow, Transport::Rtp);
+        if let Some(href) = manifest_href {
+            sender = sender.manifest(href);
+        }
+        let sender = sender.build();
+        let id = sender.core.id;
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_source(source);
+        bundle.insert_flow(flow);
+        bundle.insert_sender(sender);
+
+        (Model::from_resources(bundle), id)
+    }
+
+    /// Activate the sender with every parameter resolved, or left as
+    /// `"auto"`.
+    fn activate(model: &mut Model, id: &Uuid, resolved: bool) {
+        let params = if resolved {
+            RtpSenderParams {
+                source_ip: Auto::Value([10, 0, 0, 1].into()),
+                destination_ip: Auto::Value([239, 0, 0, 1].into()),
+                source_port: Auto::Value(5004),
+                destination_port: Auto::Value(5004),
+                rtp_enabled: true,
+            }
+        } else {
+            RtpSenderParams::default()
+        };
+
+        let connection = model.sender_connections.get_mut(id).unwrap();
+        params.apply(&mut connection.active.transport_params[0]);
+    }
+
+    #[test]
+    fn href_only_while_generated() {
+        let store = ManifestStore::new(BASE_URL);
+        let (mut model, id) = model(None);
+        let href = format!("http://127.0.0.1:3000{}", manifest_path(&id));
+
+        // Nothing to describe until the parameters are resolved
+        store.update_all(&mut model);
+        assert!(store.get(&id).is_none());
+        assert!(model.senders[&id].manifest_href.is_empty());
+
+        activate(&mut model, &id, true);
+        let version = model.senders[&id].core.version;
+        store.update(&mut model, &id);
+        let manifest = store.get(&id).unwrap();
+        assert!(manifest.data.contains("c=IN IP4 239.0.0.1/64"));
+        assert_eq!(model.senders[&id].manifest_href, href);
+        assert!(model.senders[&id].core.version > version);
+
+        activate(&mut model, &id, false);
+        let version = model.senders[&id].core.version;
+        store.update(&mut model, &id);
+        assert!(store.get(&id).is_none());
+        assert!(model.senders[&id].manifest_href.is_empty());
+        assert!(model.senders[&id].core.version > version);
+    }
+
+    #[test]
+    fn external_href_kept() {
+        let store = ManifestStore::new(BASE_URL);
+        let (mut model, id) = model(Some("http://example.com/sender.sdp"));
+
+        activate(&mut model, &id, true);
+        store.update(&mut model, &id);
+        assert!(store.get(&id).is_some());
+        assert_eq!(
+            model.senders[&id].manifest_href,
+            "http://example.com/sender.sdp"
+        );
+
+        activate(&mut model, &id, false);
+        store.update(&mut model, &id);
+        assert_eq!(
+            model.senders[&id].manifest_href,
+            "http://example.com/sender.sdp"
+        );
+    }
+
+    #[test]
+    fn provided_manifest() {
+        let store = ManifestStore::new(BASE_URL);
+        let (mut model, id) = model(Some("http://example.com/sender.sdp"));
+        let href = format!("http://127.0.0.1:3000{}", manifest_path(&id));
+
+        let version = model.senders[&id].core.version;
+        store.set(&mut model, id, Manifest::sdp("v=0\r\n"));
+        assert_eq!(store.get(&id), Some(Manifest::sdp("v=0\r\n")));
+        assert_eq!(model.senders[&id].manifest_href, href);
+        assert!(model.senders[&id].core.version > version);
+
+        // Provided manifests are not replaced by generated SDP
+        activate(&mut model, &id, true);
+        store.update(&mut model, &id);
+        assert_eq!(store.get(&id), Some(Manifest::sdp("v=0\r\n")));
+
+        // Removing one returns to generated SDP, or none
+        store.remove(&mut model, &id);
+        assert!(store.get(&id).unwrap().data.contains("m=video"));
+        assert_eq!(model.senders[&id].manifest_href, href);
+
+        store.set(&mut model, id, Manifest::sdp("v=0\r\n"));
+        activate(&mut model, &id, false);
+        store.remove(&mut model, &id);
+        assert!(store.get(&id).is_none());
+        assert!(model.senders[&id].manifest_href.is_empty());
+    }
+}
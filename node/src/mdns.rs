@@ -1,13 +1,14 @@
 use std::{
     any::Any,
     cmp::Ordering,
+    fmt,
     net::{IpAddr, SocketAddr},
     str::FromStr,
     sync::Arc,
     time::Duration,
 };
 
-use nmos_model::version::APIVersion;
+use nmos_model::version::{is_04::V1_0, APIVersion};
 use reqwest::Url;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tracing::{error, info};
@@ -19,6 +20,34 @@ use zeroconf::{
 
 pub struct NmosMdnsConfig {}
 
+/// Reasons a discovered registry advertisement may be rejected.
+#[derive(Debug)]
+pub enum RegistryParseError {
+    MissingTxtRecord,
+    MissingField(&'static str),
+    InvalidField { field: &'static str, value: String },
+    InvalidAddress(String),
+    InvalidUrl(String),
+}
+
+impl fmt::Display for RegistryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryParseError::MissingTxtRecord => write!(f, "missing TXT record"),
+            RegistryParseError::MissingField(field) => {
+                write!(f, "missing required TXT field \"{}\"", field)
+            }
+            RegistryParseError::InvalidField { field, value } => {
+                write!(f, "invalid value \"{}\" for TXT field \"{}\"", value, field)
+            }
+            RegistryParseError::InvalidAddress(address) => {
+                write!(f, "invalid address \"{}\"", address)
+            }
+            RegistryParseError::InvalidUrl(err) => write!(f, "cannot build URL: {}", err),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct NmosMdnsRegistry {
     pub api_proto: String,
@@ -29,71 +58,113 @@ pub struct NmosMdnsRegistry {
 }
 
 impl NmosMdnsRegistry {
-    pub fn parse(discovery: &ServiceDiscovery) -> Option<Self> {
+    pub fn parse(discovery: &ServiceDiscovery) -> Result<Self, RegistryParseError> {
         // TXT record required
         let txt = match discovery.txt() {
             Some(txt) => txt,
-            None => return None,
+            None => return Err(RegistryParseError::MissingTxtRecord),
         };
 
-        // Get required fields
-        if let (Some(api_proto), Some(api_ver), Some(api_auth), Some(pri)) = (
-            txt.get("api_proto"),
-            txt.get("api_ver"),
-            txt.get("api_auth"),
-            txt.get("pri"),
-        ) {
-            // TODO: Validate fields
-
-            let address = discovery.address();
-            let port = discovery.port();
-
-            // Use std to form valid address port combination
-            let address = match IpAddr::from_str(address) {
-                Ok(addr) => addr,
-                Err(_) => return None,
-            };
-            let socket = SocketAddr::new(address, *port);
-            let authority = socket.to_string();
-
-            // Build URL
-            let base = format!("{}://{}/x-nmos/registration/", api_proto, authority);
-
-            let url = match Url::parse(&base) {
-                Ok(url) => url,
-                Err(err) => {
-                    error!("Cannot build URL: {}", err);
-                    return None;
-                }
-            };
-
-            // Parse api_ver
-            let api_ver: Vec<APIVersion> =
-                api_ver.split(',').flat_map(APIVersion::from_str).collect();
-
-            // Parse api_auth
-            let api_auth = match api_auth.parse::<bool>() {
-                Ok(auth) => auth,
-                Err(_) => return None,
-            };
-
-            // Parse pri
-            let pri = match pri.parse::<u8>() {
-                Ok(pri) => pri,
-                Err(_) => return None,
-            };
-
-            Some(Self {
-                api_proto,
-                api_ver,
-                api_auth,
-                pri,
-                url,
-            })
-        } else {
-            None
+        Self::from_txt(|key| txt.get(key), discovery.address(), *discovery.port())
+    }
+
+    /// Parse an advertisement from its TXT record fields, looked up by key,
+    /// and the address and port it was discovered at.
+    fn from_txt<F>(txt: F, address: &str, port: u16) -> Result<Self, RegistryParseError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        // Priority is the only field required by every version of the spec
+        let pri = match txt("pri") {
+            Some(pri) => pri,
+            None => return Err(RegistryParseError::MissingField("pri")),
+        };
+        let pri = match pri.parse::<u8>() {
+            Ok(pri) => pri,
+            Err(_) => {
+                return Err(RegistryParseError::InvalidField {
+                    field: "pri",
+                    value: pri,
+                })
+            }
+        };
+
+        // Registries predating IS-04 v1.1 omit api_proto, implying HTTP
+        let api_proto = txt("api_proto").unwrap_or_else(|| String::from("http"));
+        if api_proto != "http" && api_proto != "https" {
+            return Err(RegistryParseError::InvalidField {
+                field: "api_proto",
+                value: api_proto,
+            });
         }
+
+        // Registries predating IS-04 v1.1 omit api_ver, implying v1.0 only
+        let api_ver = match txt("api_ver") {
+            Some(api_ver) => match parse_api_ver(&api_ver) {
+                Some(versions) => versions,
+                None => {
+                    return Err(RegistryParseError::InvalidField {
+                        field: "api_ver",
+                        value: api_ver,
+                    })
+                }
+            },
+            None => vec![V1_0],
+        };
+
+        // Registries predating IS-04 v1.3 omit api_auth, implying no authorization
+        let api_auth = match txt("api_auth") {
+            Some(api_auth) => match api_auth.as_str() {
+                "true" => true,
+                "false" => false,
+                _ => {
+                    return Err(RegistryParseError::InvalidField {
+                        field: "api_auth",
+                        value: api_auth,
+                    })
+                }
+            },
+            None => false,
+        };
+
+        // Use std to form valid address port combination
+        let address = match IpAddr::from_str(address) {
+            Ok(addr) => addr,
+            Err(_) => return Err(RegistryParseError::InvalidAddress(address.to_owned())),
+        };
+        let socket = SocketAddr::new(address, port);
+        let authority = socket.to_string();
+
+        // Build URL
+        let base = format!("{}://{}/x-nmos/registration/", api_proto, authority);
+        let url =
+            Url::parse(&base).map_err(|err| RegistryParseError::InvalidUrl(err.to_string()))?;
+
+        Ok(Self {
+            api_proto,
+            api_ver,
+            api_auth,
+            pri,
+            url,
+        })
     }
+
+    /// Base URL of the registration API at the given version.
+    #[must_use]
+    pub fn api_url(&self, api: &APIVersion) -> Url {
+        self.url
+            .join(&format!("{}/", api))
+            .expect("API version is a valid path segment")
+    }
+}
+
+/// Parse a comma separated `api_ver` list, rejecting the whole list if any
+/// entry is malformed.
+fn parse_api_ver(api_ver: &str) -> Option<Vec<APIVersion>> {
+    api_ver
+        .split(',')
+        .map(|version| APIVersion::from_str(version.trim()).ok())
+        .collect()
 }
 
 impl Ord for NmosMdnsRegistry {
@@ -234,3 +305,121 @@ impl MdnsPoller<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use nmos_model::version::is_04::{V1_2, V1_3};
+
+    use super::*;
+
+    fn parse(fields: &[(&str, &str)]) -> Result<NmosMdnsRegistry, RegistryParseError> {
+        let txt: HashMap<_, _> = fields.iter().copied().collect();
+        NmosMdnsRegistry::from_txt(
+            |key| txt.get(key).map(|value| value.to_string()),
+            "192.0.2.10",
+            8080,
+        )
+    }
+
+    fn invalid_field(result: Result<NmosMdnsRegistry, RegistryParseError>) -> &'static str {
+        match result {
+            Err(RegistryParseError::InvalidField { field, .. }) => field,
+            other => panic!("expected invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn full_record() {
+        let registry = parse(&[
+            ("pri", "10"),
+            ("api_proto", "https"),
+            ("api_ver", "v1.2, v1.3"),
+            ("api_auth", "true"),
+        ])
+        .unwrap();
+
+        assert_eq!(registry.pri, 10);
+        assert_eq!(registry.api_proto, "https");
+        assert_eq!(registry.api_ver, vec![V1_2, V1_3]);
+        assert!(registry.api_auth);
+        assert_eq!(
+            registry.api_url(&V1_3).as_str(),
+            "https://192.0.2.10:8080/x-nmos/registration/v1.3/"
+        );
+    }
+
+    #[test]
+    fn missing_optional_fields_use_v1_0_defaults() {
+        let registry = parse(&[("pri", "100")]).unwrap();
+
+        assert_eq!(registry.api_proto, "http");
+        assert_eq!(registry.api_ver, vec![V1_0]);
+        assert!(!registry.api_auth);
+        assert_eq!(
+            registry.api_url(&V1_0).as_str(),
+            "http://192.0.2.10:8080/x-nmos/registration/v1.0/"
+        );
+    }
+
+    #[test]
+    fn missing_priority() {
+        assert!(matches!(
+            parse(&[("api_ver", "v1.1")]),
+            Err(RegistryParseError::MissingField("pri"))
+        ));
+    }
+
+    #[test]
+    fn invalid_fields() {
+        assert_eq!(invalid_field(parse(&[("pri", "256")])), "pri");
+        assert_eq!(
+            invalid_field(parse(&[("pri", "1"), ("api_proto", "ftp")])),
+            "api_proto"
+        );
+        assert_eq!(
+            invalid_field(parse(&[("pri", "1"), ("api_ver", "v1.1,1.2")])),
+            "api_ver"
+        );
+        assert_eq!(
+            invalid_field(parse(&[("pri", "1"), ("api_ver", "")])),
+            "api_ver"
+        );
+        assert_eq!(
+            invalid_field(parse(&[("pri", "1"), ("api_auth", "yes")])),
+            "api_auth"
+        );
+    }
+
+    #[test]
+    fn invalid_address() {
+        let result = NmosMdnsRegistry::from_txt(
+            |key| (key == "pri").then(|| String::from("1")),
+            "registry.local",
+            80,
+        );
+        assert!(matches!(result, Err(RegistryParseError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn ipv6_address() {
+        let registry = NmosMdnsRegistry::from_txt(
+            |key| (key == "pri").then(|| String::from("1")),
+            "2001:db8::1",
+            80,
+        )
+        .unwrap();
+        assert_eq!(
+            registry.url.as_str(),
+            "http://[2001:db8::1]/x-nmos/registration/"
+        );
+    }
+
+    #[test]
+    fn orders_by_lowest_priority() {
+        let high = parse(&[("pri", "10"), ("api_ver", "v1.1")]).unwrap();
+        let low = parse(&[("pri", "100")]).unwrap();
+        assert!(high > low);
+    }
+}
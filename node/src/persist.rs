@@ -0,0 +1,68 @@
+use std::{io, path::PathBuf};
+
+use nmos_model::{persist::Snapshot, Model};
+use tokio::sync::{Mutex, RwLock};
+use tracing::error;
+
+/// Keeps a snapshot file in step with the model. Does nothing if no file was
+/// configured.
+pub struct Persistence {
+    path: Option<PathBuf>,
+    /// Last snapshot written, locked for the whole of a save so writes happen
+    /// in the order the model changed
+    last: Mutex<Option<Snapshot>>,
+}
+
+impl Persistence {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Apply the saved snapshot, if any, to a newly built model.
+    pub fn restore(&mut self, model: &mut Model) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(snapshot) = Snapshot::load(path)? {
+            model.restore(&snapshot);
+            *self.last.get_mut() = Some(snapshot);
+        }
+        Ok(())
+    }
+
+    /// Write the model to disk if it differs from the last snapshot written.
+    /// The snapshot is taken under the model's read lock, which is released
+    /// before the file is written.
+    pub async fn save(&self, model: &RwLock<Model>) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+
+        let mut last = self.last.lock().await;
+        let snapshot = model.read().await.snapshot(last.as_ref());
+        if last.as_ref() == Some(&snapshot) {
+            return Ok(());
+        }
+
+        let stored = snapshot.clone();
+        tokio::task::spawn_blocking(move || stored.store(path))
+            .await
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))??;
+        *last = Some(snapshot);
+
+        Ok(())
+    }
+
+    /// Save after a change, logging any failure.
+    pub async fn changed(&self, model: &RwLock<Model>) {
+        if let Err(err) = self.save(model).await {
+            error!("Failed to persist model state: {}", err);
+        }
+    }
+}
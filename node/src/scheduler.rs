@@ -0,0 +1,723 @@
+//! Applies IS-05 activations, either immediately or once the time of a
+//! scheduled activation is reached.
+
+use std::sync::Arc;
+
+use nmos_model::connection::{
+    Activation, ActivationMode, ReceiverEndpoint, RtpResolver, SenderEndpoint, TransportParams,
+};
+use nmos_model::resource::Transport;
+use nmos_model::tai::TaiTime;
+use nmos_model::Model;
+use serde_json::Value;
+use tokio::sync::{Notify, RwLock};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::connection_handler::ConnectionHandler;
+use crate::event_handler::{ActivationError, EventHandler};
+use crate::manifest::ManifestStore;
+use crate::persist::Persistence;
+
+/// Reject parameters from a connection handler with the wrong number of legs.
+fn check_legs(
+    params: Vec<TransportParams>,
+    legs: usize,
+) -> Result<Vec<TransportParams>, ActivationError> {
+    if params.len() == legs {
+        Ok(params)
+    } else {
+        Err(ActivationError(format!(
+            "Connection handler returned {} legs, expected {}",
+            params.len(),
+            legs
+        )))
+    }
+}
+
+/// Reject RTP parameters of enabled legs left as `"auto"` by both the
+/// resolver and the connection handler, which would otherwise be reported as
+/// active.
+fn check_resolved(
+    transport: &Transport,
+    params: &[TransportParams],
+) -> Result<(), ActivationError> {
+    if !matches!(
+        transport,
+        Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast
+    ) {
+        return Ok(());
+    }
+
+    for (leg, params) in params.iter().enumerate() {
+        // Disabled legs are never sent or received, so need no addresses
+        if params.get("rtp_enabled").and_then(Value::as_bool) == Some(false) {
+            continue;
+        }
+
+        if let Some((param, _)) = params.iter().find(|(_, value)| *value == "auto") {
+            return Err(ActivationError(format!(
+                "No value chosen for {} on leg {}",
+                param, leg
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Mode and requested time of activating a staged endpoint, immediate unless
+/// a scheduled activation is pending.
+fn requested(staged: &Activation) -> (ActivationMode, Option<TaiTime>) {
+    match staged.mode {
+        Some(mode) if staged.pending().is_some() => (mode, staged.requested_time),
+        _ => (ActivationMode::Immediate, None),
+    }
+}
+
+fn cancelled() -> ActivationError {
+    ActivationError(String::from("Activation was cancelled or replaced"))
+}
+
+pub struct Scheduler {
+    model: Arc<RwLock<Model>>,
+    event_handler: Option<Arc<dyn EventHandler>>,
+    connection_handler: Option<Arc<dyn ConnectionHandler>>,
+    resolver: Arc<dyn RtpResolver>,
+    manifests: Arc<ManifestStore>,
+    persistence: Arc<Persistence>,
+    notify: Notify,
+    /// Signalled after each activation, so registries are told of changes
+    model_changed: Arc<Notify>,
+}
+
+impl Scheduler {
+    pub fn new(
+        model: Arc<RwLock<Model>>,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        connection_handler: Option<Arc<dyn ConnectionHandler>>,
+        resolver: Arc<dyn RtpResolver>,
+        manifests: Arc<ManifestStore>,
+        persistence: Arc<Persistence>,
+        model_changed: Arc<Notify>,
+    ) -> Self {
+        Self {
+            model,
+            event_handler,
+            connection_handler,
+            resolver,
+            manifests,
+            persistence,
+            notify: Notify::new(),
+            model_changed,
+        }
+    }
+
+    /// Wake the scheduler after activations are scheduled or cancelled.
+    pub fn reschedule(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Cancel a failed scheduled activation, unless it has been replaced.
+    /// Failed immediate activations are left for the caller to undo.
+    async fn cancel_sender(&self, id: Uuid, generation: u64, mode: ActivationMode) {
+        if mode == ActivationMode::Immediate {
+            return;
+        }
+        if let Some(connection) = self.model.write().await.sender_connections.get_mut(&id) {
+            if connection.generation == generation {
+                connection.cancel();
+            }
+        }
+    }
+
+    /// Cancel a failed scheduled activation, unless it has been replaced.
+    /// Failed immediate activations are left for the caller to undo.
+    async fn cancel_receiver(&self, id: Uuid, generation: u64, mode: ActivationMode) {
+        if mode == ActivationMode::Immediate {
+            return;
+        }
+        if let Some(connection) = self.model.write().await.receiver_connections.get_mut(&id) {
+            if connection.generation == generation {
+                connection.cancel();
+            }
+        }
+    }
+
+    /// Apply a sender's staged endpoint as of `generation`, giving the
+    /// application a chance to veto it or adjust its parameters. Fails if the
+    /// endpoint is staged again in the meantime. A vetoed or unresolved
+    /// scheduled activation is cancelled, while a failed immediate activation
+    /// leaves the model untouched. The sender's SDP is regenerated from the
+    /// new active parameters.
+    pub async fn activate_sender(
+        &self,
+        id: Uuid,
+        generation: u64,
+    ) -> Result<Activation, ActivationError> {
+        let (transport, mode, requested_time, mut endpoint) =
+            match self.model.read().await.sender_connections.get(&id) {
+                // Staged again, cancelled or rescheduled before the model was read
+                Some(connection) if connection.generation != generation => return Err(cancelled()),
+                Some(connection) => {
+                    let (mode, requested_time) = requested(&connection.staged.activation);
+                    (
+                        connection.transport.clone(),
+                        mode,
+                        requested_time,
+                        SenderEndpoint {
+                            transport_params: connection.resolved(&id, self.resolver.as_ref()),
+                            ..connection.staged.clone()
+                        },
+                    )
+                }
+                None => return Err(ActivationError(format!("Sender {} does not exist", id))),
+            };
+
+        if let Some(connection_handler) = &self.connection_handler {
+            let legs = endpoint.transport_params.len();
+            let params = connection_handler
+                .activate_sender(id, &endpoint)
+                .await
+                .and_then(|params| check_legs(params, legs));
+            match params {
+                Ok(params) => endpoint.transport_params = params,
+                Err(err) => {
+                    self.cancel_sender(id, generation, mode).await;
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Err(err) = check_resolved(&transport, &endpoint.transport_params) {
+            self.cancel_sender(id, generation, mode).await;
+            return Err(err);
+        }
+
+        if let Some(event_handler) = &self.event_handler {
+            if let Err(err) = event_handler.activate_sender(id, &endpoint).await {
+                self.cancel_sender(id, generation, mode).await;
+                return Err(err);
+            }
+        }
+
+        let mut model = self.model.write().await;
+
+        // Staged again, cancelled or rescheduled while the handlers were consulted
+        match model.sender_connections.get(&id) {
+            Some(connection) if connection.generation != generation => return Err(cancelled()),
+            _ => {}
+        }
+
+        let activation = model
+            .activate_sender(
+                &id,
+                mode,
+                requested_time,
+                TaiTime::now(),
+                endpoint.transport_params,
+            )
+            .ok_or_else(|| ActivationError(format!("Sender {} does not exist", id)))?;
+        self.manifests.update(&mut model, &id);
+        drop(model);
+
+        self.persistence.changed(&self.model).await;
+        self.model_changed.notify_one();
+        Ok(activation)
+    }
+
+    /// Apply a receiver's staged endpoint as of `generation`, giving the
+    /// application a chance to veto it or adjust its parameters. Fails if the
+    /// endpoint is staged again in the meantime. A vetoed or unresolved
+    /// scheduled activation is cancelled, while a failed immediate activation
+    /// leaves the model untouched.
+    pub async fn activate_receiver(
+        &self,
+        id: Uuid,
+        generation: u64,
+    ) -> Result<Activation, ActivationError> {
+        let (transport, mode, requested_time, mut endpoint) =
+            match self.model.read().await.receiver_connections.get(&id) {
+                // Staged again, cancelled or rescheduled before the model was read
+                Some(connection) if connection.generation != generation => return Err(cancelled()),
+                Some(connection) => {
+                    let (mode, requested_time) = requested(&connection.staged.activation);
+                    (
+                        connection.transport.clone(),
+                        mode,
+                        requested_time,
+                        ReceiverEndpoint {
+                            transport_params: connection.resolved(&id, self.resolver.as_ref()),
+                            ..connection.staged.clone()
+                        },
+                    )
+                }
+                None => return Err(ActivationError(format!("Receiver {} does not exist", id))),
+            };
+
+        if let Some(connection_handler) = &self.connection_handler {
+            let legs = endpoint.transport_params.len();
+            let params = connection_handler
+                .activate_receiver(id, &endpoint)
+                .await
+                .and_then(|params| check_legs(params, legs));
+            match params {
+                Ok(params) => endpoint.transport_params = params,
+                Err(err) => {
+                    self.cancel_receiver(id, generation, mode).await;
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Err(err) = check_resolved(&transport, &endpoint.transport_params) {
+            self.cancel_receiver(id, generation, mode).await;
+            return Err(err);
+        }
+
+        if let Some(event_handler) = &self.event_handler {
+            if let Err(err) = event_handler.activate_receiver(id, &endpoint).await {
+                self.cancel_receiver(id, generation, mode).await;
+                return Err(err);
+            }
+        }
+
+        let mut model = self.model.write().await;
+
+        // Staged again, cancelled or rescheduled while the handlers were consulted
+        match model.receiver_connections.get(&id) {
+            Some(connection) if connection.generation != generation => return Err(cancelled()),
+            _ => {}
+        }
+
+        let activation = model
+            .activate_receiver(
+                &id,
+                mode,
+                requested_time,
+                TaiTime::now(),
+                endpoint.transport_params,
+            )
+            .ok_or_else(|| ActivationError(format!("Receiver {} does not exist", id)))?;
+        drop(model);
+
+        self.persistence.changed(&self.model).await;
+        self.model_changed.notify_one();
+        Ok(activation)
+    }
+
+    /// Activate endpoints staged by restoring saved state, so connections
+    /// which were active before a restart are re-established through the
+    /// handlers.
+    pub async fn resume(&self) {
+        let (senders, receivers) = {
+            let model = self.model.read().await;
+
+            let senders: Vec<_> = model
+                .sender_connections
+                .iter()
+                .filter(|(_, c)| c.staged.master_enable && !c.active.master_enable)
+                .map(|(id, c)| (*id, c.generation))
+                .collect();
+            let receivers: Vec<_> = model
+                .receiver_connections
+                .iter()
+                .filter(|(_, c)| c.staged.master_enable && !c.active.master_enable)
+                .map(|(id, c)| (*id, c.generation))
+                .collect();
+
+            (senders, receivers)
+        };
+
+        for (id, generation) in senders {
+            match self.activate_sender(id, generation).await {
+                Ok(_) => info!("Resumed sender {}", id),
+                Err(err) => error!("Failed to resume sender {}: {}", id, err),
+            }
+        }
+
+        for (id, generation) in receivers {
+            match self.activate_receiver(id, generation).await {
+                Ok(_) => info!("Resumed receiver {}", id),
+                Err(err) => error!("Failed to resume receiver {}: {}", id, err),
+            }
+        }
+    }
+
+    /// Earliest time a pending activation is due.
+    async fn next_activation(&self) -> Option<TaiTime> {
+        let model = self.model.read().await;
+
+        let senders = model
+            .sender_connections
+            .values()
+            .filter_map(|c| c.staged.activation.pending());
+        let receivers = model
+            .receiver_connections
+            .values()
+            .filter_map(|c| c.staged.activation.pending());
+
+        senders.chain(receivers).min()
+    }
+
+    async fn activate_due(&self) {
+        let now = TaiTime::now();
+        let due = |activation: &Activation| activation.pending().map_or(false, |t| t <= now);
+
+        let (senders, receivers) = {
+            let model = self.model.read().await;
+
+            let senders: Vec<_> = model
+                .sender_connections
+                .iter()
+                .filter(|(_, c)| due(&c.staged.activation))
+                .map(|(id, c)| (*id, c.generation))
+                .collect();
+            let receivers: Vec<_> = model
+                .receiver_connections
+                .iter()
+                .filter(|(_, c)| due(&c.staged.activation))
+                .map(|(id, c)| (*id, c.generation))
+                .collect();
+
+            (senders, receivers)
+        };
+
+        for (id, generation) in senders {
+            match self.activate_sender(id, generation).await {
+                Ok(_) => info!("Activated sender {}", id),
+                Err(err) => error!("Failed to activate sender {}: {}", id, err),
+            }
+        }
+
+        for (id, generation) in receivers {
+            match self.activate_receiver(id, generation).await {
+                Ok(_) => info!("Activated receiver {}", id),
+                Err(err) => error!("Failed to activate receiver {}: {}", id, err),
+            }
+        }
+    }
+
+    /// Apply scheduled activations as they fall due.
+    pub async fn run(&self) {
+        loop {
+            match self.next_activation().await {
+                Some(time) => {
+                    let delay = time.duration_since(TaiTime::now()).unwrap_or_default();
+                    if tokio::time::timeout(delay, self.notify.notified())
+                        .await
+                        .is_ok()
+                    {
+                        continue;
+                    }
+                }
+                None => {
+                    self.notify.notified().await;
+                    continue;
+                }
+            }
+
+            self.activate_due().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use async_trait::async_trait;
+    use nmos_model::connection::{ReceiverPatch, RtpPool, TransportParams};
+    use nmos_model::resource::{Device, DeviceType, Format, Node, Receiver, ResourceBundle};
+    use serde_json::json;
+
+    use super::*;
+
+    enum Handler {
+        Reject,
+        /// Stage a patch while consulted, as a concurrent request would
+        Restage(Arc<RwLock<Model>>),
+    }
+
+    #[async_trait]
+    impl ConnectionHandler for Handler {
+        async fn activate_receiver(
+            &self,
+            receiver_id: Uuid,
+            endpoint: &ReceiverEndpoint,
+        ) -> Result<Vec<TransportParams>, ActivationError> {
+            match self {
+                Handler::Reject => Err(ActivationError(String::from("Rejected"))),
+                Handler::Restage(model) => {
+                    stage(model, receiver_id, json!({"master_enable": false})).await;
+                    Ok(endpoint.transport_params.clone())
+                }
+            }
+        }
+    }
+
+    /// Records the endpoints the event hooks are called with.
+    #[derive(Default)]
+    struct Events(std::sync::Mutex<Vec<ReceiverEndpoint>>);
+
+    #[async_trait]
+    impl EventHandler for Events {
+        async fn activate_receiver(
+            &self,
+            _receiver_id: Uuid,
+            endpoint: &ReceiverEndpoint,
+        ) -> Result<(), ActivationError> {
+            self.0.lock().unwrap().push(endpoint.clone());
+            Ok(())
+        }
+    }
+
+    /// Model with a single RTP receiver.
+    fn model() -> (Arc<RwLock<Model>>, Uuid) {
+        let node = Node::builder("Node", "http://127.0.0.1:3000/").build();
+        let device = Device::builder("Device", &node, DeviceType::Generic).build();
+        let receiver =
+            Receiver::builder("Receiver", &device, Format::Video, Transport::Rtp).build();
+        let id = receiver.core.id;
+
+        let mut bundle = ResourceBundle::new();
+        bundle.insert_node(node);
+        bundle.insert_device(device);
+        bundle.insert_receiver(receiver);
+
+        (Arc::new(RwLock::new(Model::from_resources(bundle))), id)
+    }
+
+    fn scheduler(model: &Arc<RwLock<Model>>, handler: Option<Handler>) -> Scheduler {
+        scheduler_with_events(model, handler, None)
+    }
+
+    fn scheduler_with_events(
+        model: &Arc<RwLock<Model>>,
+        handler: Option<Handler>,
+        events: Option<Arc<Events>>,
+    ) -> Scheduler {
+        let handler = handler.map(|h| Arc::new(h) as Arc<dyn ConnectionHandler>);
+        let events = events.map(|e| e as Arc<dyn EventHandler>);
+        let resolver = RtpPool::new(vec![Ipv4Addr::LOCALHOST.into()])
+            .multicast_range(Ipv4Addr::new(239, 0, 0, 1), 4);
+
+        Scheduler::new(
+            model.clone(),
+            events,
+            handler,
+            Arc::new(resolver),
+            Arc::new(ManifestStore::new("http://127.0.0.1:3000/")),
+            Arc::new(Persistence::new(None)),
+            Arc::new(Notify::new()),
+        )
+    }
+
+    /// Stage a patch, returning the generation it was staged as.
+    async fn stage(model: &RwLock<Model>, id: Uuid, patch: Value) -> u64 {
+        let patch: ReceiverPatch = serde_json::from_value(patch).unwrap();
+        let mut model = model.write().await;
+        let connection = model.receiver_connections.get_mut(&id).unwrap();
+        connection.stage(patch).unwrap();
+        connection.generation
+    }
+
+    /// Schedule activation of the staged endpoint, already due.
+    async fn schedule_due(model: &RwLock<Model>, id: Uuid) {
+        let now = TaiTime::now();
+        let mut model = model.write().await;
+        let connection = model.receiver_connections.get_mut(&id).unwrap();
+        connection
+            .schedule(ActivationMode::ScheduledAbsolute, now, now)
+            .unwrap();
+    }
+
+    async fn connection(
+        model: &RwLock<Model>,
+        id: Uuid,
+    ) -> nmos_model::connection::ReceiverConnection {
+        model.read().await.receiver_connections[&id].clone()
+    }
+
+    fn params(value: Value) -> TransportParams {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn legs_checked() {
+        let legs = vec![TransportParams::new(); 2];
+        assert_eq!(check_legs(legs.clone(), 2), Ok(legs.clone()));
+        assert!(check_legs(legs, 1).is_err());
+    }
+
+    #[test]
+    fn resolved_checked() {
+        let resolved = params(json!({"interface_ip": "127.0.0.1", "rtp_enabled": true}));
+        let auto = params(json!({"interface_ip": "auto", "rtp_enabled": true}));
+        let disabled = params(json!({"interface_ip": "auto", "rtp_enabled": false}));
+
+        assert_eq!(
+            check_resolved(&Transport::Rtp, std::slice::from_ref(&resolved)),
+            Ok(())
+        );
+        assert_eq!(
+            check_resolved(&Transport::RtpMulticast, &[resolved.clone(), auto.clone()]),
+            Err(ActivationError(String::from(
+                "No value chosen for interface_ip on leg 1"
+            )))
+        );
+
+        // An ST 2022-7 sender with its second leg disabled
+        assert_eq!(
+            check_resolved(&Transport::Rtp, &[resolved, disabled]),
+            Ok(())
+        );
+
+        // Other transports may keep "auto" in their active parameters
+        assert_eq!(check_resolved(&Transport::Websocket, &[auto]), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn immediate_activation() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, None);
+        let sender_id = Uuid::new_v4();
+
+        let generation = stage(
+            &model,
+            id,
+            json!({"sender_id": sender_id, "master_enable": true}),
+        )
+        .await;
+        let activation = scheduler.activate_receiver(id, generation).await.unwrap();
+        assert_eq!(activation.mode, Some(ActivationMode::Immediate));
+
+        let connection = connection(&model, id).await;
+        assert!(connection.active.master_enable);
+        assert_eq!(
+            connection.active.transport_params[0]["interface_ip"],
+            "127.0.0.1"
+        );
+        assert_eq!(connection.staged.activation, Activation::default());
+        assert_eq!(
+            model.read().await.receivers[&id].subscription,
+            Some(sender_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn superseded_immediate_activation() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, None);
+
+        let generation = stage(&model, id, json!({"master_enable": true})).await;
+        stage(&model, id, json!({"master_enable": false})).await;
+
+        assert_eq!(
+            scheduler.activate_receiver(id, generation).await,
+            Err(cancelled())
+        );
+        assert!(!connection(&model, id).await.active.master_enable);
+    }
+
+    #[tokio::test]
+    async fn restaged_while_consulted() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, Some(Handler::Restage(model.clone())));
+
+        let generation = stage(&model, id, json!({"master_enable": true})).await;
+        assert_eq!(
+            scheduler.activate_receiver(id, generation).await,
+            Err(cancelled())
+        );
+
+        let connection = connection(&model, id).await;
+        assert!(!connection.active.master_enable);
+        assert_eq!(connection.active.activation, Activation::default());
+    }
+
+    #[tokio::test]
+    async fn rejected_immediate_activation_is_left_to_caller() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, Some(Handler::Reject));
+
+        let generation = stage(&model, id, json!({"master_enable": true})).await;
+        assert!(scheduler.activate_receiver(id, generation).await.is_err());
+
+        // Still staged as patched, for the caller to revert
+        let connection = connection(&model, id).await;
+        assert_eq!(connection.generation, generation);
+        assert!(connection.staged.master_enable);
+        assert!(!connection.active.master_enable);
+    }
+
+    #[tokio::test]
+    async fn event_hooks_follow_connection_handler() {
+        let (model, id) = model();
+        let events = Arc::new(Events::default());
+
+        // Not told of activations the connection handler rejects
+        let scheduler = scheduler_with_events(&model, Some(Handler::Reject), Some(events.clone()));
+        let generation = stage(&model, id, json!({"master_enable": true})).await;
+        assert!(scheduler.activate_receiver(id, generation).await.is_err());
+        assert!(events.0.lock().unwrap().is_empty());
+
+        // Told of accepted ones with their resolved parameters
+        let scheduler = scheduler_with_events(&model, None, Some(events.clone()));
+        assert!(scheduler.activate_receiver(id, generation).await.is_ok());
+        let endpoints = events.0.lock().unwrap().clone();
+        assert_eq!(endpoints.len(), 1);
+        assert!(endpoints[0].master_enable);
+        assert_eq!(
+            endpoints[0].transport_params[0]["interface_ip"],
+            "127.0.0.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn due_activation() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, None);
+
+        stage(&model, id, json!({"master_enable": true})).await;
+        schedule_due(&model, id).await;
+        assert!(scheduler.next_activation().await.is_some());
+        scheduler.activate_due().await;
+
+        let connection = connection(&model, id).await;
+        assert!(connection.active.master_enable);
+        assert_eq!(
+            connection.active.activation.mode,
+            Some(ActivationMode::ScheduledAbsolute)
+        );
+        assert_eq!(connection.staged.activation, Activation::default());
+        assert!(scheduler.next_activation().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejected_due_activation_is_cancelled() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, Some(Handler::Reject));
+
+        stage(&model, id, json!({"master_enable": true})).await;
+        schedule_due(&model, id).await;
+        scheduler.activate_due().await;
+
+        let connection = connection(&model, id).await;
+        assert!(!connection.active.master_enable);
+        assert!(connection.staged.master_enable);
+        assert_eq!(connection.staged.activation, Activation::default());
+        assert!(scheduler.next_activation().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn resume_restored_connection() {
+        let (model, id) = model();
+        let scheduler = scheduler(&model, None);
+
+        stage(&model, id, json!({"master_enable": true})).await;
+        scheduler.resume().await;
+
+        assert!(connection(&model, id).await.active.master_enable);
+    }
+}